@@ -0,0 +1,13 @@
+-- Ledger of every credit transfer, written in the same transaction as the balance update
+
+CREATE TABLE IF NOT EXISTS transactions(
+    id BIGSERIAL PRIMARY KEY,
+    from_user_id TEXT NOT NULL,
+    to_user_id TEXT NOT NULL,
+    amount BIGINT NOT NULL CHECK(amount > 0),
+    memo TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS transactions_from_user_id_idx ON transactions(from_user_id, id);
+CREATE INDEX IF NOT EXISTS transactions_to_user_id_idx ON transactions(to_user_id, id);
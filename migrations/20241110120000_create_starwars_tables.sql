@@ -0,0 +1,49 @@
+-- Star Wars data that used to only live in the slabs of `StarWarsAPI`
+-- every table has an integer `key` that plays the role of the slab index,
+-- `id` is the id the GraphQL api hands out
+
+CREATE TYPE episode AS ENUM ('NEW_HOPE', 'EMPIRE', 'JEDI');
+
+CREATE TABLE planets(
+    key SERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    climate TEXT NOT NULL,
+    diameter BIGINT NOT NULL CHECK(diameter >= 0),
+    gravity TEXT NOT NULL,
+    population BIGINT NOT NULL CHECK(population >= 0),
+    rotation_period BIGINT NOT NULL CHECK(rotation_period >= 0),
+    orbital_period BIGINT NOT NULL CHECK(orbital_period >= 0)
+);
+
+CREATE TABLE starships(
+    key SERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    length DOUBLE PRECISION NOT NULL CHECK(length >= 0)
+);
+
+CREATE TABLE characters(
+    key SERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    is_human BOOLEAN NOT NULL,
+    name TEXT NOT NULL,
+    home_planet INTEGER REFERENCES planets(key) ON DELETE SET NULL,
+    star_ship INTEGER REFERENCES starships(key) ON DELETE SET NULL,
+    primary_function TEXT,
+    mass BIGINT NOT NULL CHECK(mass >= 0)
+);
+
+-- `position` keeps the friends in the order they were given
+CREATE TABLE friendships(
+    character INTEGER NOT NULL REFERENCES characters(key) ON DELETE CASCADE,
+    friend INTEGER NOT NULL REFERENCES characters(key) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    PRIMARY KEY(character, friend)
+);
+
+CREATE TABLE character_episodes(
+    character INTEGER NOT NULL REFERENCES characters(key) ON DELETE CASCADE,
+    episode episode NOT NULL,
+    PRIMARY KEY(character, episode)
+);
@@ -0,0 +1,48 @@
+-- Same universe as `StarWarsAPI::new()`
+
+INSERT INTO planets(key, id, name, climate, diameter, gravity, population, rotation_period, orbital_period)
+VALUES
+    (1, '1', 'Tatooine', 'arid', 10465, 'Standard', 200000, 23, 304),
+    (2, '2', 'Alderaan', 'arid', 10465, 'Temperate', 2000000000, 24, 364)
+;
+
+INSERT INTO starships(key, id, name, length)
+VALUES
+    (1, '1', 'X-Wing', 12.49),
+    (2, '2', 'Tantive IV', 126),
+    (3, '3', 'Tie Figter', 9.2),
+    (4, '4', 'Death Star', 12.49),
+    (5, '5', 'Millenium Falcon', 34.75)
+;
+
+INSERT INTO characters(key, id, is_human, name, home_planet, star_ship, primary_function, mass)
+VALUES
+    (1, '1', TRUE, 'Luke Skywalker', 1, 1, NULL, 77),
+    (2, '2', TRUE, 'Darth Vader', 1, 3, NULL, 120),
+    (3, '3', TRUE, 'Han Solo', NULL, 5, NULL, 85),
+    (4, '4', TRUE, 'Leia Organa', 2, 2, NULL, 60),
+    (5, '5', TRUE, 'Wilhuff Tarkin', NULL, 4, NULL, 90),
+    (6, '6', FALSE, 'R2-D2', NULL, NULL, 'Astromech', 32),
+    (7, '7', FALSE, 'C-3PO', NULL, NULL, 'Protocol', 75)
+;
+
+INSERT INTO friendships(character, friend, position)
+VALUES
+    (1, 4, 0), (1, 3, 1), (1, 6, 2), (1, 7, 3), -- luke
+    (4, 1, 0), (4, 3, 1), (4, 6, 2), (4, 7, 3), -- leia
+    (3, 4, 0), (3, 1, 1), (3, 6, 2), (3, 7, 3), -- han
+    (6, 1, 0), (6, 4, 1), (6, 3, 2), (6, 7, 3), -- r2
+    (7, 1, 0), (7, 3, 1), (7, 4, 2), (7, 6, 3), -- 3po
+    (5, 2, 0), -- tarkin
+    (2, 5, 0) -- vader
+;
+
+INSERT INTO character_episodes(character, episode)
+SELECT key, episode
+FROM characters, unnest(enum_range(NULL::episode)) AS episode
+;
+
+-- the explicit keys above don't move the sequences
+SELECT setval(pg_get_serial_sequence('planets', 'key'), (SELECT MAX(key) FROM planets));
+SELECT setval(pg_get_serial_sequence('starships', 'key'), (SELECT MAX(key) FROM starships));
+SELECT setval(pg_get_serial_sequence('characters', 'key'), (SELECT MAX(key) FROM characters));
@@ -0,0 +1,16 @@
+-- A human can fly more than one starship and a starship can have more than one pilot,
+-- so characters.star_ship becomes its own table
+
+CREATE TABLE pilots (
+    pilot INTEGER NOT NULL REFERENCES characters(key) ON DELETE CASCADE,
+    starship INTEGER NOT NULL REFERENCES starships(key) ON DELETE CASCADE,
+    PRIMARY KEY (pilot, starship)
+);
+
+-- for StarShip.pilots
+CREATE INDEX pilots_starship_idx ON pilots(starship);
+
+INSERT INTO pilots(pilot, starship)
+SELECT key, star_ship FROM characters WHERE star_ship IS NOT NULL;
+
+ALTER TABLE characters DROP COLUMN star_ship;
@@ -0,0 +1,85 @@
+-- Films become their own entity, `appearsIn` is derived from the films a character is in.
+-- character_episodes is converted to film_characters, the `episode` enum goes away.
+
+CREATE TABLE films(
+    key SERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    episode_id INTEGER NOT NULL UNIQUE CHECK(episode_id > 0),
+    title TEXT NOT NULL,
+    release_date DATE NOT NULL,
+    director TEXT NOT NULL,
+    producer TEXT NOT NULL,
+    opening_crawl TEXT NOT NULL
+);
+
+CREATE TABLE film_characters(
+    film INTEGER NOT NULL REFERENCES films(key) ON DELETE CASCADE,
+    character INTEGER NOT NULL REFERENCES characters(key) ON DELETE CASCADE,
+    PRIMARY KEY(film, character)
+);
+
+-- for the films of a character
+CREATE INDEX film_characters_character_idx ON film_characters(character);
+
+CREATE TABLE film_starships(
+    film INTEGER NOT NULL REFERENCES films(key) ON DELETE CASCADE,
+    starship INTEGER NOT NULL REFERENCES starships(key) ON DELETE CASCADE,
+    PRIMARY KEY(film, starship)
+);
+
+CREATE TABLE film_planets(
+    film INTEGER NOT NULL REFERENCES films(key) ON DELETE CASCADE,
+    planet INTEGER NOT NULL REFERENCES planets(key) ON DELETE CASCADE,
+    PRIMARY KEY(film, planet)
+);
+
+INSERT INTO films(key, id, episode_id, title, release_date, director, producer, opening_crawl)
+VALUES
+    (1, '1', 4, 'A New Hope', '1977-05-25', 'George Lucas', 'Gary Kurtz, Rick McCallum',
+     'It is a period of civil war. Rebel spaceships, striking from a hidden base, have won their first victory against the evil Galactic Empire.
+
+During the battle, Rebel spies managed to steal secret plans to the Empire''s ultimate weapon, the DEATH STAR, an armored space station with enough power to destroy an entire planet.
+
+Pursued by the Empire''s sinister agents, Princess Leia races home aboard her starship, custodian of the stolen plans that can save her people and restore freedom to the galaxy....'),
+    (2, '2', 5, 'The Empire Strikes Back', '1980-05-17', 'Irvin Kershner', 'Gary Kurtz, Rick McCallum',
+     'It is a dark time for the Rebellion. Although the Death Star has been destroyed, Imperial troops have driven the Rebel forces from their hidden base and pursued them across the galaxy.
+
+Evading the dreaded Imperial Starfleet, a group of freedom fighters led by Luke Skywalker has established a new secret base on the remote ice world of Hoth.
+
+The evil lord Darth Vader, obsessed with finding young Skywalker, has dispatched thousands of remote probes into the far reaches of space....'),
+    (3, '3', 6, 'Return of the Jedi', '1983-05-25', 'Richard Marquand', 'Howard G. Kazanjian, George Lucas, Rick McCallum',
+     'Luke Skywalker has returned to his home planet of Tatooine in an attempt to rescue his friend Han Solo from the clutches of the vile gangster Jabba the Hutt.
+
+Little does Luke know that the GALACTIC EMPIRE has secretly begun construction on a new armored space station even more powerful than the first dreaded Death Star.
+
+When completed, this ultimate weapon will spell certain doom for the small band of rebels struggling to restore freedom to the galaxy...')
+;
+
+SELECT setval(pg_get_serial_sequence('films', 'key'), (SELECT MAX(key) FROM films));
+
+INSERT INTO film_characters(film, character)
+SELECT f.key, ce.character
+FROM character_episodes ce
+JOIN films f ON f.episode_id = CASE ce.episode
+    WHEN 'NEW_HOPE' THEN 4
+    WHEN 'EMPIRE' THEN 5
+    WHEN 'JEDI' THEN 6
+END;
+
+-- only the starships and planets of the seed, they are found by id
+INSERT INTO film_starships(film, starship)
+SELECT f.key, s.key
+FROM (VALUES ('1', '1'), ('1', '2'), ('1', '3'), ('1', '4'), ('1', '5'),
+             ('2', '1'), ('2', '3'), ('2', '5'),
+             ('3', '1'), ('3', '5')) AS v(film, starship)
+JOIN films f ON f.id = v.film
+JOIN starships s ON s.id = v.starship;
+
+INSERT INTO film_planets(film, planet)
+SELECT f.key, p.key
+FROM (VALUES ('1', '1'), ('1', '2'), ('3', '1')) AS v(film, planet)
+JOIN films f ON f.id = v.film
+JOIN planets p ON p.id = v.planet;
+
+DROP TABLE character_episodes;
+DROP TYPE episode;
@@ -0,0 +1,13 @@
+-- The heroes used to be hard-coded (luke for the saga and EMPIRE, R2-D2 for the rest),
+-- now `setEpisodeHero` can change them. `episode_id` 0 is the hero of the entire saga,
+-- an episode without a row falls back to it.
+
+CREATE TABLE heroes(
+    episode_id INTEGER PRIMARY KEY CHECK(episode_id >= 0),
+    character INTEGER NOT NULL REFERENCES characters(key) ON DELETE RESTRICT
+);
+
+INSERT INTO heroes(episode_id, character)
+SELECT v.episode_id, c.key
+FROM (VALUES (0, '1'), (4, '6'), (5, '1'), (6, '6')) AS v(episode_id, character)
+JOIN characters c ON c.id = v.character;
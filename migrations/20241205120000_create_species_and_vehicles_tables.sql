@@ -0,0 +1,82 @@
+-- Species and vehicles of the SWAPI model, droids are still `is_human = FALSE`
+-- but now also have the Droid species
+
+CREATE TABLE species(
+    key SERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    classification TEXT NOT NULL,
+    language TEXT NOT NULL,
+    -- NULL when it is indefinite (droids) or unknown
+    average_lifespan BIGINT CHECK(average_lifespan >= 0),
+    homeworld INTEGER REFERENCES planets(key) ON DELETE SET NULL
+);
+
+CREATE TABLE vehicles(
+    key SERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    model TEXT NOT NULL,
+    manufacturer TEXT NOT NULL,
+    cost_in_credits BIGINT CHECK(cost_in_credits >= 0),
+    crew BIGINT NOT NULL CHECK(crew >= 0),
+    passengers BIGINT NOT NULL CHECK(passengers >= 0)
+);
+
+ALTER TABLE characters ADD COLUMN species INTEGER REFERENCES species(key) ON DELETE SET NULL;
+
+CREATE TABLE vehicle_pilots(
+    pilot INTEGER NOT NULL REFERENCES characters(key) ON DELETE CASCADE,
+    vehicle INTEGER NOT NULL REFERENCES vehicles(key) ON DELETE CASCADE,
+    PRIMARY KEY(pilot, vehicle)
+);
+
+-- for Vehicle.pilots
+CREATE INDEX vehicle_pilots_vehicle_idx ON vehicle_pilots(vehicle);
+
+CREATE TABLE film_species(
+    film INTEGER NOT NULL REFERENCES films(key) ON DELETE CASCADE,
+    species INTEGER NOT NULL REFERENCES species(key) ON DELETE CASCADE,
+    PRIMARY KEY(film, species)
+);
+
+CREATE TABLE film_vehicles(
+    film INTEGER NOT NULL REFERENCES films(key) ON DELETE CASCADE,
+    vehicle INTEGER NOT NULL REFERENCES vehicles(key) ON DELETE CASCADE,
+    PRIMARY KEY(film, vehicle)
+);
+
+INSERT INTO species(key, id, name, classification, language, average_lifespan, homeworld)
+VALUES
+    (1, '1', 'Human', 'mammal', 'Galactic Basic', 120, NULL),
+    (2, '2', 'Droid', 'artificial', 'n/a', NULL, NULL)
+;
+
+INSERT INTO vehicles(key, id, name, model, manufacturer, cost_in_credits, crew, passengers)
+VALUES
+    (1, '1', 'Sand Crawler', 'Digger Crawler', 'Corellia Mining Corporation', 150000, 46, 30),
+    (2, '2', 'X-34 landspeeder', 'X-34 landspeeder', 'SoroSuub Corporation', 10550, 1, 1),
+    (3, '3', 'Snowspeeder', 't-47 airspeeder', 'Incom corporation', NULL, 2, 0),
+    (4, '4', 'Imperial Speeder Bike', '74-Z speeder bike', 'Aratech Repulsor Company', 8000, 1, 1)
+;
+
+SELECT setval(pg_get_serial_sequence('species', 'key'), (SELECT MAX(key) FROM species));
+SELECT setval(pg_get_serial_sequence('vehicles', 'key'), (SELECT MAX(key) FROM vehicles));
+
+UPDATE characters SET species = CASE WHEN is_human THEN 1 ELSE 2 END;
+
+-- luke and leia on the speeder bike, luke in the snowspeeder
+INSERT INTO vehicle_pilots(pilot, vehicle)
+SELECT c.key, v.key
+FROM (VALUES ('1', '3'), ('1', '4'), ('4', '4')) AS p(pilot, vehicle)
+JOIN characters c ON c.id = p.pilot
+JOIN vehicles v ON v.id = p.vehicle;
+
+INSERT INTO film_species(film, species)
+SELECT f.key, s.key FROM films f CROSS JOIN species s;
+
+INSERT INTO film_vehicles(film, vehicle)
+SELECT f.key, v.key
+FROM (VALUES ('1', '1'), ('1', '2'), ('2', '3'), ('3', '4')) AS p(film, vehicle)
+JOIN films f ON f.id = p.film
+JOIN vehicles v ON v.id = p.vehicle;
@@ -0,0 +1,17 @@
+-- Long-lived keys for partner integrations, sent as `X-Api-Key`.
+-- Only the sha256 of a key is stored, the key itself is shown once by `issueApiKey`.
+-- `used` counts the requests since `window_start`, the window is one hour.
+
+CREATE TABLE api_keys(
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    prefix TEXT NOT NULL,
+    owner_id TEXT NOT NULL,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    quota INTEGER NOT NULL CHECK(quota > 0),
+    used INTEGER NOT NULL DEFAULT 0,
+    window_start TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    revoked_at TIMESTAMPTZ
+);
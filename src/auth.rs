@@ -0,0 +1,442 @@
+use std::{fmt, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::starwars::{
+    api_keys::{ApiKeyScope, ApiKeys},
+    errors::ApiError,
+};
+
+/// the header partner integrations send their api key in
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// How the callers are checked, out of the config
+pub enum Authentication {
+    /// `AUTH_DISABLED=true`, nobody is checked and everybody can do everything
+    Disabled,
+
+    /// Bearer tokens checked with this key.
+    /// Without `JWT_SECRET` or `JWT_PUBLIC_KEY` every caller is anonymous.
+    Tokens(Option<Box<Auth>>),
+}
+
+impl Authentication {
+    /// the caller of a request with this bearer token, or why the token is refused
+    pub fn caller(&self, token: Option<&str>) -> Result<Caller, String> {
+        match (self, token) {
+            (Authentication::Disabled, _) => Ok(Caller::Unchecked),
+            (Authentication::Tokens(_), None) => Ok(Caller::Anonymous),
+            (Authentication::Tokens(None), Some(_)) => {
+                Err("no JWT_SECRET or JWT_PUBLIC_KEY to check the bearer token with".into())
+            }
+            (Authentication::Tokens(Some(auth)), Some(token)) => auth
+                .verify(token)
+                .map_err(|e| format!("invalid bearer token: {e}")),
+        }
+    }
+}
+
+/// Checks the bearer tokens, with the key from `JWT_SECRET` (HS256)
+/// or `JWT_PUBLIC_KEY` (RS256)
+pub struct Auth {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl Auth {
+    /// tokens signed with a shared secret
+    pub fn hs256(secret: &str) -> Self {
+        Self::new(
+            DecodingKey::from_secret(secret.as_bytes()),
+            Algorithm::HS256,
+        )
+    }
+
+    /// tokens signed with the private key of this PEM public key
+    pub fn rs256(pem: &str) -> Result<Self, String> {
+        let key = DecodingKey::from_rsa_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+        Ok(Self::new(key, Algorithm::RS256))
+    }
+
+    fn new(key: DecodingKey, algorithm: Algorithm) -> Self {
+        let mut validation = Validation::new(algorithm);
+        // only checked when `with_audience` says what it should be
+        validation.validate_aud = false;
+        Self { key, validation }
+    }
+
+    /// only tokens with this `iss`
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.validation.set_issuer(&[issuer]);
+        self
+    }
+
+    /// only tokens with this `aud`
+    pub fn with_audience(mut self, audience: &str) -> Self {
+        self.validation.set_audience(&[audience]);
+        self.validation.validate_aud = true;
+        self
+    }
+
+    /// the caller of a token, `exp` is required
+    fn verify(&self, token: &str) -> Result<Caller, jsonwebtoken::errors::Error> {
+        let claims = decode::<Claims>(token, &self.key, &self.validation)?.claims;
+        Ok(Caller::User {
+            user_id: claims.sub,
+            role: claims.role,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    /// id of the character of the caller, the same id as the credits use
+    sub: String,
+
+    /// `viewer` when the token doesn't say
+    #[serde(default)]
+    role: Role,
+}
+
+/// What a caller may do, besides what they may do with their own character.
+/// An admin can do everything a viewer can.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// looks around, and manages their own character and credits
+    #[default]
+    Viewer,
+
+    /// changes the universe and sees every account
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Who sends the request. The auth layer puts it in the extensions of every request,
+/// the handlers pass it on to the GraphQL data.
+#[derive(Clone, Debug)]
+pub enum Caller {
+    /// `AUTH_DISABLED=true`, so nobody is checked
+    Unchecked,
+
+    /// no bearer token
+    Anonymous,
+
+    /// the `sub` and `role` of a valid token
+    User { user_id: String, role: Role },
+
+    /// a valid `X-Api-Key`, it only acts for its owner with the `ACCOUNT` scope
+    ApiKey {
+        /// `ApiKey.id`, to count the operations over a websocket
+        id: i64,
+        owner_id: String,
+        scopes: Vec<ApiKeyScope>,
+    },
+}
+
+impl Caller {
+    /// id of the character of the caller
+    pub fn user_id(&self) -> Option<&str> {
+        match self {
+            Caller::User { user_id, .. } => Some(user_id),
+            Caller::ApiKey {
+                owner_id, scopes, ..
+            } if scopes.contains(&ApiKeyScope::Account) => Some(owner_id),
+            Caller::ApiKey { .. } | Caller::Unchecked | Caller::Anonymous => None,
+        }
+    }
+
+    /// has at least `role`, with `AUTH_DISABLED` everybody has every role
+    pub fn has_role(&self, role: Role) -> bool {
+        match self {
+            Caller::Unchecked => true,
+            Caller::Anonymous => false,
+            Caller::User { role: own, .. } => *own >= role,
+            Caller::ApiKey { scopes, .. } => {
+                role == Role::Viewer || scopes.contains(&ApiKeyScope::Admin)
+            }
+        }
+    }
+}
+
+/// Axum middleware: a request without a token is anonymous, one with an invalid token
+/// is refused with a 401 before it gets to GraphQL.
+pub async fn authenticate(
+    State(auth): State<Arc<Authentication>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let caller = match auth.caller(bearer_token(&request)) {
+        Ok(caller) => caller,
+        Err(e) => return unauthorized(&e),
+    };
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+/// Axum middleware after `authenticate`: a request with an `X-Api-Key` is done by that key.
+/// A POST is one operation and counts for the quota, the GraphiQL page and the websocket
+/// upgrade don't: `WebSocketQuota` counts the operations over the websocket.
+/// Unknown and revoked keys get a 401, a key over its quota a 429.
+pub async fn api_key(
+    State(api_keys): State<Option<ApiKeys>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = request.headers().get(API_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let Ok(key) = key.to_str() else {
+        return unauthorized("invalid api key");
+    };
+    if bearer_token(&request).is_some() {
+        return unauthorized("send a bearer token or an api key, not both");
+    }
+    let Some(api_keys) = api_keys else {
+        return unauthorized("api keys need a database");
+    };
+    let counts = request.method() == Method::POST;
+    let used = if counts {
+        api_keys.use_key(key.trim()).await
+    } else {
+        api_keys.find(key.trim()).await
+    };
+    let used = match used {
+        Ok(Some(used)) => used,
+        Ok(None) => return unauthorized("invalid api key"),
+        Err(e) => return refuse(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    };
+    if counts && used.exceeded() {
+        let retry_after = (used.resets_at - chrono::Utc::now()).num_seconds().max(1);
+        let mut response = refuse(
+            StatusCode::TOO_MANY_REQUESTS,
+            &ApiError::QuotaExceeded { quota: used.quota },
+        );
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        return response;
+    }
+    tracing::debug!("request with api key {}", used.id);
+    let (quota, remaining) = (used.quota, used.remaining());
+    request.extensions_mut().insert(Caller::ApiKey {
+        id: used.id,
+        owner_id: used.owner_id,
+        scopes: used.scopes,
+    });
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("x-ratelimit-limit", HeaderValue::from(quota));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(remaining));
+    response
+}
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    bearer(
+        request
+            .headers()
+            .get(header::AUTHORIZATION)?
+            .to_str()
+            .ok()?,
+    )
+}
+
+/// The token of a websocket, out of `{"Authorization": "Bearer <token>"}` in the payload
+/// of `connection_init`, like the header of a normal request
+pub fn connection_init_token(payload: &serde_json::Value) -> Option<&str> {
+    let value = payload
+        .get("Authorization")
+        .or_else(|| payload.get("authorization"))?;
+    bearer(value.as_str()?)
+}
+
+fn bearer(value: &str) -> Option<&str> {
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then_some(token.trim())
+}
+
+/// a 401 that looks like any other GraphQL error
+fn unauthorized(message: &str) -> Response {
+    error_response(
+        StatusCode::UNAUTHORIZED,
+        ApiError::Unauthenticated.code(),
+        message,
+    )
+}
+
+fn refuse(status: StatusCode, error: &ApiError) -> Response {
+    if let ApiError::Database(e) = error {
+        tracing::error!("database error: {e}");
+    }
+    error_response(status, error.code(), &error.to_string())
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = serde_json::json!({
+        "errors": [{
+            "message": message,
+            "extensions": { "code": code },
+        }]
+    });
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    use super::*;
+
+    const SECRET: &str = "a secret";
+
+    fn token(secret: &str, claims: serde_json::Value) -> String {
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn in_an_hour() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    fn key(scopes: Vec<ApiKeyScope>) -> Caller {
+        Caller::ApiKey {
+            id: 1,
+            owner_id: "1".into(),
+            scopes,
+        }
+    }
+
+    #[test]
+    fn roles_and_user_ids() {
+        let viewer = Caller::User {
+            user_id: "1".into(),
+            role: Role::Viewer,
+        };
+        let admin = Caller::User {
+            user_id: "2".into(),
+            role: Role::Admin,
+        };
+        let cases = [
+            (Caller::Unchecked, None, true, true),
+            (Caller::Anonymous, None, false, false),
+            (viewer, Some("1"), true, false),
+            (admin, Some("2"), true, true),
+            (key(Vec::new()), None, true, false),
+            (key(vec![ApiKeyScope::Account]), Some("1"), true, false),
+            (key(vec![ApiKeyScope::Admin]), None, true, true),
+        ];
+        for (caller, user_id, viewer, admin) in cases {
+            assert_eq!(caller.user_id(), user_id, "{caller:?}");
+            assert_eq!(caller.has_role(Role::Viewer), viewer, "{caller:?}");
+            assert_eq!(caller.has_role(Role::Admin), admin, "{caller:?}");
+        }
+    }
+
+    #[test]
+    fn tokens_are_verified() {
+        let auth = Authentication::Tokens(Some(Box::new(Auth::hs256(SECRET))));
+        let admin = token(
+            SECRET,
+            serde_json::json!({ "sub": "1", "role": "admin", "exp": in_an_hour() }),
+        );
+        assert!(matches!(
+            auth.caller(Some(&admin)),
+            Ok(Caller::User { user_id, role: Role::Admin }) if user_id == "1"
+        ));
+        let viewer = token(
+            SECRET,
+            serde_json::json!({ "sub": "3", "exp": in_an_hour() }),
+        );
+        assert!(matches!(
+            auth.caller(Some(&viewer)),
+            Ok(Caller::User {
+                role: Role::Viewer,
+                ..
+            })
+        ));
+        assert!(matches!(auth.caller(None), Ok(Caller::Anonymous)));
+
+        let refused = [
+            token(
+                "another secret",
+                serde_json::json!({ "sub": "1", "exp": in_an_hour() }),
+            ),
+            token(SECRET, serde_json::json!({ "sub": "1", "exp": 1 })),
+            token(SECRET, serde_json::json!({ "sub": "1" })),
+            "not a token".into(),
+        ];
+        for token in refused {
+            assert!(auth.caller(Some(&token)).is_err(), "{token}");
+        }
+    }
+
+    #[test]
+    fn issuer_and_audience_are_checked() {
+        let auth = Authentication::Tokens(Some(Box::new(
+            Auth::hs256(SECRET)
+                .with_issuer("swapi")
+                .with_audience("partners"),
+        )));
+        let claims = |iss: &str, aud: &str| serde_json::json!({ "sub": "1", "exp": in_an_hour(), "iss": iss, "aud": aud });
+        assert!(auth
+            .caller(Some(&token(SECRET, claims("swapi", "partners"))))
+            .is_ok());
+        assert!(auth
+            .caller(Some(&token(SECRET, claims("other", "partners"))))
+            .is_err());
+        assert!(auth
+            .caller(Some(&token(SECRET, claims("swapi", "other"))))
+            .is_err());
+    }
+
+    #[test]
+    fn websockets_send_the_token_in_the_payload() {
+        let payload = serde_json::json!({ "Authorization": "Bearer abc" });
+        assert_eq!(connection_init_token(&payload), Some("abc"));
+        let payload = serde_json::json!({ "authorization": "bearer abc" });
+        assert_eq!(connection_init_token(&payload), Some("abc"));
+        for payload in [
+            serde_json::json!({}),
+            serde_json::Value::Null,
+            serde_json::json!({ "Authorization": "Basic abc" }),
+        ] {
+            assert_eq!(connection_init_token(&payload), None);
+        }
+    }
+
+    #[test]
+    fn without_keys_nobody_gets_in() {
+        let auth = Authentication::Tokens(None);
+        assert!(matches!(auth.caller(None), Ok(Caller::Anonymous)));
+        let admin = token(
+            SECRET,
+            serde_json::json!({ "sub": "1", "role": "admin", "exp": in_an_hour() }),
+        );
+        assert!(auth.caller(Some(&admin)).is_err());
+
+        let disabled = Authentication::Disabled;
+        assert!(matches!(disabled.caller(None), Ok(Caller::Unchecked)));
+    }
+}
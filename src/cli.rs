@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use crate::starwars::{credits::Credits, dataset, swapi_dump, Repository};
+
+pub const USAGE: &str = "usage:
+  swapi-rs                                  start the server
+  swapi-rs import <dump dir> <dataset>      turn a swapi.dev dump into a .json or .yaml dataset
+  swapi-rs export <snapshot>                write the universe and the credits to a .json or .yaml dataset";
+
+/// What to do, the server unless a subcommand is given
+pub enum Command {
+    Serve,
+    /// `import <dump dir> <dataset>`
+    Import {
+        dump: PathBuf,
+        dataset: PathBuf,
+    },
+    /// `export <snapshot>`, with the same configuration as the server
+    Export {
+        snapshot: PathBuf,
+    },
+}
+
+impl Command {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let args: Vec<String> = args.into_iter().collect();
+        match args
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [] => Ok(Command::Serve),
+            ["import", dump, dataset] => Ok(Command::Import {
+                dump: dump.into(),
+                dataset: dataset.into(),
+            }),
+            ["export", snapshot] => Ok(Command::Export {
+                snapshot: snapshot.into(),
+            }),
+            _ => Err(format!("unknown command `{}`\n{USAGE}", args.join(" "))),
+        }
+    }
+}
+
+/// Imports a swapi.dev dump into a dataset for `DATASET_PATH`, prints what couldn't be mapped
+pub fn import(dump: PathBuf, dataset: PathBuf) -> Result<(), String> {
+    let (data, report) = swapi_dump::import(&dump).map_err(|e| e.to_string())?;
+    println!("{report}");
+
+    // the import keeps to the rules, but the loader would refuse it otherwise
+    let problems = data.problems();
+    if !problems.is_empty() {
+        return Err(format!(
+            "the imported dataset is invalid:\n  - {}",
+            problems.join("\n  - ")
+        ));
+    }
+    data.save(&dataset).map_err(|e| e.to_string())?;
+    println!(
+        "wrote {} characters, {} planets, {} starships, {} vehicles, {} species and {} films to {}",
+        data.characters.len(),
+        data.planets.len(),
+        data.starships.len(),
+        data.vehicles.len(),
+        data.species.len(),
+        data.films.len(),
+        dataset.display()
+    );
+    Ok(())
+}
+
+/// Writes what the server would start with to `snapshot`, `DATASET_PATH` loads it again.
+/// For the memory backend that is the dataset or the built-in universe,
+/// the `snapshot` query has what a running server has in memory.
+pub async fn export(api: &Repository, credits: &Credits, snapshot: PathBuf) -> Result<(), String> {
+    let data = dataset::snapshot(api, credits)
+        .await
+        .map_err(|e| e.to_string())?;
+    data.save(&snapshot).map_err(|e| e.to_string())?;
+    println!("wrote the snapshot to {}", snapshot.display());
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::{env, fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{
+    auth::{Auth, Authentication},
+    starwars::repository::StorageBackend,
+};
+
+/// Everything the server reads from the environment at startup
+pub struct Config {
+    /// `DATABASE_URL`, postgres is used for the credits and (by default) the star wars data.
+    /// Without it everything is kept in memory.
+    pub database_url: Option<String>,
+
+    /// `STORAGE_BACKEND`, `postgres` or `memory`,
+    /// defaults to `postgres` when there is a database and `memory` otherwise
+    pub storage: StorageBackend,
+
+    /// `DATASET_PATH`, a `.json` or `.yaml` file with the universe for the `memory` backend.
+    /// The built-in universe is used when it isn't set or can't be loaded.
+    pub dataset: Option<PathBuf>,
+
+    /// `SNAPSHOT_PATH`, where the `memory` backend keeps a snapshot of everything.
+    /// It is restored at startup instead of the dataset when it exists.
+    pub snapshot: Option<PathBuf>,
+
+    /// `SNAPSHOT_INTERVAL`, seconds between two snapshots, defaults to 60
+    pub snapshot_interval: Duration,
+
+    /// `JWT_SECRET` (HS256) or `JWT_PUBLIC_KEY` (RS256, a PEM file or the PEM itself),
+    /// with the optional `JWT_ISSUER` and `JWT_AUDIENCE`.
+    /// Without a key every caller is anonymous, only `AUTH_DISABLED=true` turns the checks off.
+    pub auth: Authentication,
+
+    /// `QUERY_MAX_DEPTH`, how deep a query may nest its fields, defaults to 15
+    pub max_depth: usize,
+
+    /// `QUERY_MAX_COMPLEXITY`, what a query may cost, defaults to 5000.
+    /// A field costs 1, a list costs its page size times what is asked of one item.
+    pub max_complexity: usize,
+}
+
+/// seconds between two snapshots when `SNAPSHOT_INTERVAL` isn't set
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 60;
+
+/// deep enough for the introspection query of GraphiQL and a few levels of friends of friends
+const DEFAULT_MAX_DEPTH: usize = 15;
+const DEFAULT_MAX_COMPLEXITY: usize = 5000;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Missing(&'static str),
+    Invalid { var: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing(var) => write!(f, "{var} must be set"),
+            ConfigError::Invalid { var, reason } => write!(f, "invalid {var}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let database_url = env::var("DATABASE_URL").ok().filter(|url| !url.is_empty());
+        let storage = match parse("STORAGE_BACKEND")? {
+            Some(StorageBackend::Postgres) if database_url.is_none() => {
+                return Err(ConfigError::Missing("DATABASE_URL"))
+            }
+            Some(storage) => storage,
+            None if database_url.is_some() => StorageBackend::Postgres,
+            None => StorageBackend::Memory,
+        };
+        let dataset = env::var_os("DATASET_PATH")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let snapshot = env::var_os("SNAPSHOT_PATH")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let snapshot_interval = match parse("SNAPSHOT_INTERVAL")? {
+            Some(0) => {
+                return Err(ConfigError::Invalid {
+                    var: "SNAPSHOT_INTERVAL",
+                    reason: "must be at least 1 second".into(),
+                })
+            }
+            Some(seconds) => Duration::from_secs(seconds),
+            None => Duration::from_secs(DEFAULT_SNAPSHOT_INTERVAL),
+        };
+        Ok(Self {
+            database_url,
+            storage,
+            dataset,
+            snapshot,
+            snapshot_interval,
+            auth: auth()?,
+            max_depth: limit("QUERY_MAX_DEPTH", DEFAULT_MAX_DEPTH)?,
+            max_complexity: limit("QUERY_MAX_COMPLEXITY", DEFAULT_MAX_COMPLEXITY)?,
+        })
+    }
+}
+
+fn var(var: &'static str) -> Option<String> {
+    env::var(var).ok().filter(|value| !value.is_empty())
+}
+
+fn auth() -> Result<Authentication, ConfigError> {
+    let disabled = parse("AUTH_DISABLED")?.unwrap_or(false);
+    let auth = match (var("JWT_SECRET"), var("JWT_PUBLIC_KEY")) {
+        (None, None) if disabled => return Ok(Authentication::Disabled),
+        (None, None) => return Ok(Authentication::Tokens(None)),
+        _ if disabled => {
+            return Err(ConfigError::Invalid {
+                var: "AUTH_DISABLED",
+                reason: "can't turn the checks off with JWT_SECRET or JWT_PUBLIC_KEY set".into(),
+            })
+        }
+        (Some(_), Some(_)) => {
+            return Err(ConfigError::Invalid {
+                var: "JWT_PUBLIC_KEY",
+                reason: "set either JWT_SECRET or JWT_PUBLIC_KEY, not both".into(),
+            })
+        }
+        (Some(secret), None) => Auth::hs256(&secret),
+        (None, Some(key)) => {
+            let invalid = |reason: String| ConfigError::Invalid {
+                var: "JWT_PUBLIC_KEY",
+                reason,
+            };
+            let pem = if key.trim_start().starts_with("-----BEGIN") {
+                key
+            } else {
+                std::fs::read_to_string(&key).map_err(|e| invalid(format!("{key}: {e}")))?
+            };
+            Auth::rs256(&pem).map_err(invalid)?
+        }
+    };
+    let auth = match var("JWT_ISSUER") {
+        Some(issuer) => auth.with_issuer(&issuer),
+        None => auth,
+    };
+    let auth = match var("JWT_AUDIENCE") {
+        Some(audience) => auth.with_audience(&audience),
+        None => auth,
+    };
+    Ok(Authentication::Tokens(Some(Box::new(auth))))
+}
+
+/// a query limit, it can't be 0 as nothing would get through
+fn limit(var: &'static str, default: usize) -> Result<usize, ConfigError> {
+    match parse(var)? {
+        Some(0) => Err(ConfigError::Invalid {
+            var,
+            reason: "must be at least 1".into(),
+        }),
+        Some(limit) => Ok(limit),
+        None => Ok(default),
+    }
+}
+
+/// reads `var` and parses it, `None` when it isn't set
+fn parse<T>(var: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match env::var(var) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e: T::Err| ConfigError::Invalid {
+                var,
+                reason: e.to_string(),
+            }),
+        Err(_) => Ok(None),
+    }
+}
@@ -1,58 +1,255 @@
+mod auth;
+mod cli;
+mod config;
 mod starwars;
 
-use async_graphql::dataloader::*;
-use async_graphql::{http::GraphiQLSource, EmptySubscription, Schema};
-use async_graphql_axum::GraphQL;
+use async_graphql::{
+    http::{GraphiQLSource, ALL_WEBSOCKET_PROTOCOLS},
+    Data,
+};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use auth::{Authentication, Caller};
 use axum::{
-    response::{self, IntoResponse},
+    extract::{State, WebSocketUpgrade},
+    middleware,
+    response::{self, IntoResponse, Response},
     routing::get,
-    Router,
+    Extension, Router,
 };
+use cli::Command;
+use config::Config;
 use sqlx::PgPool;
-use starwars::{credits_loader::CreditsDataLoader, MutationRoot, QueryRoot, StarWarsAPI};
+use starwars::{
+    api_keys::ApiKeys,
+    credits::{CreditEvents, Credits, MemoryCredits, PgCredits},
+    limits::QueryLimits,
+    repository::{load_dataset, StorageBackend},
+    schema::{self, StarWarsSchema},
+    snapshots::Snapshots,
+};
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tower_http::trace::TraceLayer;
 
 async fn graphiql() -> impl IntoResponse {
-    response::Html(GraphiQLSource::build().endpoint("/").finish())
+    response::Html(
+        GraphiQLSource::build()
+            .endpoint("/")
+            .subscription_endpoint("/ws")
+            .finish(),
+    )
+}
+
+/// queries and mutations, with the caller the auth layer found
+async fn graphql(
+    State(schema): State<StarWarsSchema>,
+    Extension(caller): Extension<Caller>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    schema
+        .execute(request.into_inner().data(caller))
+        .await
+        .into()
+}
+
+/// graphql-ws (and the older subscriptions-transport-ws) protocol.
+/// The caller is the one of the upgrade request, unless the `connection_init` payload
+/// has a token: browsers can't send headers with a websocket.
+async fn graphql_ws(
+    State(schema): State<StarWarsSchema>,
+    Extension(caller): Extension<Caller>,
+    Extension(auth): Extension<Arc<Authentication>>,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            let mut data = Data::default();
+            data.insert(caller);
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .with_data(data)
+                .on_connection_init(move |payload| async move {
+                    let mut data = Data::default();
+                    if let Some(token) = auth::connection_init_token(&payload) {
+                        // an invalid token closes the connection
+                        data.insert(auth.caller(Some(token))?);
+                    }
+                    Ok(data)
+                })
+                .serve()
+        })
 }
 
 #[tokio::main]
 async fn main() {
-    let swapi = StarWarsAPI::default();
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = PgPool::connect(&database_url).await.unwrap();
-
-    // Run migrations automatically when the application starts
-    sqlx::migrate!("./migrations") // Path to the migrations folder
-        .run(&pool)
-        .await
-        .expect("Failed to run migrations");
+    let command = Command::from_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2)
+    });
+    if let Command::Import { dump, dataset } = command {
+        if let Err(e) = cli::import(dump, dataset) {
+            eprintln!("{e}");
+            std::process::exit(1)
+        }
+        return;
+    }
 
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::DEBUG)
         .init();
 
-    let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
-        .data(swapi)
-        .data(pool.clone()) // the database connection
-        .data(DataLoader::new(
-            CreditsDataLoader { pool: pool.clone() },
-            tokio::task::spawn,
-        ))
-        //.data(DatabasePool) // kunt een database toevoegen
-        //.data(FacebookAPI) // kunt een api toevoegen
-        //.data(s3Bucket) // s3 buckets
-        // ...etc
-        .finish();
+    let config = Config::from_env().unwrap_or_else(|e| panic!("{e}"));
+    let pool = match &config.database_url {
+        Some(database_url) => {
+            let pool = PgPool::connect(database_url).await.unwrap();
+
+            // Run migrations automatically when the application starts
+            sqlx::migrate!("./migrations") // Path to the migrations folder
+                .run(&pool)
+                .await
+                .expect("Failed to run migrations");
+            Some(pool)
+        }
+        None => None,
+    };
 
+    // only the memory backend needs snapshots, postgres keeps everything itself
+    let snapshot = match (config.storage, config.snapshot) {
+        (StorageBackend::Memory, snapshot) => snapshot,
+        (StorageBackend::Postgres, Some(_)) => {
+            tracing::warn!("ignoring SNAPSHOT_PATH, it is only for the memory backend");
+            None
+        }
+        (StorageBackend::Postgres, None) => None,
+    };
+    let restored = snapshot.as_deref().and_then(|path| {
+        let restored = Snapshots::restore(path)
+            .unwrap_or_else(|e| panic!("can't restore the snapshot {}: {e}", path.display()));
+        if restored.is_some() {
+            tracing::info!("restored the snapshot {}", path.display());
+        }
+        restored
+    });
+    let mut dataset = match restored {
+        Some(restored) => Some(restored),
+        None => config.dataset.as_deref().and_then(load_dataset),
+    };
+    let accounts = dataset.as_mut().and_then(|dataset| dataset.credits.take());
+    let ledger = dataset.as_mut().and_then(|dataset| dataset.ledger.take());
+    let swapi = config.storage.repository(pool.as_ref(), dataset);
+    // without a database the credits live in memory as well
+    let credits: Credits = match (&pool, accounts) {
+        (Some(pool), _) => Arc::new(PgCredits { pool: pool.clone() }),
+        (None, Some(accounts)) => {
+            let (transactions, next_id) =
+                ledger.map_or((Vec::new(), 1), |l| (l.transactions, l.next_id));
+            Arc::new(MemoryCredits::with_accounts(
+                accounts.into_iter().map(|a| (a.user_id, a.balance)),
+                transactions.into_iter().map(Into::into).collect(),
+                next_id,
+            ))
+        }
+        (None, None) => Arc::new(MemoryCredits::new()),
+    };
+
+    if let Command::Export { snapshot } = command {
+        if let Err(e) = cli::export(&swapi, &credits, snapshot).await {
+            eprintln!("{e}");
+            std::process::exit(1)
+        }
+        return;
+    }
+
+    // api keys and their quotas only exist in postgres
+    let api_keys = pool.clone().map(|pool| ApiKeys { pool });
+
+    // with a database every replica hears the transfers of the others
+    let events = match &pool {
+        Some(pool) => CreditEvents::listen(pool.clone())
+            .await
+            .unwrap_or_else(|e| panic!("can't listen for transfers: {e}")),
+        None => CreditEvents::new(),
+    };
+
+    let schema = schema::build(
+        swapi.clone(),
+        credits.clone(),
+        events,
+        api_keys.clone(),
+        QueryLimits {
+            max_depth: config.max_depth,
+            max_complexity: config.max_complexity,
+        },
+    );
+
+    match &config.auth {
+        Authentication::Disabled => {
+            tracing::warn!("AUTH_DISABLED is set, every caller can do everything")
+        }
+        Authentication::Tokens(None) => {
+            tracing::warn!("no JWT_SECRET or JWT_PUBLIC_KEY, every caller is anonymous")
+        }
+        Authentication::Tokens(Some(_)) => {}
+    }
+    let authentication = Arc::new(config.auth);
     let app = Router::new()
-        .route("/", get(graphiql).post_service(GraphQL::new(schema)))
-        .layer(TraceLayer::new_for_http());
+        .route("/", get(graphiql).post(graphql))
+        .route("/ws", get(graphql_ws))
+        .layer(middleware::from_fn_with_state(api_keys, auth::api_key))
+        .layer(middleware::from_fn_with_state(
+            authentication.clone(),
+            auth::authenticate,
+        ))
+        .layer(Extension(authentication))
+        .layer(TraceLayer::new_for_http())
+        .with_state(schema);
 
     println!("GraphiQL IDE: http://localhost:8000");
 
+    let snapshots = snapshot.map(|path| Snapshots {
+        path,
+        api: swapi,
+        credits,
+    });
+    if let Some(snapshots) = &snapshots {
+        snapshots.clone().every(config.snapshot_interval);
+    }
+
     axum::serve(TcpListener::bind("0.0.0.0:8000").await.unwrap(), app)
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+
+    // the last mutations since the previous snapshot
+    if let Some(snapshots) = snapshots {
+        match snapshots.write().await {
+            Ok(()) => tracing::info!("wrote the snapshot {}", snapshots.path.display()),
+            Err(e) => tracing::error!("can't write the snapshot {}: {e}", snapshots.path.display()),
+        }
+    }
+}
+
+/// ctrl-c, or the SIGTERM of docker and kubernetes
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for ctrl-c");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+    tracing::info!("shutting down");
 }
@@ -0,0 +1,500 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextSubscribe},
+    Context, Enum, ErrorExtensions, InputObject, Response, ServerError, SimpleObject,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use futures::{stream, stream::BoxStream, StreamExt};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use super::errors::ApiError;
+use crate::auth::Caller;
+
+/// every key starts with this, so they are easy to recognise in configs and logs
+const KEY_PREFIX: &str = "swapi_";
+
+/// how many characters of a key `ApiKey.prefix` shows
+const SHOWN_CHARACTERS: usize = KEY_PREFIX.len() + 6;
+
+/// operations per hour of a key when `issueApiKey` doesn't say
+const DEFAULT_QUOTA: i32 = 1000;
+
+const API_KEY_COLUMNS: &str =
+    "id, name, prefix, owner_id, scopes, quota, used, created_at, revoked_at";
+
+/// What a key may do besides reading everything anonymous callers can read
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ApiKeyScope {
+    /// act for the owner: its credits and transactions, `transact` and its own character
+    Account,
+
+    /// everything an admin can do
+    Admin,
+}
+
+impl ApiKeyScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            ApiKeyScope::Account => "account",
+            ApiKeyScope::Admin => "admin",
+        }
+    }
+
+    /// scopes the code doesn't know (anymore) are ignored
+    fn parse_all(scopes: Vec<String>) -> Vec<Self> {
+        scopes
+            .iter()
+            .filter_map(|scope| match scope.as_str() {
+                "account" => Some(ApiKeyScope::Account),
+                "admin" => Some(ApiKeyScope::Admin),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// One row of the `api_keys` table, without the hash of the key
+#[derive(SimpleObject, Clone)]
+pub struct ApiKey {
+    pub id: i64,
+
+    /// what the key is for, like the name of the partner
+    pub name: String,
+
+    /// the first characters of the key, to tell keys apart
+    pub prefix: String,
+
+    /// id of the character the key belongs to
+    pub owner_id: String,
+
+    pub scopes: Vec<ApiKeyScope>,
+
+    /// operations per hour
+    pub quota: i32,
+
+    /// operations in the current hour
+    pub used: i32,
+
+    pub created_at: DateTime<Utc>,
+
+    /// the key doesn't work anymore since then
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiKeyRow {
+    id: i64,
+    name: String,
+    prefix: String,
+    owner_id: String,
+    scopes: Vec<String>,
+    quota: i32,
+    used: i32,
+    created_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(row: ApiKeyRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            prefix: row.prefix,
+            owner_id: row.owner_id,
+            scopes: ApiKeyScope::parse_all(row.scopes),
+            quota: row.quota,
+            used: row.used,
+            created_at: row.created_at,
+            revoked_at: row.revoked_at,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct NewApiKey {
+    /// what the key is for, like the name of the partner
+    pub name: String,
+
+    /// id of the character the key acts for
+    pub owner_id: String,
+
+    #[graphql(default)]
+    pub scopes: Vec<ApiKeyScope>,
+
+    /// operations per hour, 1000 by default
+    #[graphql(default_with = "DEFAULT_QUOTA")]
+    pub quota: i32,
+}
+
+/// A new key, this is the only time the key itself can be seen
+#[derive(SimpleObject)]
+pub struct IssuedApiKey {
+    /// send it as `X-Api-Key`, only its hash is kept
+    pub key: String,
+
+    pub api_key: ApiKey,
+}
+
+/// An api key that was sent with a request, with its count for the current hour
+pub struct KeyUse {
+    pub id: i64,
+    pub owner_id: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub quota: i32,
+
+    /// operations in the current hour, this one included when it was counted
+    pub used: i32,
+
+    /// when the current hour is over
+    pub resets_at: DateTime<Utc>,
+}
+
+impl KeyUse {
+    pub fn exceeded(&self) -> bool {
+        self.used > self.quota
+    }
+
+    pub fn remaining(&self) -> i32 {
+        (self.quota - self.used).max(0)
+    }
+}
+
+/// id, owner_id, scopes, quota, used and resets_at of a `KeyUse`
+type KeyUseRow = (i64, String, Vec<String>, i32, i32, DateTime<Utc>);
+
+/// The `api_keys` table. There is no memory version, without a database there are no api keys.
+#[derive(Clone)]
+pub struct ApiKeys {
+    pub pool: PgPool,
+}
+
+impl ApiKeys {
+    /// a new random key for `owner_id`, that has to be checked already
+    pub async fn issue(
+        &self,
+        input: NewApiKey,
+        owner_id: String,
+    ) -> Result<IssuedApiKey, ApiError> {
+        if input.quota <= 0 {
+            return Err(ApiError::InvalidInput(format!(
+                "the quota must be positive, not {}",
+                input.quota
+            )));
+        }
+        let key = generate_key();
+        let scopes: Vec<&str> = input.scopes.iter().map(|scope| scope.as_str()).collect();
+        let row: ApiKeyRow = sqlx::query_as(&format!(
+            "INSERT INTO api_keys(name, key_hash, prefix, owner_id, scopes, quota)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING {API_KEY_COLUMNS}"
+        ))
+        .bind(input.name)
+        .bind(hash(&key))
+        .bind(&key[..SHOWN_CHARACTERS])
+        .bind(owner_id)
+        .bind(scopes)
+        .bind(input.quota)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(IssuedApiKey {
+            key,
+            api_key: row.into(),
+        })
+    }
+
+    /// the key stops working right away, revoking it again keeps the first `revoked_at`
+    pub async fn revoke(&self, id: i64) -> Result<ApiKey, ApiError> {
+        let row: Option<ApiKeyRow> = sqlx::query_as(&format!(
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, now())
+             WHERE id = $1
+             RETURNING {API_KEY_COLUMNS}"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(Into::into).ok_or_else(|| ApiError::NotFound {
+            kind: "api key",
+            id: id.to_string(),
+        })
+    }
+
+    /// every key, the revoked ones as well, newest first
+    pub async fn list(&self) -> Result<Vec<ApiKey>, ApiError> {
+        let rows: Vec<ApiKeyRow> = sqlx::query_as(&format!(
+            "SELECT {API_KEY_COLUMNS} FROM api_keys ORDER BY id DESC"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Counts an operation with `key`, `None` when the key doesn't exist or is revoked.
+    /// The quota is per hour since the first operation of that hour. One update does the
+    /// check and the count, so parallel operations can't get past the quota together.
+    pub async fn use_key(&self, key: &str) -> Result<Option<KeyUse>, ApiError> {
+        self.count(Some(hash(key)), None).await
+    }
+
+    /// `use_key` for a key that was found before, like the one of a websocket
+    pub async fn use_id(&self, id: i64) -> Result<Option<KeyUse>, ApiError> {
+        self.count(None, Some(id)).await
+    }
+
+    /// `key` without counting anything, `None` when the key doesn't exist or is revoked
+    pub async fn find(&self, key: &str) -> Result<Option<KeyUse>, ApiError> {
+        let row: Option<KeyUseRow> = sqlx::query_as(
+            "SELECT id, owner_id, scopes, quota,
+                    CASE WHEN window_start <= now() - INTERVAL '1 hour' THEN 0 ELSE used END,
+                    GREATEST(window_start + INTERVAL '1 hour', now())
+             FROM api_keys
+             WHERE key_hash = $1 AND revoked_at IS NULL",
+        )
+        .bind(hash(key))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(KeyUse::from))
+    }
+
+    /// counts an operation with the key that has this hash or this id
+    async fn count(
+        &self,
+        key_hash: Option<String>,
+        id: Option<i64>,
+    ) -> Result<Option<KeyUse>, ApiError> {
+        // every SET sees the old `window_start`, `used` stops at one over the quota
+        // without `quota + 1`, which is out of range for a quota of `i32::MAX`
+        let row: Option<KeyUseRow> = sqlx::query_as(
+            "UPDATE api_keys SET
+                 used = CASE WHEN window_start <= now() - INTERVAL '1 hour' THEN 1
+                             WHEN used > quota THEN used
+                             ELSE used + 1 END,
+                 window_start = CASE WHEN window_start <= now() - INTERVAL '1 hour' THEN now()
+                                     ELSE window_start END
+             WHERE (key_hash = $1 OR id = $2) AND revoked_at IS NULL
+             RETURNING id, owner_id, scopes, quota, used, window_start + INTERVAL '1 hour'",
+        )
+        .bind(key_hash)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(KeyUse::from))
+    }
+}
+
+impl From<KeyUseRow> for KeyUse {
+    fn from((id, owner_id, scopes, quota, used, resets_at): KeyUseRow) -> Self {
+        KeyUse {
+            id,
+            owner_id,
+            scopes: ApiKeyScope::parse_all(scopes),
+            quota,
+            used,
+            resets_at,
+        }
+    }
+}
+
+/// Counts every operation over a websocket for the quota of its api key, like the
+/// subscriptions. Over http the `api_key` middleware counts them, one per request.
+/// The hook runs before the request data is there, so it finds the caller in the
+/// connection data.
+pub struct WebSocketQuota;
+
+impl ExtensionFactory for WebSocketQuota {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(WebSocketQuota)
+    }
+}
+
+impl Extension for WebSocketQuota {
+    fn subscribe<'s>(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        stream: BoxStream<'s, Response>,
+        next: NextSubscribe<'_>,
+    ) -> BoxStream<'s, Response> {
+        let stream = next.run(ctx, stream);
+        let (Some(Caller::ApiKey { id, .. }), Some(api_keys)) =
+            (ctx.data_opt::<Caller>(), ctx.data_opt::<ApiKeys>())
+        else {
+            return stream;
+        };
+        let (id, api_keys) = (*id, api_keys.clone());
+        stream::once(async move {
+            let refused = match api_keys.use_id(id).await {
+                Ok(Some(used)) if !used.exceeded() => return stream,
+                Ok(Some(used)) => ApiError::QuotaExceeded { quota: used.quota },
+                Ok(None) => ApiError::Unauthenticated,
+                Err(e) => e,
+            };
+            let e = refused.extend();
+            let mut error = ServerError::new(e.message, None);
+            error.extensions = e.extensions;
+            stream::once(async move { Response::from_errors(vec![error]) }).boxed()
+        })
+        .flatten()
+        .boxed()
+    }
+}
+
+/// the api keys out of the schema data, they are only there with a database
+pub fn api_keys<'a>(ctx: &Context<'a>) -> Result<&'a ApiKeys, ApiError> {
+    ctx.data_opt::<ApiKeys>()
+        .ok_or(ApiError::Unavailable("api keys need a database"))
+}
+
+fn generate_key() -> String {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{KEY_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// the keys are random enough that a plain sha256 is fine, no need for a slow password hash
+fn hash(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{Data, Request};
+
+    use super::*;
+    use crate::starwars::{
+        credits::{CreditEvents, MemoryCredits},
+        limits::QueryLimits,
+        schema, test_db, StarWarsAPI,
+    };
+
+    async fn issue(api_keys: &ApiKeys, quota: i32) -> IssuedApiKey {
+        let input = NewApiKey {
+            name: "partner".into(),
+            owner_id: "1".into(),
+            scopes: vec![ApiKeyScope::Account],
+            quota,
+        };
+        api_keys.issue(input, "1".into()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn quotas_are_per_hour() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let api_keys = ApiKeys { pool };
+        let issued = issue(&api_keys, 2).await;
+        let used = |used: Option<KeyUse>| {
+            let used = used.unwrap();
+            (used.used, used.remaining(), used.exceeded())
+        };
+
+        assert_eq!(
+            used(api_keys.find(&issued.key).await.unwrap()),
+            (0, 2, false)
+        );
+        assert_eq!(
+            used(api_keys.use_key(&issued.key).await.unwrap()),
+            (1, 1, false)
+        );
+        assert_eq!(
+            used(api_keys.use_id(issued.api_key.id).await.unwrap()),
+            (2, 0, false)
+        );
+        // stays at one over the quota, however often it is tried
+        for _ in 0..3 {
+            assert_eq!(
+                used(api_keys.use_key(&issued.key).await.unwrap()),
+                (3, 0, true)
+            );
+        }
+        assert_eq!(
+            used(api_keys.find(&issued.key).await.unwrap()),
+            (3, 0, true)
+        );
+
+        // an hour later the count starts again
+        sqlx::query("UPDATE api_keys SET window_start = now() - INTERVAL '61 minutes'")
+            .execute(&api_keys.pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            used(api_keys.find(&issued.key).await.unwrap()),
+            (0, 2, false)
+        );
+        let again = api_keys.use_key(&issued.key).await.unwrap().unwrap();
+        assert_eq!((again.used, again.exceeded()), (1, false));
+        assert!(again.resets_at > Utc::now() + chrono::Duration::minutes(59));
+
+        api_keys.revoke(issued.api_key.id).await.unwrap();
+        assert!(api_keys.use_key(&issued.key).await.unwrap().is_none());
+        assert!(api_keys.find(&issued.key).await.unwrap().is_none());
+        assert!(api_keys.use_key("swapi_unknown").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn the_biggest_quota_counts() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let api_keys = ApiKeys { pool };
+        let issued = issue(&api_keys, i32::MAX).await;
+        for used in 1..=2 {
+            let counted = api_keys.use_key(&issued.key).await.unwrap().unwrap();
+            assert_eq!((counted.used, counted.exceeded()), (used, false));
+        }
+    }
+
+    #[tokio::test]
+    async fn every_subscription_counts() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let api_keys = ApiKeys { pool };
+        let issued = issue(&api_keys, 1).await;
+        let schema = schema::build(
+            Arc::new(StarWarsAPI::new()),
+            Arc::new(MemoryCredits::new()),
+            CreditEvents::new(),
+            Some(api_keys.clone()),
+            QueryLimits {
+                max_depth: 15,
+                max_complexity: 5000,
+            },
+        );
+        let subscribe = || {
+            let caller = Caller::ApiKey {
+                id: issued.api_key.id,
+                owner_id: "1".into(),
+                scopes: vec![ApiKeyScope::Account],
+            };
+            let query = r#"subscription { creditsChanged(userId: "1") { credits } }"#;
+            // the websocket puts the caller in the connection data
+            let mut data = Data::default();
+            data.insert(caller);
+            schema.execute_stream_with_session_data(Request::new(query), Arc::new(data))
+        };
+
+        // subscriptions only start when they are polled
+        let mut first = subscribe();
+        let listening = tokio::spawn(async move { first.next().await });
+        let wait = std::time::Duration::from_secs(5);
+        let until = tokio::time::Instant::now() + wait;
+        while api_keys.find(&issued.key).await.unwrap().unwrap().used == 0 {
+            assert!(
+                tokio::time::Instant::now() < until,
+                "the first one never started"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!listening.is_finished(), "the first one is listening");
+        let refused = tokio::time::timeout(wait, subscribe().next()).await;
+        let refused = refused.expect("the second one is refused").unwrap();
+        let code = refused.errors[0].extensions.as_ref().unwrap().get("code");
+        assert_eq!(code.unwrap().to_string(), r#""QUOTA_EXCEEDED""#);
+        let used = api_keys.find(&issued.key).await.unwrap().unwrap();
+        assert_eq!(used.used, 2);
+    }
+}
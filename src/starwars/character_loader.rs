@@ -0,0 +1,18 @@
+use async_graphql::{dataloader::*, FieldError, ResultExt};
+use std::collections::HashMap;
+
+use super::{data::APICharacter, Repository};
+
+pub(crate) struct CharacterLoader {
+    pub api: Repository,
+}
+
+/// Loader for the characters behind friend indices, one lookup for all friends of a level
+impl Loader<usize> for CharacterLoader {
+    type Value = APICharacter;
+    type Error = FieldError;
+
+    async fn load(&self, keys: &[usize]) -> Result<HashMap<usize, Self::Value>, Self::Error> {
+        self.api.get_characters_by_idx(keys).await.extend()
+    }
+}
@@ -0,0 +1,891 @@
+use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
+
+use async_graphql::{
+    connection::{query, Connection, Edge},
+    ErrorExtensions, Result,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::{Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::{broadcast, Mutex};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+use super::{
+    errors::ApiError,
+    models::{CreditHistoryEntry, LedgerEntry, Transfer},
+    pagination::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+};
+
+/// how many transfers a slow subscriber can fall behind before it starts missing some
+const EVENT_CAPACITY: usize = 256;
+
+/// the accounts of the credits migration, droids can't have credits as they have no rights
+const SEED_ACCOUNTS: [(&str, i64); 5] = [
+    ("1", 100), // luke
+    ("2", 100), // vader
+    ("3", 100), // han solo
+    ("4", 100), // Leia
+    ("5", 100), // WilHuff
+];
+
+/// Where the balances and the ledger of transfers live.
+/// `PgCredits` uses the `credits` and `transactions` tables,
+/// `MemoryCredits` keeps them in the process for when there is no database.
+#[async_trait]
+pub trait CreditsStore: Send + Sync {
+    /// the balances of the `user_ids` that have an account
+    async fn balances(&self, user_ids: &[String]) -> Result<HashMap<String, i64>, ApiError>;
+
+    /// every account and the whole ledger at one moment, what a snapshot keeps of the credits
+    async fn export(&self) -> Result<CreditsExport, ApiError>;
+
+    /// Moves `amount` credits from `from_user_id` to `to_user_id` and writes it to the ledger.
+    /// Either both balances and the ledger change or nothing does.
+    async fn transfer(
+        &self,
+        from_user_id: String,
+        to_user_id: String,
+        amount: usize,
+        memo: Option<String>,
+    ) -> Result<Transfer, ApiError>;
+
+    /// at most `limit` ledger entries of `user_id` with an id below `before`, newest first
+    async fn ledger(
+        &self,
+        user_id: &str,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<LedgerEntry>, ApiError>;
+}
+
+/// The credits store the resolvers get out of the schema data
+pub type Credits = Arc<dyn CreditsStore>;
+
+/// Everything in a credits store, `MemoryCredits::with_accounts` starts from it again
+pub struct CreditsExport {
+    /// every account with its balance, ordered by user id
+    pub accounts: Vec<(String, i64)>,
+    /// every entry, ordered by id
+    pub ledger: Vec<LedgerEntry>,
+    /// the id the next transfer gets, above every id in `ledger`
+    pub next_id: i64,
+}
+
+/// the postgres channel `PgCredits` notifies after every transfer
+const TRANSFER_CHANNEL: &str = "credit_transfers";
+
+/// Fan-out of committed transfers to the GraphQL subscriptions,
+/// every subscription gets its own receiver.
+/// Without a database `transact` publishes here after the transfer. With a database
+/// the transfer comes through `NOTIFY`, so subscribers see the transfers of every replica.
+#[derive(Clone)]
+pub struct CreditEvents {
+    sender: broadcast::Sender<Transfer>,
+
+    /// the transfers come from the database, `publish` does nothing
+    listening: bool,
+}
+
+impl Default for CreditEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What `PgCredits` sends with `NOTIFY`. A notification can be at most 8000 bytes,
+/// so the listener gets the rest of the transfer out of the ledger.
+#[derive(Serialize, Deserialize)]
+struct TransferNotice {
+    id: i64,
+    from_balance: i64,
+    to_balance: i64,
+}
+
+impl CreditEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            sender,
+            listening: false,
+        }
+    }
+
+    /// Events out of the notifications of `PgCredits`, from this replica and the others.
+    /// Listens before it returns, so no transfer after this gets lost.
+    pub async fn listen(pool: PgPool) -> Result<Self, ApiError> {
+        let mut listener = PgListener::connect_with(&pool).await?;
+        listener.listen(TRANSFER_CHANNEL).await?;
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        let events = Self {
+            sender,
+            listening: true,
+        };
+
+        let sender = events.sender.clone();
+        tokio::spawn(async move {
+            loop {
+                // `recv` connects again by itself, notifications in between are lost
+                let notification = match listener.recv().await {
+                    Ok(notification) => notification,
+                    Err(e) => {
+                        tracing::error!("listening for transfers failed: {e}");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                match received_transfer(&pool, notification.payload()).await {
+                    Ok(transfer) => {
+                        let _ = sender.send(transfer);
+                    }
+                    Err(e) => tracing::error!("can't read the notified transfer: {e}"),
+                }
+            }
+        });
+        Ok(events)
+    }
+
+    pub fn publish(&self, transfer: Transfer) {
+        if self.listening {
+            return;
+        }
+        // an error only means nobody is listening right now
+        let _ = self.sender.send(transfer);
+    }
+
+    /// Stream of every transfer from now on. A subscriber that falls more than
+    /// `EVENT_CAPACITY` transfers behind gets an `EVENTS_MISSED` error and continues after it.
+    pub fn subscribe(&self) -> impl Stream<Item = Result<Transfer, ApiError>> {
+        BroadcastStream::new(self.sender.subscribe()).map(|event| {
+            event.map_err(|BroadcastStreamRecvError::Lagged(missed)| {
+                tracing::warn!("a subscriber missed {missed} transfers");
+                ApiError::EventsMissed(missed)
+            })
+        })
+    }
+}
+
+/// the transfer of a notification of `PgCredits`, with its ledger entry
+async fn received_transfer(pool: &PgPool, payload: &str) -> Result<Transfer, Box<dyn Error>> {
+    let notice: TransferNotice = serde_json::from_str(payload)?;
+    let transaction: LedgerEntry = sqlx::query_as(
+        "SELECT id, from_user_id, to_user_id, amount, memo, created_at FROM transactions
+         WHERE id = $1",
+    )
+    .bind(notice.id)
+    .fetch_one(pool)
+    .await?;
+    Ok(Transfer {
+        from_user_id: transaction.from_user_id.clone(),
+        to_user_id: transaction.to_user_id.clone(),
+        amount: transaction.amount,
+        from_balance: notice.from_balance,
+        to_balance: notice.to_balance,
+        transaction,
+    })
+}
+
+/// checks that don't depend on where the credits are stored, returns the amount as BIGINT
+fn validate_transfer(from_user_id: &str, to_user_id: &str, amount: usize) -> Result<i64, ApiError> {
+    let amount = match i64::try_from(amount) {
+        Ok(amount) if amount > 0 => amount,
+        _ => return Err(ApiError::InvalidAmount(amount)),
+    };
+    if from_user_id == to_user_id {
+        return Err(ApiError::SameAccount(from_user_id.into()));
+    }
+    Ok(amount)
+}
+
+/// the balance of `to_user_id` after it gets `amount`, a balance can't go past `i64::MAX`
+fn receive(to_balance: i64, amount: i64) -> Result<i64, ApiError> {
+    to_balance
+        .checked_add(amount)
+        .ok_or(ApiError::InvalidAmount(amount.unsigned_abs() as usize))
+}
+
+pub struct PgCredits {
+    pub pool: PgPool,
+}
+
+#[async_trait]
+impl CreditsStore for PgCredits {
+    async fn balances(&self, user_ids: &[String]) -> Result<HashMap<String, i64>, ApiError> {
+        Ok(
+            sqlx::query_as("SELECT user_id, amount FROM credits WHERE user_id = ANY($1)")
+                .bind(user_ids)
+                .fetch(&self.pool)
+                .map_ok(|result: (String, i64)| (result.0, result.1))
+                .try_collect()
+                .await?,
+        )
+    }
+
+    async fn export(&self) -> Result<CreditsExport, ApiError> {
+        // one snapshot, so the balances are the sum of the ledger
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+        let accounts =
+            sqlx::query_as("SELECT user_id, COALESCE(amount, 0) FROM credits ORDER BY user_id")
+                .fetch_all(&mut *tx)
+                .await?;
+        let ledger = sqlx::query_as(
+            "SELECT id, from_user_id, to_user_id, amount, memo, created_at FROM transactions
+             ORDER BY id",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        // the sequence doesn't roll back, so a failed transfer can leave a gap before it
+        let next_id = sqlx::query_scalar(
+            "SELECT CASE WHEN is_called THEN last_value + 1 ELSE last_value END
+             FROM transactions_id_seq",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(CreditsExport {
+            accounts,
+            ledger,
+            next_id,
+        })
+    }
+
+    /// Both rows are locked with `FOR UPDATE` inside one transaction, so two transfers
+    /// touching the same account are serialized and the balance can never go negative.
+    /// The rows are always locked in the same order (by user id) to avoid deadlocks
+    /// between A -> B and B -> A transfers.
+    /// The transfer is written to the `transactions` ledger in the same transaction.
+    async fn transfer(
+        &self,
+        from_user_id: String,
+        to_user_id: String,
+        amount: usize,
+        memo: Option<String>,
+    ) -> Result<Transfer, ApiError> {
+        let amount = validate_transfer(&from_user_id, &to_user_id, amount)?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let accounts: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT user_id, COALESCE(amount, 0) FROM credits
+             WHERE user_id = ANY($1)
+             ORDER BY user_id
+             FOR UPDATE",
+        )
+        .bind([&from_user_id, &to_user_id])
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let balance_of = |user_id: &str| {
+            accounts
+                .iter()
+                .find(|(id, _)| id == user_id)
+                .map(|&(_, balance)| balance)
+        };
+        let from_balance = balance_of(&from_user_id)
+            .ok_or_else(|| ApiError::UnknownAccount(from_user_id.clone()))?;
+        let to_balance =
+            balance_of(&to_user_id).ok_or_else(|| ApiError::UnknownAccount(to_user_id.clone()))?;
+
+        if from_balance < amount {
+            return Err(ApiError::InsufficientFunds {
+                user_id: from_user_id,
+                balance: from_balance,
+                requested: amount,
+            });
+        }
+        let (from_balance, to_balance) = (from_balance - amount, receive(to_balance, amount)?);
+
+        let update = "UPDATE credits SET amount = $2 WHERE user_id = $1";
+        sqlx::query(update)
+            .bind(&from_user_id)
+            .bind(from_balance)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(update)
+            .bind(&to_user_id)
+            .bind(to_balance)
+            .execute(&mut *tx)
+            .await?;
+
+        let transaction: LedgerEntry = sqlx::query_as(
+            "INSERT INTO transactions(from_user_id, to_user_id, amount, memo)
+             VALUES ($1, $2, $3, $4)
+             RETURNING id, from_user_id, to_user_id, amount, memo, created_at",
+        )
+        .bind(&from_user_id)
+        .bind(&to_user_id)
+        .bind(amount)
+        .bind(memo)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // postgres only delivers it when the transaction commits, to every `CreditEvents::listen`
+        let notice = TransferNotice {
+            id: transaction.id,
+            from_balance,
+            to_balance,
+        };
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(TRANSFER_CHANNEL)
+            .bind(serde_json::to_string(&notice).expect("a notice is valid json"))
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Transfer {
+            from_user_id,
+            to_user_id,
+            amount,
+            from_balance,
+            to_balance,
+            transaction,
+        })
+    }
+
+    async fn ledger(
+        &self,
+        user_id: &str,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<LedgerEntry>, ApiError> {
+        Ok(sqlx::query_as(
+            "SELECT id, from_user_id, to_user_id, amount, memo, created_at FROM transactions
+             WHERE (from_user_id = $1 OR to_user_id = $1)
+               AND ($2::BIGINT IS NULL OR id < $2)
+             ORDER BY id DESC
+             LIMIT $3",
+        )
+        .bind(user_id)
+        .bind(before)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+/// Credits without a database, every restart starts again from the seeded accounts
+/// or the accounts of the dataset.
+/// One lock over the balances and the ledger makes every transfer atomic.
+pub struct MemoryCredits {
+    state: Mutex<MemoryCreditsState>,
+}
+
+struct MemoryCreditsState {
+    balances: HashMap<String, i64>,
+    /// ordered by id
+    ledger: Vec<LedgerEntry>,
+    /// the id of the next transfer
+    next_id: i64,
+}
+
+impl Default for MemoryCredits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryCredits {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MemoryCreditsState {
+                balances: SEED_ACCOUNTS
+                    .iter()
+                    .map(|&(user_id, amount)| (user_id.to_string(), amount))
+                    .collect(),
+                ledger: Vec::new(),
+                next_id: 1,
+            }),
+        }
+    }
+
+    /// Starts with these accounts instead of the seeded ones, and with the ledger of
+    /// a snapshot. `ledger` is ordered by id and `next_id` is above every id in it.
+    pub fn with_accounts(
+        accounts: impl IntoIterator<Item = (String, i64)>,
+        ledger: Vec<LedgerEntry>,
+        next_id: i64,
+    ) -> Self {
+        Self {
+            state: Mutex::new(MemoryCreditsState {
+                balances: accounts.into_iter().collect(),
+                ledger,
+                next_id,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl CreditsStore for MemoryCredits {
+    async fn balances(&self, user_ids: &[String]) -> Result<HashMap<String, i64>, ApiError> {
+        let state = self.state.lock().await;
+        Ok(user_ids
+            .iter()
+            .filter_map(|id| Some((id.clone(), *state.balances.get(id)?)))
+            .collect())
+    }
+
+    async fn export(&self) -> Result<CreditsExport, ApiError> {
+        let state = self.state.lock().await;
+        let mut accounts: Vec<(String, i64)> = state
+            .balances
+            .iter()
+            .map(|(user_id, &balance)| (user_id.clone(), balance))
+            .collect();
+        accounts.sort();
+        Ok(CreditsExport {
+            accounts,
+            ledger: state.ledger.clone(),
+            next_id: state.next_id,
+        })
+    }
+
+    async fn transfer(
+        &self,
+        from_user_id: String,
+        to_user_id: String,
+        amount: usize,
+        memo: Option<String>,
+    ) -> Result<Transfer, ApiError> {
+        let amount = validate_transfer(&from_user_id, &to_user_id, amount)?;
+
+        let mut state = self.state.lock().await;
+        let from_balance = *state
+            .balances
+            .get(&from_user_id)
+            .ok_or_else(|| ApiError::UnknownAccount(from_user_id.clone()))?;
+        let to_balance = *state
+            .balances
+            .get(&to_user_id)
+            .ok_or_else(|| ApiError::UnknownAccount(to_user_id.clone()))?;
+
+        if from_balance < amount {
+            return Err(ApiError::InsufficientFunds {
+                user_id: from_user_id,
+                balance: from_balance,
+                requested: amount,
+            });
+        }
+        let (from_balance, to_balance) = (from_balance - amount, receive(to_balance, amount)?);
+
+        state.balances.insert(from_user_id.clone(), from_balance);
+        state.balances.insert(to_user_id.clone(), to_balance);
+        let transaction = LedgerEntry {
+            id: state.next_id,
+            from_user_id: from_user_id.clone(),
+            to_user_id: to_user_id.clone(),
+            amount,
+            memo,
+            created_at: Utc::now(),
+        };
+        state.next_id += 1;
+        state.ledger.push(transaction.clone());
+
+        Ok(Transfer {
+            from_user_id,
+            to_user_id,
+            amount,
+            from_balance,
+            to_balance,
+            transaction,
+        })
+    }
+
+    async fn ledger(
+        &self,
+        user_id: &str,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<LedgerEntry>, ApiError> {
+        let state = self.state.lock().await;
+        Ok(state
+            .ledger
+            .iter()
+            .rev()
+            .filter(|entry| before.is_none_or(|before| entry.id < before))
+            .filter(|entry| entry.from_user_id == user_id || entry.to_user_id == user_id)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Page through the ledger entries of `user_id`, newest first.
+/// The cursor is the id of the ledger entry, so pages stay stable when new transfers come in.
+pub async fn history(
+    store: &Credits,
+    user_id: String,
+    first: Option<i32>,
+    after: Option<String>,
+) -> Result<Connection<i64, CreditHistoryEntry>> {
+    query(
+        after,
+        None,
+        first,
+        None,
+        |after: Option<i64>, _, first, _| async move {
+            let first = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+            // fetch one extra entry to know if there is a next page
+            let mut entries = store
+                .ledger(&user_id, after, first + 1)
+                .await
+                .map_err(|e| e.extend())?;
+
+            let has_next_page = entries.len() > first;
+            entries.truncate(first);
+
+            let mut connection = Connection::new(after.is_some(), has_next_page);
+            connection.edges.extend(entries.into_iter().map(|entry| {
+                Edge::new(
+                    entry.id,
+                    CreditHistoryEntry {
+                        user_id: user_id.clone(),
+                        entry,
+                    },
+                )
+            }));
+            Ok::<_, async_graphql::Error>(connection)
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starwars::test_db;
+
+    async fn balance(store: &dyn CreditsStore, user_id: &str) -> i64 {
+        store.balances(&[user_id.to_string()]).await.unwrap()[user_id]
+    }
+
+    #[tokio::test]
+    async fn pg_transfer_moves_credits() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let credits = PgCredits { pool };
+
+        let transfer = credits
+            .transfer("1".into(), "2".into(), 30, None)
+            .await
+            .unwrap();
+        assert_eq!((transfer.from_balance, transfer.to_balance), (70, 130));
+        assert_eq!(balance(&credits, "1").await, 70);
+        assert_eq!(balance(&credits, "2").await, 130);
+    }
+
+    #[tokio::test]
+    async fn pg_export_has_the_whole_ledger() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let credits = PgCredits { pool };
+        assert_eq!(credits.export().await.unwrap().next_id, 1);
+
+        for amount in [10, 20] {
+            credits
+                .transfer("1".into(), "2".into(), amount, None)
+                .await
+                .unwrap();
+        }
+        let export = credits.export().await.unwrap();
+        let amounts: Vec<(i64, i64)> = export.ledger.iter().map(|e| (e.id, e.amount)).collect();
+        assert_eq!(amounts, [(1, 10), (2, 20)]);
+        assert_eq!(export.next_id, 3);
+        assert_eq!(export.accounts[0], ("1".into(), 70));
+    }
+
+    #[tokio::test]
+    async fn pg_transfer_rejects_without_changing_balances() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let credits = PgCredits { pool };
+
+        let err = |result: Result<Transfer, ApiError>| result.err().unwrap().code();
+        assert_eq!(
+            err(credits.transfer("1".into(), "2".into(), 101, None).await),
+            "INSUFFICIENT_FUNDS"
+        );
+        assert_eq!(
+            err(credits.transfer("1".into(), "nobody".into(), 1, None).await),
+            "UNKNOWN_ACCOUNT"
+        );
+        assert_eq!(
+            err(credits.transfer("1".into(), "1".into(), 1, None).await),
+            "SAME_ACCOUNT"
+        );
+        assert_eq!(
+            err(credits.transfer("1".into(), "2".into(), 0, None).await),
+            "INVALID_AMOUNT"
+        );
+        assert_eq!(balance(&credits, "1").await, 100);
+        assert_eq!(balance(&credits, "2").await, 100);
+
+        // a dataset can load any balance, one more credit doesn't fit
+        sqlx::query("UPDATE credits SET amount = $1 WHERE user_id = '2'")
+            .bind(i64::MAX)
+            .execute(&credits.pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            err(credits.transfer("1".into(), "2".into(), 1, None).await),
+            "INVALID_AMOUNT"
+        );
+        assert_eq!(balance(&credits, "1").await, 100);
+        assert_eq!(balance(&credits, "2").await, i64::MAX);
+        assert!(credits.ledger("1", None, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pg_parallel_transfers_never_overdraw() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let credits = Arc::new(PgCredits { pool });
+
+        // 100 credits, so only 10 of these can go through
+        let transfers = (0..20).map(|i| {
+            let credits = credits.clone();
+            let to = if i % 2 == 0 { "2" } else { "3" };
+            tokio::spawn(async move { credits.transfer("1".into(), to.into(), 10, None).await })
+        });
+        let mut succeeded = 0;
+        for transfer in transfers {
+            if transfer.await.unwrap().is_ok() {
+                succeeded += 1;
+            }
+        }
+        assert_eq!(succeeded, 10);
+        assert_eq!(balance(&*credits, "1").await, 0);
+        assert_eq!(
+            balance(&*credits, "2").await + balance(&*credits, "3").await,
+            300
+        );
+    }
+
+    fn ids(page: &Connection<i64, CreditHistoryEntry>) -> Vec<i64> {
+        page.edges.iter().map(|edge| edge.node.entry.id).collect()
+    }
+
+    /// five transfers of luke and one between han and leia, ledger ids 1 to 6
+    async fn ledger_of_luke(store: &Credits) {
+        for (from, to) in [
+            ("1", "2"),
+            ("2", "1"),
+            ("3", "4"),
+            ("1", "3"),
+            ("1", "4"),
+            ("5", "1"),
+        ] {
+            store
+                .transfer(from.into(), to.into(), 1, None)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn check_history(store: Credits) {
+        ledger_of_luke(&store).await;
+
+        let page = history(&store, "1".into(), Some(2), None).await.unwrap();
+        assert_eq!(ids(&page), [6, 5]);
+        assert!(page.has_next_page && !page.has_previous_page);
+
+        let page = history(&store, "1".into(), Some(2), Some("5".into()))
+            .await
+            .unwrap();
+        assert_eq!(ids(&page), [4, 2]);
+        assert!(page.has_next_page && page.has_previous_page);
+
+        let page = history(&store, "1".into(), Some(2), Some("2".into()))
+            .await
+            .unwrap();
+        assert_eq!(ids(&page), [1]);
+        assert!(!page.has_next_page);
+
+        let page = history(&store, "1".into(), None, None).await.unwrap();
+        assert_eq!(ids(&page), [6, 5, 4, 2, 1]);
+
+        // a page is never bigger than MAX_PAGE_SIZE, a negative one is an error
+        let page = history(&store, "1".into(), Some(1000), None).await.unwrap();
+        assert_eq!(page.edges.len(), 5);
+        assert!(history(&store, "1".into(), Some(-1), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_history_pages_newest_first() {
+        check_history(Arc::new(MemoryCredits::new())).await;
+    }
+
+    #[tokio::test]
+    async fn pg_history_pages_newest_first() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        check_history(Arc::new(PgCredits { pool })).await;
+    }
+
+    fn transfer_with_id(id: i64) -> Transfer {
+        Transfer {
+            from_user_id: "1".into(),
+            to_user_id: "2".into(),
+            amount: 1,
+            from_balance: 99,
+            to_balance: 101,
+            transaction: LedgerEntry {
+                id,
+                from_user_id: "1".into(),
+                to_user_id: "2".into(),
+                amount: 1,
+                memo: None,
+                created_at: Utc::now(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_subscribers_hear_what_they_missed() {
+        let events = CreditEvents::new();
+        let mut subscription = Box::pin(events.subscribe());
+        for id in 0..EVENT_CAPACITY as i64 + 3 {
+            events.publish(transfer_with_id(id));
+        }
+
+        match subscription.next().await {
+            Some(Err(ApiError::EventsMissed(3))) => {}
+            _ => panic!("expected EVENTS_MISSED for 3 transfers"),
+        }
+        let next = subscription.next().await.unwrap().unwrap();
+        assert_eq!(next.transaction.id, 3);
+    }
+
+    #[tokio::test]
+    async fn pg_transfers_reach_every_listener() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        // two replicas on the same database
+        let here = CreditEvents::listen(pool.clone()).await.unwrap();
+        let there = CreditEvents::listen(pool.clone()).await.unwrap();
+        let mut subscriptions = [Box::pin(here.subscribe()), Box::pin(there.subscribe())];
+
+        let credits = PgCredits { pool };
+        let transfer = credits
+            .transfer("1".into(), "2".into(), 5, Some("for the droids".into()))
+            .await
+            .unwrap();
+        // like `transact` does, the notification is what counts
+        here.publish(transfer.clone());
+
+        for subscription in &mut subscriptions {
+            let received = tokio::time::timeout(Duration::from_secs(5), subscription.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert_eq!(received.transaction.id, transfer.transaction.id);
+            assert_eq!(received.transaction.memo.as_deref(), Some("for the droids"));
+            assert_eq!((received.from_balance, received.to_balance), (95, 105));
+
+            let again = tokio::time::timeout(Duration::from_millis(200), subscription.next());
+            assert!(again.await.is_err(), "the transfer came twice");
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_transfer_moves_credits_and_writes_the_ledger() {
+        let credits =
+            MemoryCredits::with_accounts([("1".into(), 50), ("2".into(), 0)], Vec::new(), 1);
+
+        let transfer = credits
+            .transfer("1".into(), "2".into(), 20, Some("rent".into()))
+            .await
+            .unwrap();
+        assert_eq!((transfer.from_balance, transfer.to_balance), (30, 20));
+        assert_eq!(transfer.transaction.id, 1);
+        assert_eq!(balance(&credits, "1").await, 30);
+        assert_eq!(balance(&credits, "2").await, 20);
+
+        let ledger = credits.ledger("2", None, 10).await.unwrap();
+        assert_eq!(ledger.len(), 1);
+        assert_eq!(ledger[0].memo.as_deref(), Some("rent"));
+    }
+
+    #[tokio::test]
+    async fn memory_transfer_rejects_without_changing_anything() {
+        let credits = MemoryCredits::new();
+
+        let err = |result: Result<Transfer, ApiError>| result.err().unwrap().code();
+        assert_eq!(
+            err(credits.transfer("1".into(), "2".into(), 101, None).await),
+            "INSUFFICIENT_FUNDS"
+        );
+        assert_eq!(
+            err(credits.transfer("1".into(), "nobody".into(), 1, None).await),
+            "UNKNOWN_ACCOUNT"
+        );
+        assert_eq!(
+            err(credits.transfer("nobody".into(), "1".into(), 1, None).await),
+            "UNKNOWN_ACCOUNT"
+        );
+        assert_eq!(
+            err(credits.transfer("1".into(), "1".into(), 1, None).await),
+            "SAME_ACCOUNT"
+        );
+        assert_eq!(
+            err(credits.transfer("1".into(), "2".into(), 0, None).await),
+            "INVALID_AMOUNT"
+        );
+        assert_eq!(
+            err(credits
+                .transfer("1".into(), "2".into(), usize::MAX, None)
+                .await),
+            "INVALID_AMOUNT"
+        );
+        assert_eq!(balance(&credits, "1").await, 100);
+        assert_eq!(balance(&credits, "2").await, 100);
+        assert!(credits.ledger("1", None, 10).await.unwrap().is_empty());
+
+        // a dataset can load any balance, one more credit doesn't fit
+        credits
+            .state
+            .lock()
+            .await
+            .balances
+            .insert("2".into(), i64::MAX);
+        assert_eq!(
+            err(credits.transfer("1".into(), "2".into(), 1, None).await),
+            "INVALID_AMOUNT"
+        );
+        assert_eq!(balance(&credits, "1").await, 100);
+        assert_eq!(balance(&credits, "2").await, i64::MAX);
+        assert!(credits.ledger("1", None, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn memory_parallel_transfers_never_overdraw() {
+        let credits = Arc::new(MemoryCredits::new());
+
+        let transfers = (0..20).map(|i| {
+            let credits = credits.clone();
+            let to = if i % 2 == 0 { "2" } else { "3" };
+            tokio::spawn(async move { credits.transfer("1".into(), to.into(), 10, None).await })
+        });
+        let mut succeeded = 0;
+        for transfer in transfers {
+            if transfer.await.unwrap().is_ok() {
+                succeeded += 1;
+            }
+        }
+        assert_eq!(succeeded, 10);
+        assert_eq!(balance(&*credits, "1").await, 0);
+        assert_eq!(credits.ledger("1", None, 100).await.unwrap().len(), 10);
+    }
+}
@@ -1,8 +1,10 @@
-use async_graphql::{dataloader::*, FieldError};
-use futures::TryStreamExt;
-use std::{collections::HashMap, sync::Arc};
+use async_graphql::{dataloader::*, FieldError, ResultExt};
+use std::collections::HashMap;
+
+use super::credits::Credits;
+
 pub struct CreditsDataLoader {
-    pub pool: sqlx::PgPool,
+    pub store: Credits,
 }
 
 /// Loader for loading just the credits
@@ -11,13 +13,6 @@ impl Loader<String> for CreditsDataLoader {
     type Error = FieldError;
 
     async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
-        Ok(
-            sqlx::query_as("SELECT user_id, amount FROM credits WHERE user_id = ANY($1)")
-                .bind(keys)
-                .fetch(&self.pool)
-                .map_ok(|result: (String, i64)| (result.0, result.1))
-                .try_collect()
-                .await?,
-        )
+        self.store.balances(keys).await.extend()
     }
 }
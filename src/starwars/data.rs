@@ -1,9 +1,29 @@
-use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use std::{
+    cmp,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 use tokio::sync::Mutex;
 
-use crate::starwars::models::Episode;
-use async_graphql::*;
-use slab::Slab;
+use crate::starwars::{
+    dataset::{indices, CharacterKind, Dataset, DatasetError, Indexed},
+    errors::ApiError,
+    models::{
+        CharacterFilter, CharacterOrder, CharacterOrderField, NewDroid, NewFilm, NewHuman,
+        NewPlanet, NewStarShip, OrderDirection, UpdateCharacter, UpdatePlanet, UpdateStarShip,
+    },
+    pagination::{Page, PageRequest},
+    repository::{
+        episode_taken, validate_film, validate_length, validate_name, validate_update,
+        StarWarsRepository,
+    },
+    table::Table,
+};
 
 // Dit kunnen we eigenlijk zien als een soort database connectie
 // of een connectie naar een andera api endpoint
@@ -31,14 +51,20 @@ pub(crate) struct APICharacter {
     /// maps in StarWars.characters
     pub friends: Vec<usize>,
 
-    /// all the episodes this character appeared in
-    pub appears_in: Vec<Episode>,
+    /// the films this character is in, sorted on index
+    pub films: Vec<usize>,
 
     /// Optional Home planet of  a Human
     pub home_planet: Option<usize>,
 
-    /// the starship of a Human
-    pub star_ship: Option<usize>,
+    /// the starships a Human flies, sorted on index
+    pub star_ships: Vec<usize>,
+
+    /// the vehicles a Human drives, sorted on index
+    pub vehicles: Vec<usize>,
+
+    /// the species of the character, `None` if we don't know
+    pub species: Option<usize>,
 
     /// primary function of droid
     pub primary_function: Option<String>,
@@ -50,6 +76,7 @@ pub(crate) struct APICharacter {
 // wil gewoon es met een builder pattern werken
 // heb mezelf gwn meer werk gegeven eigenlijk
 
+#[allow(clippy::wrong_self_convention)]
 impl APICharacter {
     pub fn build(id: impl Into<String>, name: impl Into<String>) -> Self {
         APICharacter {
@@ -57,9 +84,11 @@ impl APICharacter {
             id: id.into(),
             name: name.into(),
             friends: vec![],
-            appears_in: vec![],
+            films: vec![],
             home_planet: None,
-            star_ship: None,
+            star_ships: Vec::new(),
+            vehicles: Vec::new(),
+            species: None,
             primary_function: None,
             mass: 0,
         }
@@ -80,8 +109,10 @@ impl APICharacter {
         self
     }
 
-    pub fn appeared_in(mut self, episodes: Vec<Episode>) -> Self {
-        self.appears_in = episodes;
+    pub fn in_films(mut self, mut films: Vec<usize>) -> Self {
+        films.sort_unstable();
+        films.dedup();
+        self.films = films;
         self
     }
 
@@ -91,7 +122,17 @@ impl APICharacter {
     }
 
     pub fn star_ship(mut self, starship: usize) -> Self {
-        self.star_ship = Some(starship);
+        add_sorted(&mut self.star_ships, starship);
+        self
+    }
+
+    pub fn vehicle(mut self, vehicle: usize) -> Self {
+        add_sorted(&mut self.vehicles, vehicle);
+        self
+    }
+
+    pub fn species(mut self, species: usize) -> Self {
+        self.species = Some(species);
         self
     }
 
@@ -118,6 +159,47 @@ pub struct APIStarShip {
     pub length: f64,
 }
 
+/// a vehicle that can't leave the atmosphere, like a speeder bike
+#[derive(Clone)]
+pub struct APIVehicle {
+    /// id of vehicle
+    pub id: String,
+
+    pub name: String,
+
+    pub model: String,
+
+    /// comma separated, like on swapi
+    pub manufacturer: String,
+
+    /// `None` when nobody knows what it costs
+    pub cost_in_credits: Option<usize>,
+
+    /// people needed to drive it
+    pub crew: usize,
+
+    pub passengers: usize,
+}
+
+#[derive(Clone)]
+pub struct APISpecies {
+    /// id of species
+    pub id: String,
+
+    pub name: String,
+
+    /// mammal, artificial, ...
+    pub classification: String,
+
+    pub language: String,
+
+    /// in standard years, `None` when it is indefinite (droids) or unknown
+    pub average_lifespan: Option<usize>,
+
+    /// index of the planet the species comes from
+    pub homeworld: Option<usize>,
+}
+
 #[derive(Clone)]
 pub struct APIPlanet {
     /// id of planet
@@ -143,19 +225,84 @@ pub struct APIPlanet {
     pub orbital_period: usize,
 }
 
+#[derive(Clone)]
+pub struct APIFilm {
+    /// id of film
+    pub id: String,
+
+    /// number of the film in the saga, A New Hope is episode 4
+    pub episode_id: usize,
+
+    pub title: String,
+
+    pub release_date: NaiveDate,
+
+    pub director: String,
+
+    /// comma separated, like on swapi
+    pub producer: String,
+
+    pub opening_crawl: String,
+
+    /// indices of the starships in this film, sorted
+    pub starships: Vec<usize>,
+
+    /// indices of the planets in this film, sorted
+    pub planets: Vec<usize>,
+
+    /// indices of the species in this film, sorted
+    pub species: Vec<usize>,
+
+    /// indices of the vehicles in this film, sorted
+    pub vehicles: Vec<usize>,
+}
+
+// the opening crawls of the original trilogy
+const NEW_HOPE_CRAWL: &str = "It is a period of civil war. Rebel spaceships, striking from a \
+hidden base, have won their first victory against the evil Galactic Empire.
+
+During the battle, Rebel spies managed to steal secret plans to the Empire's ultimate weapon, \
+the DEATH STAR, an armored space station with enough power to destroy an entire planet.
+
+Pursued by the Empire's sinister agents, Princess Leia races home aboard her starship, \
+custodian of the stolen plans that can save her people and restore freedom to the galaxy....";
+
+const EMPIRE_CRAWL: &str = "It is a dark time for the Rebellion. Although the Death Star has \
+been destroyed, Imperial troops have driven the Rebel forces from their hidden base and pursued \
+them across the galaxy.
+
+Evading the dreaded Imperial Starfleet, a group of freedom fighters led by Luke Skywalker has \
+established a new secret base on the remote ice world of Hoth.
+
+The evil lord Darth Vader, obsessed with finding young Skywalker, has dispatched thousands of \
+remote probes into the far reaches of space....";
+
+const JEDI_CRAWL: &str = "Luke Skywalker has returned to his home planet of Tatooine in an \
+attempt to rescue his friend Han Solo from the clutches of the vile gangster Jabba the Hutt.
+
+Little does Luke know that the GALACTIC EMPIRE has secretly begun construction on a new armored \
+space station even more powerful than the first dreaded Death Star.
+
+When completed, this ultimate weapon will spell certain doom for the small band of rebels \
+struggling to restore freedom to the galaxy...";
+
 pub struct StarWarsAPI {
-    // id counters for insertion
-    char_id_counter: usize,
-    starship_id_counter: usize,
-    planet_id_counter: usize,
+    // id counters for insertion, they hold the next free id
+    char_id_counter: AtomicUsize,
+    starship_id_counter: AtomicUsize,
+    planet_id_counter: AtomicUsize,
+    film_id_counter: AtomicUsize,
 
-    luke_idx: usize,
-    r2d2_idx: usize,
+    /// the hero of every episode, `None` is the hero of the entire saga
+    heroes: Arc<Mutex<HashMap<Option<usize>, usize>>>,
 
     // seperate locks for more performance haha
-    characters: Arc<Mutex<Slab<APICharacter>>>,
-    starships: Arc<Mutex<Slab<APIStarShip>>>,
-    planets: Arc<Mutex<Slab<APIPlanet>>>,
+    characters: Arc<Mutex<Table<APICharacter>>>,
+    starships: Arc<Mutex<Table<APIStarShip>>>,
+    planets: Arc<Mutex<Table<APIPlanet>>>,
+    films: Arc<Mutex<Table<APIFilm>>>,
+    species: Arc<Mutex<Table<APISpecies>>>,
+    vehicles: Arc<Mutex<Table<APIVehicle>>>,
 }
 
 impl Default for StarWarsAPI {
@@ -166,7 +313,7 @@ impl Default for StarWarsAPI {
 
 impl StarWarsAPI {
     pub fn new() -> Self {
-        let mut starships = Slab::with_capacity(7);
+        let mut starships = Table::new();
         let xwing = starships.insert(APIStarShip {
             id: "1".into(),
             name: "X-Wing".into(),
@@ -193,54 +340,113 @@ impl StarWarsAPI {
             length: 34.75,
         });
 
-        let mut characters = Slab::with_capacity(7);
+        let mut vehicles = Table::new();
+        let sandcrawler = vehicles.insert(APIVehicle {
+            id: "1".into(),
+            name: "Sand Crawler".into(),
+            model: "Digger Crawler".into(),
+            manufacturer: "Corellia Mining Corporation".into(),
+            cost_in_credits: Some(150_000),
+            crew: 46,
+            passengers: 30,
+        });
+        let landspeeder = vehicles.insert(APIVehicle {
+            id: "2".into(),
+            name: "X-34 landspeeder".into(),
+            model: "X-34 landspeeder".into(),
+            manufacturer: "SoroSuub Corporation".into(),
+            cost_in_credits: Some(10_550),
+            crew: 1,
+            passengers: 1,
+        });
+        let snowspeeder = vehicles.insert(APIVehicle {
+            id: "3".into(),
+            name: "Snowspeeder".into(),
+            model: "t-47 airspeeder".into(),
+            manufacturer: "Incom corporation".into(),
+            cost_in_credits: None,
+            crew: 2,
+            passengers: 0,
+        });
+        let speeder_bike = vehicles.insert(APIVehicle {
+            id: "4".into(),
+            name: "Imperial Speeder Bike".into(),
+            model: "74-Z speeder bike".into(),
+            manufacturer: "Aratech Repulsor Company".into(),
+            cost_in_credits: Some(8_000),
+            crew: 1,
+            passengers: 1,
+        });
+
+        let mut species = Table::new();
+        let human = species.insert(APISpecies {
+            id: "1".into(),
+            name: "Human".into(),
+            classification: "mammal".into(),
+            language: "Galactic Basic".into(),
+            average_lifespan: Some(120),
+            homeworld: None,
+        });
+        let droid = species.insert(APISpecies {
+            id: "2".into(),
+            name: "Droid".into(),
+            classification: "artificial".into(),
+            language: "n/a".into(),
+            average_lifespan: None,
+            homeworld: None,
+        });
+
+        let mut characters = Table::new();
 
         let luke = characters.insert(
             APICharacter::build("1", "Luke Skywalker")
                 .is_human()
-                .appeared_in(vec![Episode::Empire, Episode::NewHope, Episode::Jedi])
+                .species(human)
                 .star_ship(xwing)
+                .vehicle(snowspeeder)
+                .vehicle(speeder_bike)
                 .mass(77),
         );
         let vader = characters.insert(
             APICharacter::build("2", "Darth Vader")
                 .is_human()
+                .species(human)
                 .star_ship(tie)
-                .appeared_in(vec![Episode::Empire, Episode::NewHope, Episode::Jedi])
                 .mass(120),
         );
         let han = characters.insert(
             APICharacter::build("3", "Han Solo")
                 .is_human()
-                .appeared_in(vec![Episode::Empire, Episode::NewHope, Episode::Jedi])
+                .species(human)
                 .star_ship(falcon)
                 .mass(85),
         );
         let leia = characters.insert(
             APICharacter::build("4", "Leia Organa")
                 .is_human()
+                .species(human)
                 .star_ship(tantive)
-                .appeared_in(vec![Episode::Empire, Episode::NewHope, Episode::Jedi])
+                .vehicle(speeder_bike)
                 .mass(60),
         );
         let tarkin = characters.insert(
             APICharacter::build("5", "Wilhuff Tarkin")
                 .is_human()
+                .species(human)
                 .star_ship(death_star)
-                .appeared_in(vec![Episode::Empire, Episode::NewHope, Episode::Jedi])
                 .mass(90),
         );
         let r2 = characters.insert(
             APICharacter::build("6", "R2-D2")
                 .is_droid()
-                .appeared_in(vec![Episode::Empire, Episode::NewHope, Episode::Jedi])
+                .species(droid)
                 .mass(32)
                 .primary_function("Astromech".into()),
         );
         let treepio = characters.insert(
             APICharacter::build("7", "C-3PO")
                 .is_droid()
-                .appeared_in(vec![Episode::Empire, Episode::NewHope, Episode::Jedi])
+                .species(droid)
                 .mass(75)
                 .primary_function("Protocol".into()),
         );
@@ -254,7 +460,7 @@ impl StarWarsAPI {
         characters[tarkin].friends = vec![vader];
         characters[vader].friends = vec![tarkin];
 
-        let mut planets = Slab::with_capacity(7);
+        let mut planets = Table::new();
 
         let tatooine = planets.insert(APIPlanet {
             id: "1".into(),
@@ -282,96 +488,1182 @@ impl StarWarsAPI {
         characters[vader].home_planet = Some(tatooine);
         characters[leia].home_planet = Some(alderaan);
 
+        let mut films = Table::new();
+
+        let new_hope = films.insert(APIFilm {
+            id: "1".into(),
+            episode_id: 4,
+            title: "A New Hope".into(),
+            release_date: NaiveDate::from_ymd_opt(1977, 5, 25).unwrap(),
+            director: "George Lucas".into(),
+            producer: "Gary Kurtz, Rick McCallum".into(),
+            opening_crawl: NEW_HOPE_CRAWL.into(),
+            starships: vec![xwing, tantive, tie, death_star, falcon],
+            planets: vec![tatooine, alderaan],
+            species: vec![human, droid],
+            vehicles: vec![sandcrawler, landspeeder],
+        });
+        let empire = films.insert(APIFilm {
+            id: "2".into(),
+            episode_id: 5,
+            title: "The Empire Strikes Back".into(),
+            release_date: NaiveDate::from_ymd_opt(1980, 5, 17).unwrap(),
+            director: "Irvin Kershner".into(),
+            producer: "Gary Kurtz, Rick McCallum".into(),
+            opening_crawl: EMPIRE_CRAWL.into(),
+            starships: vec![xwing, tie, falcon],
+            planets: vec![],
+            species: vec![human, droid],
+            vehicles: vec![snowspeeder],
+        });
+        let jedi = films.insert(APIFilm {
+            id: "3".into(),
+            episode_id: 6,
+            title: "Return of the Jedi".into(),
+            release_date: NaiveDate::from_ymd_opt(1983, 5, 25).unwrap(),
+            director: "Richard Marquand".into(),
+            producer: "Howard G. Kazanjian, George Lucas, Rick McCallum".into(),
+            opening_crawl: JEDI_CRAWL.into(),
+            starships: vec![xwing, falcon],
+            planets: vec![tatooine],
+            species: vec![human, droid],
+            vehicles: vec![speeder_bike],
+        });
+
+        // iedereen speelt in de hele originele trilogie mee
+        for (_, character) in characters.iter_mut() {
+            character.films = vec![new_hope, empire, jedi];
+        }
+
         StarWarsAPI {
-            char_id_counter: 7,
-            starship_id_counter: 6,
-            planet_id_counter: 3,
-            luke_idx: luke,
-            r2d2_idx: r2,
+            char_id_counter: AtomicUsize::new(8),
+            starship_id_counter: AtomicUsize::new(6),
+            planet_id_counter: AtomicUsize::new(3),
+            film_id_counter: AtomicUsize::new(4),
+            heroes: Arc::new(Mutex::new(HashMap::from([
+                (None, luke),
+                (Some(4), r2),
+                (Some(5), luke),
+                (Some(6), r2),
+            ]))),
             characters: Arc::new(Mutex::new(characters)),
             starships: Arc::new(Mutex::new(starships)),
             planets: Arc::new(Mutex::new(planets)),
+            films: Arc::new(Mutex::new(films)),
+            species: Arc::new(Mutex::new(species)),
+            vehicles: Arc::new(Mutex::new(vehicles)),
+        }
+    }
+
+    /// The universe of a dataset file instead of the built-in one.
+    /// Nothing is loaded when the dataset has a problem, the error lists all of them.
+    pub fn from_dataset(dataset: Dataset) -> Result<Self, DatasetError> {
+        let dataset = dataset.validate()?;
+
+        // the tables are empty, so the n-th item of a list ends up at key n
+        let planet = indices(dataset.planets.iter().map(|p| &p.id));
+        let starship = indices(dataset.starships.iter().map(|s| &s.id));
+        let vehicle = indices(dataset.vehicles.iter().map(|v| &v.id));
+        let species = indices(dataset.species.iter().map(|s| &s.id));
+        let film = indices(dataset.films.iter().map(|f| &f.id));
+        let character = indices(dataset.characters.iter().map(|c| &c.id));
+        let all = |ids: &[String], idx: &HashMap<String, usize>| -> Vec<usize> {
+            let mut all: Vec<usize> = ids.iter().map(|id| idx[id.as_str()]).collect();
+            all.sort_unstable();
+            all.dedup();
+            all
+        };
+
+        let char_id_counter = AtomicUsize::new(next_free_id(&dataset.characters, |c| &c.id));
+        let starship_id_counter = AtomicUsize::new(next_free_id(&dataset.starships, |s| &s.id));
+        let planet_id_counter = AtomicUsize::new(next_free_id(&dataset.planets, |p| &p.id));
+        let film_id_counter = AtomicUsize::new(next_free_id(&dataset.films, |f| &f.id));
+
+        let mut planets = Table::new();
+        for p in dataset.planets {
+            planets.insert(APIPlanet {
+                id: p.id,
+                name: p.name,
+                climate: p.climate,
+                diameter: p.diameter,
+                gravity: p.gravity,
+                population: p.population,
+                rotation_period: p.rotation_period,
+                orbital_period: p.orbital_period,
+            });
+        }
+        let mut starships = Table::new();
+        for s in dataset.starships {
+            starships.insert(APIStarShip {
+                id: s.id,
+                name: s.name,
+                length: s.length,
+            });
         }
+        let mut vehicles = Table::new();
+        for v in dataset.vehicles {
+            vehicles.insert(APIVehicle {
+                id: v.id,
+                name: v.name,
+                model: v.model,
+                manufacturer: v.manufacturer,
+                cost_in_credits: v.cost_in_credits,
+                crew: v.crew,
+                passengers: v.passengers,
+            });
+        }
+        let mut all_species = Table::new();
+        for s in dataset.species {
+            all_species.insert(APISpecies {
+                homeworld: s.homeworld.map(|id| planet[id.as_str()]),
+                id: s.id,
+                name: s.name,
+                classification: s.classification,
+                language: s.language,
+                average_lifespan: s.average_lifespan,
+            });
+        }
+        let mut films = Table::new();
+        for f in dataset.films {
+            films.insert(APIFilm {
+                starships: all(&f.starships, &starship),
+                planets: all(&f.planets, &planet),
+                species: all(&f.species, &species),
+                vehicles: all(&f.vehicles, &vehicle),
+                id: f.id,
+                episode_id: f.episode_id,
+                title: f.title,
+                release_date: f.release_date,
+                director: f.director,
+                producer: f.producer,
+                opening_crawl: f.opening_crawl,
+            });
+        }
+        let mut characters = Table::new();
+        for c in dataset.characters {
+            let mut built = APICharacter::build(c.id, c.name)
+                .set_friends(c.friends.iter().map(|id| character[id.as_str()]).collect())
+                .in_films(all(&c.films, &film))
+                .mass(c.mass);
+            built = match c.kind {
+                CharacterKind::Human => built.is_human(),
+                CharacterKind::Droid => built.is_droid(),
+            };
+            if let Some(id) = c.species {
+                built = built.species(species[id.as_str()]);
+            }
+            if let Some(id) = c.home_planet {
+                built = built.home_planet(planet[id.as_str()]);
+            }
+            for id in &c.starships {
+                built = built.star_ship(starship[id.as_str()]);
+            }
+            for id in &c.vehicles {
+                built = built.vehicle(vehicle[id.as_str()]);
+            }
+            if let Some(function) = c.primary_function {
+                built = built.primary_function(function);
+            }
+            characters.insert(built);
+        }
+        let heroes = dataset
+            .heroes
+            .iter()
+            .map(|hero| (hero.episode, character[hero.character.as_str()]))
+            .collect();
+
+        Ok(StarWarsAPI {
+            char_id_counter,
+            starship_id_counter,
+            planet_id_counter,
+            film_id_counter,
+            heroes: Arc::new(Mutex::new(heroes)),
+            characters: Arc::new(Mutex::new(characters)),
+            starships: Arc::new(Mutex::new(starships)),
+            planets: Arc::new(Mutex::new(planets)),
+            films: Arc::new(Mutex::new(films)),
+            species: Arc::new(Mutex::new(all_species)),
+            vehicles: Arc::new(Mutex::new(vehicles)),
+        })
+    }
+
+    async fn starship_idx(&self, id: &str) -> Result<usize, ApiError> {
+        self.starships
+            .lock()
+            .await
+            .iter()
+            .find(|(_, s)| s.id == id)
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| ApiError::NotFound {
+                kind: "starship",
+                id: id.into(),
+            })
+    }
+
+    async fn film_idx(&self, id: &str) -> Result<usize, ApiError> {
+        self.films
+            .lock()
+            .await
+            .iter()
+            .find(|(_, f)| f.id == id)
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| ApiError::NotFound {
+                kind: "film",
+                id: id.into(),
+            })
+    }
+
+    /// the films of the episodes, for the `appearsIn` of the mutations
+    async fn episode_films(&self, episodes: &[usize]) -> Result<Vec<usize>, ApiError> {
+        let films = self.films.lock().await;
+        episodes
+            .iter()
+            .map(|&episode| {
+                films
+                    .iter()
+                    .find(|(_, f)| f.episode_id == episode)
+                    .map(|(idx, _)| idx)
+                    .ok_or_else(|| ApiError::NotFound {
+                        kind: "film",
+                        id: format!("episode {episode}"),
+                    })
+            })
+            .collect()
+    }
+
+    async fn species_idx(&self, id: &str) -> Result<usize, ApiError> {
+        self.species
+            .lock()
+            .await
+            .iter()
+            .find(|(_, s)| s.id == id)
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| ApiError::NotFound {
+                kind: "species",
+                id: id.into(),
+            })
+    }
+
+    async fn vehicle_idx(&self, id: &str) -> Result<usize, ApiError> {
+        self.vehicles
+            .lock()
+            .await
+            .iter()
+            .find(|(_, v)| v.id == id)
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| ApiError::NotFound {
+                kind: "vehicle",
+                id: id.into(),
+            })
     }
 
-    pub async fn get_saga_hero(&self) -> APICharacter {
-        self.characters.lock().await[self.luke_idx].clone()
+    async fn planet_idx(&self, id: &str) -> Result<usize, ApiError> {
+        self.planets
+            .lock()
+            .await
+            .iter()
+            .find(|(_, p)| p.id == id)
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| ApiError::NotFound {
+                kind: "planet",
+                id: id.into(),
+            })
+    }
+}
+
+#[async_trait]
+impl StarWarsRepository for StarWarsAPI {
+    async fn get_saga_hero(&self) -> Result<APICharacter, ApiError> {
+        let characters = self.characters.lock().await;
+        let heroes = self.heroes.lock().await;
+        hero(&characters, &heroes, None)
     }
 
-    pub async fn get_r2d2(&self) -> APICharacter {
-        self.characters.lock().await[self.r2d2_idx].clone()
+    async fn get_hero(&self, episode: usize) -> Result<APICharacter, ApiError> {
+        let characters = self.characters.lock().await;
+        let heroes = self.heroes.lock().await;
+        hero(&characters, &heroes, Some(episode))
     }
 
-    pub async fn get_hero(&self, episode: Episode) -> APICharacter {
-        if episode == Episode::Empire {
-            self.get_saga_hero().await
-        } else {
-            self.get_r2d2().await
+    async fn set_hero(
+        &self,
+        episode: Option<usize>,
+        character_id: String,
+    ) -> Result<APICharacter, ApiError> {
+        let characters = self.characters.lock().await;
+        let idx = character_idx(&characters, &character_id)?;
+        if let Some(episode) = episode {
+            self.episode_films(&[episode]).await?;
         }
+        self.heroes.lock().await.insert(episode, idx);
+        Ok(characters[idx].clone())
     }
 
-    pub async fn get_human(&self, id: String) -> Option<APICharacter> {
-        self.characters
+    async fn export(&self) -> Result<Dataset, ApiError> {
+        // every lock at once, so the relations in the dataset all point to something
+        let characters = self.characters.lock().await;
+        let starships = self.starships.lock().await;
+        let planets = self.planets.lock().await;
+        let films = self.films.lock().await;
+        let species = self.species.lock().await;
+        let vehicles = self.vehicles.lock().await;
+        let heroes = self.heroes.lock().await;
+        Ok(Indexed {
+            planets: cloned(&planets),
+            starships: cloned(&starships),
+            vehicles: cloned(&vehicles),
+            species: cloned(&species),
+            films: cloned(&films),
+            characters: cloned(&characters),
+            heroes: heroes
+                .iter()
+                .map(|(&episode, &idx)| (episode, idx))
+                .collect(),
+        }
+        .into())
+    }
+
+    async fn get_human(&self, id: String) -> Result<Option<APICharacter>, ApiError> {
+        Ok(self
+            .characters
             .lock()
             .await
             .iter()
             .find(|(_, c)| c.id == id)
             .map(|(_, c)| c)
             .filter(|c| c.is_human)
-            .cloned()
+            .cloned())
+    }
+
+    async fn get_humans(&self, page: PageRequest) -> Result<Page<APICharacter>, ApiError> {
+        let characters = self.characters.lock().await;
+        let humans = characters.iter().filter(|(_, c)| c.is_human).collect();
+        Ok(page.apply(humans).map(Clone::clone))
     }
 
-    pub async fn get_humans(&self) -> Vec<APICharacter> {
-        self.characters
+    async fn get_droid(&self, id: String) -> Result<Option<APICharacter>, ApiError> {
+        Ok(self
+            .characters
             .lock()
             .await
             .iter()
-            .filter(|(_, c)| c.is_human)
+            .find(|(_, c)| c.id == id)
             .map(|(_, c)| c)
-            .cloned()
-            .collect()
+            .filter(|c| !c.is_human)
+            .cloned())
+    }
+
+    async fn get_droids(&self, page: PageRequest) -> Result<Page<APICharacter>, ApiError> {
+        let characters = self.characters.lock().await;
+        let droids = characters.iter().filter(|(_, c)| !c.is_human).collect();
+        Ok(page.apply(droids).map(Clone::clone))
     }
 
-    pub async fn get_droid(&self, id: String) -> Option<APICharacter> {
-        self.characters
+    async fn get_characters(
+        &self,
+        filter: CharacterFilter,
+        order_by: Vec<CharacterOrder>,
+        page: PageRequest,
+    ) -> Result<Page<APICharacter>, ApiError> {
+        // a planet that doesn't exist just means nobody lives there
+        let home_planet = match &filter.home_planet_id {
+            Some(id) => Some(self.planet_idx(id).await.ok()),
+            None => None,
+        };
+        let starship = match &filter.starship_id {
+            Some(id) => Some(self.starship_idx(id).await.ok()),
+            None => None,
+        };
+        let film = match &filter.film_id {
+            Some(id) => Some(self.film_idx(id).await.ok()),
+            None => None,
+        };
+        let species = match &filter.species_id {
+            Some(id) => Some(self.species_idx(id).await.ok()),
+            None => None,
+        };
+        let vehicle = match &filter.vehicle_id {
+            Some(id) => Some(self.vehicle_idx(id).await.ok()),
+            None => None,
+        };
+        let episode_film = match filter.episode {
+            Some(episode) => Some(
+                self.episode_films(&[episode.number()])
+                    .await
+                    .ok()
+                    .map(|f| f[0]),
+            ),
+            None => None,
+        };
+        let name_contains = filter
+            .name_contains
+            .as_ref()
+            .map(|name| name.to_lowercase());
+
+        let characters = self.characters.lock().await;
+        let mut matches: Vec<(usize, &APICharacter)> = characters
+            .iter()
+            .filter(|(_, c)| {
+                filter
+                    .is_human
+                    .is_none_or(|is_human| c.is_human == is_human)
+                    && episode_film
+                        .is_none_or(|film| film.is_some_and(|film| c.films.contains(&film)))
+                    && film.is_none_or(|film| film.is_some_and(|film| c.films.contains(&film)))
+                    && filter.min_mass.is_none_or(|min| c.mass >= min)
+                    && filter.max_mass.is_none_or(|max| c.mass <= max)
+                    && name_contains
+                        .as_ref()
+                        .is_none_or(|name| c.name.to_lowercase().contains(name))
+                    && filter
+                        .has_starship
+                        .is_none_or(|has| c.star_ships.is_empty() != has)
+                    && home_planet.is_none_or(|planet| planet.is_some() && c.home_planet == planet)
+                    && starship.is_none_or(|starship| {
+                        starship.is_some_and(|starship| c.star_ships.contains(&starship))
+                    })
+                    && species.is_none_or(|species| species.is_some() && c.species == species)
+                    && vehicle.is_none_or(|vehicle| {
+                        vehicle.is_some_and(|vehicle| c.vehicles.contains(&vehicle))
+                    })
+            })
+            .collect();
+        // the table is in key order and the sort is stable, so equal characters stay in key order
+        matches.sort_by(|(_, a), (_, b)| {
+            order_by
+                .iter()
+                .fold(cmp::Ordering::Equal, |ordering, order| {
+                    ordering.then_with(|| compare(order, a, b))
+                })
+        });
+        Ok(page.apply_ordered(matches)?.map(Clone::clone))
+    }
+
+    async fn get_characters_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APICharacter>, ApiError> {
+        Ok(by_idx(&*self.characters.lock().await, idx))
+    }
+
+    async fn get_starship(&self, id: String) -> Result<Option<APIStarShip>, ApiError> {
+        Ok(self
+            .starships
             .lock()
             .await
             .iter()
             .find(|(_, c)| c.id == id)
             .map(|(_, c)| c)
-            .filter(|c| !c.is_human)
-            .cloned()
+            .cloned())
     }
 
-    pub async fn get_droids(&self) -> Vec<APICharacter> {
-        self.characters
+    async fn get_planet(&self, id: String) -> Result<Option<APIPlanet>, ApiError> {
+        Ok(self
+            .planets
             .lock()
             .await
             .iter()
-            .filter(|(_, c)| !c.is_human)
-            .map(|(_, c)| c)
-            .cloned()
-            .collect()
+            .find(|(_, p)| p.id == id)
+            .map(|(_, p)| p)
+            .cloned())
     }
 
-    pub async fn get_character(&self, idx: usize) -> Option<APICharacter> {
-        self.characters.lock().await.get(idx).cloned()
+    async fn get_planets(&self, page: PageRequest) -> Result<Page<APIPlanet>, ApiError> {
+        let planets = self.planets.lock().await;
+        Ok(page.apply(planets.iter().collect()).map(Clone::clone))
     }
 
-    pub async fn get_starship(&self, id: String) -> Option<APIStarShip> {
-        self.starships
+    async fn get_starships(&self, page: PageRequest) -> Result<Page<APIStarShip>, ApiError> {
+        let starships = self.starships.lock().await;
+        Ok(page.apply(starships.iter().collect()).map(Clone::clone))
+    }
+
+    async fn get_film(&self, id: String) -> Result<Option<APIFilm>, ApiError> {
+        Ok(self
+            .films
             .lock()
             .await
             .iter()
-            .find(|(_, c)| c.id == id)
-            .map(|(_, c)| c)
-            .cloned()
+            .find(|(_, f)| f.id == id)
+            .map(|(_, f)| f)
+            .cloned())
+    }
+
+    async fn get_films(&self, page: PageRequest) -> Result<Page<APIFilm>, ApiError> {
+        let films = self.films.lock().await;
+        Ok(page.apply(films.iter().collect()).map(Clone::clone))
+    }
+
+    async fn get_films_by_idx(&self, idx: &[usize]) -> Result<HashMap<usize, APIFilm>, ApiError> {
+        Ok(by_idx(&*self.films.lock().await, idx))
+    }
+
+    async fn get_species(&self, id: String) -> Result<Option<APISpecies>, ApiError> {
+        Ok(self
+            .species
+            .lock()
+            .await
+            .iter()
+            .find(|(_, s)| s.id == id)
+            .map(|(_, s)| s)
+            .cloned())
+    }
+
+    async fn get_all_species(&self, page: PageRequest) -> Result<Page<APISpecies>, ApiError> {
+        let species = self.species.lock().await;
+        Ok(page.apply(species.iter().collect()).map(Clone::clone))
+    }
+
+    async fn get_species_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APISpecies>, ApiError> {
+        Ok(by_idx(&*self.species.lock().await, idx))
+    }
+
+    async fn get_vehicle(&self, id: String) -> Result<Option<APIVehicle>, ApiError> {
+        Ok(self
+            .vehicles
+            .lock()
+            .await
+            .iter()
+            .find(|(_, v)| v.id == id)
+            .map(|(_, v)| v)
+            .cloned())
+    }
+
+    async fn get_vehicles(&self, page: PageRequest) -> Result<Page<APIVehicle>, ApiError> {
+        let vehicles = self.vehicles.lock().await;
+        Ok(page.apply(vehicles.iter().collect()).map(Clone::clone))
+    }
+
+    async fn get_vehicles_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIVehicle>, ApiError> {
+        Ok(by_idx(&*self.vehicles.lock().await, idx))
+    }
+
+    async fn get_starships_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIStarShip>, ApiError> {
+        Ok(by_idx(&*self.starships.lock().await, idx))
+    }
+
+    async fn get_planets_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIPlanet>, ApiError> {
+        Ok(by_idx(&*self.planets.lock().await, idx))
+    }
+
+    async fn create_human(&self, input: NewHuman) -> Result<APICharacter, ApiError> {
+        validate_name(&input.name)?;
+        let home_planet = match &input.home_planet_id {
+            Some(id) => Some(self.planet_idx(id).await?),
+            None => None,
+        };
+        let mut star_ships = Vec::new();
+        for id in input.starship_id.iter().chain(&input.starship_ids) {
+            star_ships.push(self.starship_idx(id).await?);
+        }
+        let films = self.episode_films(&input.film_episodes()).await?;
+        let species = match &input.species_id {
+            Some(id) => Some(self.species_idx(id).await?),
+            None => None,
+        };
+
+        let mut characters = self.characters.lock().await;
+        let friends = friend_indices(&characters, &input.friend_ids)?;
+
+        let mut human = APICharacter::build(next_id(&self.char_id_counter), input.name)
+            .is_human()
+            .in_films(films)
+            .set_friends(friends)
+            .mass(input.mass);
+        if let Some(planet) = home_planet {
+            human = human.home_planet(planet);
+        }
+        if let Some(species) = species {
+            human = human.species(species);
+        }
+        for starship in star_ships {
+            human = human.star_ship(starship);
+        }
+        characters.insert(human.clone());
+        Ok(human)
+    }
+
+    async fn create_droid(&self, input: NewDroid) -> Result<APICharacter, ApiError> {
+        validate_name(&input.name)?;
+        let films = self.episode_films(&input.film_episodes()).await?;
+        let species = match &input.species_id {
+            Some(id) => Some(self.species_idx(id).await?),
+            None => None,
+        };
+        let mut characters = self.characters.lock().await;
+        let friends = friend_indices(&characters, &input.friend_ids)?;
+
+        let mut droid = APICharacter::build(next_id(&self.char_id_counter), input.name)
+            .is_droid()
+            .in_films(films)
+            .set_friends(friends)
+            .mass(input.mass);
+        if let Some(function) = input.primary_function {
+            droid = droid.primary_function(function);
+        }
+        if let Some(species) = species {
+            droid = droid.species(species);
+        }
+        characters.insert(droid.clone());
+        Ok(droid)
+    }
+
+    async fn update_character(
+        &self,
+        id: String,
+        input: UpdateCharacter,
+    ) -> Result<APICharacter, ApiError> {
+        let mut characters = self.characters.lock().await;
+        let idx = character_idx(&characters, &id)?;
+
+        // validate everything before touching the character, so a bad input changes nothing
+        validate_update(&id, characters[idx].is_human, &input)?;
+        let friends = match &input.friend_ids {
+            Some(ids) => Some(friend_indices(&characters, ids)?),
+            None => None,
+        };
+        let films = match input.film_episodes() {
+            Some(episodes) => Some(self.episode_films(&episodes).await?),
+            None => None,
+        };
+        let species = match &input.species_id {
+            Some(id) => Some(self.species_idx(id).await?),
+            None => None,
+        };
+
+        let character = &mut characters[idx];
+        if let Some(name) = input.name {
+            character.name = name;
+        }
+        if let Some(mut films) = films {
+            films.sort_unstable();
+            films.dedup();
+            character.films = films;
+        }
+        if let Some(friends) = friends {
+            character.friends = friends;
+        }
+        if let Some(species) = species {
+            character.species = Some(species);
+        }
+        if let Some(mass) = input.mass {
+            character.mass = mass;
+        }
+        if let Some(function) = input.primary_function {
+            character.primary_function = Some(function);
+        }
+        Ok(character.clone())
+    }
+
+    async fn delete_character(&self, id: String) -> Result<APICharacter, ApiError> {
+        let mut characters = self.characters.lock().await;
+        let idx = character_idx(&characters, &id)?;
+        if self.heroes.lock().await.values().any(|&hero| hero == idx) {
+            return Err(ApiError::InvalidInput(format!(
+                "character `{id}` is an episode hero and can't be deleted"
+            )));
+        }
+
+        let deleted = characters.remove(idx);
+        for (_, character) in characters.iter_mut() {
+            character.friends.retain(|&friend| friend != idx);
+        }
+        Ok(deleted)
+    }
+
+    async fn create_starship(&self, input: NewStarShip) -> Result<APIStarShip, ApiError> {
+        validate_name(&input.name)?;
+        validate_length(input.length)?;
+        let starship = APIStarShip {
+            id: next_id(&self.starship_id_counter),
+            name: input.name,
+            length: input.length,
+        };
+        self.starships.lock().await.insert(starship.clone());
+        Ok(starship)
+    }
+
+    async fn update_starship(
+        &self,
+        id: String,
+        input: UpdateStarShip,
+    ) -> Result<APIStarShip, ApiError> {
+        if let Some(name) = &input.name {
+            validate_name(name)?;
+        }
+        if let Some(length) = input.length {
+            validate_length(length)?;
+        }
+
+        let mut starships = self.starships.lock().await;
+        let starship = starships
+            .iter_mut()
+            .map(|(_, s)| s)
+            .find(|s| s.id == id)
+            .ok_or(ApiError::NotFound {
+                kind: "starship",
+                id,
+            })?;
+        if let Some(name) = input.name {
+            starship.name = name;
+        }
+        if let Some(length) = input.length {
+            starship.length = length;
+        }
+        Ok(starship.clone())
+    }
+
+    async fn delete_starship(&self, id: String) -> Result<APIStarShip, ApiError> {
+        // always lock characters before starships/planets, like the other mutations
+        let mut characters = self.characters.lock().await;
+        let mut starships = self.starships.lock().await;
+        let idx = starships
+            .iter()
+            .find(|(_, s)| s.id == id)
+            .map(|(idx, _)| idx)
+            .ok_or(ApiError::NotFound {
+                kind: "starship",
+                id,
+            })?;
+
+        let deleted = starships.remove(idx);
+        for (_, character) in characters.iter_mut() {
+            character.star_ships.retain(|&starship| starship != idx);
+        }
+        for (_, film) in self.films.lock().await.iter_mut() {
+            film.starships.retain(|&starship| starship != idx);
+        }
+        Ok(deleted)
+    }
+
+    async fn create_planet(&self, input: NewPlanet) -> Result<APIPlanet, ApiError> {
+        validate_name(&input.name)?;
+        let planet = APIPlanet {
+            id: next_id(&self.planet_id_counter),
+            name: input.name,
+            climate: input.climate,
+            diameter: input.diameter,
+            gravity: input.gravity,
+            population: input.population,
+            rotation_period: input.rotation_period,
+            orbital_period: input.orbital_period,
+        };
+        self.planets.lock().await.insert(planet.clone());
+        Ok(planet)
+    }
+
+    async fn update_planet(&self, id: String, input: UpdatePlanet) -> Result<APIPlanet, ApiError> {
+        if let Some(name) = &input.name {
+            validate_name(name)?;
+        }
+
+        let mut planets = self.planets.lock().await;
+        let planet = planets
+            .iter_mut()
+            .map(|(_, p)| p)
+            .find(|p| p.id == id)
+            .ok_or(ApiError::NotFound { kind: "planet", id })?;
+        if let Some(name) = input.name {
+            planet.name = name;
+        }
+        if let Some(climate) = input.climate {
+            planet.climate = climate;
+        }
+        if let Some(diameter) = input.diameter {
+            planet.diameter = diameter;
+        }
+        if let Some(gravity) = input.gravity {
+            planet.gravity = gravity;
+        }
+        if let Some(population) = input.population {
+            planet.population = population;
+        }
+        if let Some(rotation_period) = input.rotation_period {
+            planet.rotation_period = rotation_period;
+        }
+        if let Some(orbital_period) = input.orbital_period {
+            planet.orbital_period = orbital_period;
+        }
+        Ok(planet.clone())
+    }
+
+    async fn delete_planet(&self, id: String) -> Result<APIPlanet, ApiError> {
+        let mut characters = self.characters.lock().await;
+        let mut planets = self.planets.lock().await;
+        let idx = planets
+            .iter()
+            .find(|(_, p)| p.id == id)
+            .map(|(idx, _)| idx)
+            .ok_or(ApiError::NotFound { kind: "planet", id })?;
+
+        let deleted = planets.remove(idx);
+        for (_, character) in characters.iter_mut() {
+            if character.home_planet == Some(idx) {
+                character.home_planet = None;
+            }
+        }
+        for (_, film) in self.films.lock().await.iter_mut() {
+            film.planets.retain(|&planet| planet != idx);
+        }
+        for (_, species) in self.species.lock().await.iter_mut() {
+            if species.homeworld == Some(idx) {
+                species.homeworld = None;
+            }
+        }
+        Ok(deleted)
+    }
+
+    async fn create_film(&self, input: NewFilm) -> Result<APIFilm, ApiError> {
+        validate_film(&input)?;
+        let mut starships = Vec::new();
+        for id in &input.starship_ids {
+            add_sorted(&mut starships, self.starship_idx(id).await?);
+        }
+        let mut planets = Vec::new();
+        for id in &input.planet_ids {
+            add_sorted(&mut planets, self.planet_idx(id).await?);
+        }
+
+        // characters before films, like the other mutations
+        let mut characters = self.characters.lock().await;
+        let cast = friend_indices(&characters, &input.character_ids)?;
+        let mut films = self.films.lock().await;
+        if films.iter().any(|(_, f)| f.episode_id == input.episode_id) {
+            return Err(episode_taken(input.episode_id));
+        }
+
+        let film = APIFilm {
+            id: next_id(&self.film_id_counter),
+            episode_id: input.episode_id,
+            title: input.title,
+            release_date: input.release_date,
+            director: input.director,
+            producer: input.producer,
+            opening_crawl: input.opening_crawl,
+            starships,
+            planets,
+            species: Vec::new(),
+            vehicles: Vec::new(),
+        };
+        let idx = films.insert(film.clone());
+        for character in cast {
+            add_sorted(&mut characters[character].films, idx);
+        }
+        Ok(film)
+    }
+
+    async fn assign_starship(
+        &self,
+        character_id: String,
+        starship_id: Option<String>,
+    ) -> Result<APICharacter, ApiError> {
+        let mut characters = self.characters.lock().await;
+        let idx = human_idx(&characters, &character_id)?;
+        let starship = match &starship_id {
+            Some(id) => Some(self.starship_idx(id).await?),
+            None => None,
+        };
+        characters[idx].star_ships = starship.into_iter().collect();
+        Ok(characters[idx].clone())
+    }
+
+    async fn add_pilot(
+        &self,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<APIStarShip, ApiError> {
+        let mut characters = self.characters.lock().await;
+        let idx = human_idx(&characters, &character_id)?;
+        let starship = self.starship_idx(&starship_id).await?;
+        add_sorted(&mut characters[idx].star_ships, starship);
+        Ok(self.starships.lock().await[starship].clone())
+    }
+
+    async fn remove_pilot(
+        &self,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<APIStarShip, ApiError> {
+        let mut characters = self.characters.lock().await;
+        let idx = human_idx(&characters, &character_id)?;
+        let starship = self.starship_idx(&starship_id).await?;
+        characters[idx].star_ships.retain(|&s| s != starship);
+        Ok(self.starships.lock().await[starship].clone())
+    }
+
+    async fn set_home_planet(
+        &self,
+        character_id: String,
+        planet_id: Option<String>,
+    ) -> Result<APICharacter, ApiError> {
+        let mut characters = self.characters.lock().await;
+        let idx = human_idx(&characters, &character_id)?;
+        let planet = match &planet_id {
+            Some(id) => Some(self.planet_idx(id).await?),
+            None => None,
+        };
+        characters[idx].home_planet = planet;
+        Ok(characters[idx].clone())
+    }
+}
+
+fn compare(order: &CharacterOrder, a: &APICharacter, b: &APICharacter) -> cmp::Ordering {
+    let ordering = match order.field {
+        CharacterOrderField::Id => a.id.cmp(&b.id),
+        CharacterOrderField::Name => a.name.cmp(&b.name),
+        CharacterOrderField::Mass => a.mass.cmp(&b.mass),
+    };
+    match order.direction {
+        OrderDirection::Asc => ordering,
+        OrderDirection::Desc => ordering.reverse(),
+    }
+}
+
+/// every item of the table with its key
+fn cloned<T: Clone>(table: &Table<T>) -> Vec<(usize, T)> {
+    table
+        .iter()
+        .map(|(idx, item)| (idx, item.clone()))
+        .collect()
+}
+
+/// adds `idx` to a sorted list of indices, if it isn't in there yet
+fn add_sorted(list: &mut Vec<usize>, idx: usize) {
+    if let Err(position) = list.binary_search(&idx) {
+        list.insert(position, idx);
+    }
+}
+
+/// one lock for the whole batch instead of one per index
+fn by_idx<T: Clone>(table: &Table<T>, idx: &[usize]) -> HashMap<usize, T> {
+    idx.iter()
+        .filter_map(|&i| Some((i, table.get(i)?.clone())))
+        .collect()
+}
+
+/// the id counter for a list from a dataset, past the highest numeric id in it
+fn next_free_id<T>(items: &[T], id: impl Fn(&T) -> &String) -> usize {
+    items
+        .iter()
+        .filter_map(|item| id(item).parse::<usize>().ok())
+        .max()
+        .map_or(1, |max| max + 1)
+}
+
+fn next_id(counter: &AtomicUsize) -> String {
+    counter.fetch_add(1, Ordering::Relaxed).to_string()
+}
+
+/// like `character_idx`, but droids have no starship or home planet
+fn human_idx(characters: &Table<APICharacter>, id: &str) -> Result<usize, ApiError> {
+    let idx = character_idx(characters, id)?;
+    if !characters[idx].is_human {
+        return Err(ApiError::InvalidInput(format!(
+            "character `{id}` is a droid, only humans have a starship or home planet"
+        )));
+    }
+    Ok(idx)
+}
+
+/// The hero of `episode`, an episode without a hero of its own has the hero of the saga
+fn hero(
+    characters: &Table<APICharacter>,
+    heroes: &HashMap<Option<usize>, usize>,
+    episode: Option<usize>,
+) -> Result<APICharacter, ApiError> {
+    heroes
+        .get(&episode)
+        .or_else(|| heroes.get(&None))
+        .and_then(|&idx| characters.get(idx))
+        .cloned()
+        .ok_or_else(|| ApiError::NotFound {
+            kind: "hero",
+            id: episode.map_or("saga".into(), |e| format!("episode {e}")),
+        })
+}
+
+fn character_idx(characters: &Table<APICharacter>, id: &str) -> Result<usize, ApiError> {
+    characters
+        .iter()
+        .find(|(_, c)| c.id == id)
+        .map(|(idx, _)| idx)
+        .ok_or_else(|| ApiError::NotFound {
+            kind: "character",
+            id: id.into(),
+        })
+}
+
+fn friend_indices(
+    characters: &Table<APICharacter>,
+    ids: &[String],
+) -> Result<Vec<usize>, ApiError> {
+    ids.iter().map(|id| character_idx(characters, id)).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::starwars::models::Episode;
+
+    pub(crate) fn new_human(name: &str) -> NewHuman {
+        NewHuman {
+            name: name.into(),
+            appears_in: Vec::new(),
+            episodes: Vec::new(),
+            friend_ids: Vec::new(),
+            home_planet_id: None,
+            species_id: None,
+            starship_id: None,
+            starship_ids: Vec::new(),
+            mass: 80,
+        }
     }
-    pub async fn get_starship_by_idx(&self, s_idx: usize) -> Option<APIStarShip> {
-        self.starships.lock().await.get(s_idx).cloned()
+
+    fn all_after(after: usize) -> PageRequest {
+        PageRequest {
+            after: Some(after),
+            before: None,
+            first: None,
+            last: None,
+        }
+    }
+
+    /// a deleted character leaves its key behind, so a cursor on it still means the same place
+    #[tokio::test]
+    async fn cursors_stay_put_after_a_delete() {
+        let api = StarWarsAPI::new();
+        let first = api.create_human(new_human("Biggs")).await.unwrap();
+        let page = api.get_humans(all_after(0)).await.unwrap();
+        let (cursor, _) = page.items.last().unwrap();
+        let cursor = *cursor;
+
+        api.delete_character(first.id).await.unwrap();
+        let second = api.create_human(new_human("Wedge")).await.unwrap();
+
+        let names: Vec<String> = api
+            .get_humans(all_after(cursor))
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|(_, human)| human.name)
+            .collect();
+        assert_eq!(names, [second.name]);
+    }
+
+    pub(crate) fn new_film(episode_id: usize, character_ids: &[&str]) -> NewFilm {
+        NewFilm {
+            episode_id,
+            title: "The Phantom Menace".into(),
+            release_date: NaiveDate::from_ymd_opt(1999, 5, 19).unwrap(),
+            director: "George Lucas".into(),
+            producer: "Rick McCallum".into(),
+            opening_crawl: String::new(),
+            character_ids: character_ids.iter().map(|&id| id.into()).collect(),
+            starship_ids: Vec::new(),
+            planet_ids: vec!["1".into()],
+        }
+    }
+
+    /// the episodes of the films of `id`
+    pub(crate) async fn episodes_of(api: &dyn StarWarsRepository, id: &str) -> Vec<usize> {
+        let character = api.get_human(id.into()).await.unwrap().unwrap();
+        let films = api.get_films_by_idx(&character.films).await.unwrap();
+        let mut episodes: Vec<usize> = films.values().map(|f| f.episode_id).collect();
+        episodes.sort_unstable();
+        episodes
+    }
+
+    /// `createFilm` and the `episodes` of the character inputs, for both stores
+    pub(crate) async fn check_films(api: &dyn StarWarsRepository) {
+        let film = api.create_film(new_film(1, &["1"])).await.unwrap();
+        assert_eq!(film.planets.len(), 1);
+        assert_eq!(episodes_of(api, "1").await, [1, 4, 5, 6]);
+
+        let taken = api.create_film(new_film(4, &[])).await;
+        assert_eq!(taken.err().unwrap().code(), "BAD_USER_INPUT");
+        let nobody = api.create_film(new_film(2, &["nobody"])).await;
+        assert_eq!(nobody.err().unwrap().code(), "NOT_FOUND");
+        let untitled = NewFilm {
+            title: " ".into(),
+            ..new_film(3, &[])
+        };
+        assert_eq!(
+            api.create_film(untitled).await.err().unwrap().code(),
+            "BAD_USER_INPUT"
+        );
+
+        let human = api
+            .create_human(NewHuman {
+                appears_in: vec![Episode::Jedi],
+                episodes: vec![1],
+                ..new_human("Qui-Gon")
+            })
+            .await
+            .unwrap();
+        assert_eq!(episodes_of(api, &human.id).await, [1, 6]);
+
+        let update = UpdateCharacter {
+            name: None,
+            appears_in: None,
+            episodes: Some(vec![1, 4]),
+            friend_ids: None,
+            mass: None,
+            primary_function: None,
+            species_id: None,
+        };
+        api.update_character(human.id.clone(), update)
+            .await
+            .unwrap();
+        assert_eq!(episodes_of(api, &human.id).await, [1, 4]);
+
+        let missing = api
+            .create_human(NewHuman {
+                episodes: vec![9],
+                ..new_human("Rey")
+            })
+            .await;
+        assert_eq!(missing.err().unwrap().code(), "NOT_FOUND");
+    }
+
+    /// `setEpisodeHero` for any film, also the ones after createFilm, for both stores
+    pub(crate) async fn check_heroes(api: &dyn StarWarsRepository) {
+        assert_eq!(api.get_hero(4).await.unwrap().name, "R2-D2");
+        // no film and no hero of its own, so the saga hero
+        assert_eq!(api.get_hero(1).await.unwrap().name, "Luke Skywalker");
+
+        let nothing = api.set_hero(Some(1), "2".into()).await;
+        assert_eq!(nothing.err().unwrap().code(), "NOT_FOUND");
+
+        api.create_film(new_film(1, &[])).await.unwrap();
+        api.set_hero(Some(1), "2".into()).await.unwrap();
+        assert_eq!(api.get_hero(1).await.unwrap().name, "Darth Vader");
+        assert_eq!(api.get_hero(4).await.unwrap().name, "R2-D2");
+
+        let heroes = api.export().await.unwrap().heroes;
+        let episodes: Vec<Option<usize>> = heroes.iter().map(|h| h.episode).collect();
+        assert_eq!(episodes, [None, Some(1), Some(4), Some(5), Some(6)]);
+    }
+
+    #[tokio::test]
+    async fn films_can_be_added() {
+        check_films(&StarWarsAPI::new()).await;
     }
 
-    pub async fn get_planet_by_idx(&self, c_idx: usize) -> Option<APIPlanet> {
-        self.planets.lock().await.get(c_idx).cloned()
+    #[tokio::test]
+    async fn every_film_can_have_a_hero() {
+        check_heroes(&StarWarsAPI::new()).await;
     }
 }
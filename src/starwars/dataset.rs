@@ -0,0 +1,916 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    credits::Credits,
+    data::{APICharacter, APIFilm, APIPlanet, APISpecies, APIStarShip, APIVehicle},
+    errors::ApiError,
+    models::LedgerEntry,
+    repository::Repository,
+};
+
+/// Version of the dataset format, `export` writes it and `load` refuses anything newer.
+/// A dataset without a version is taken to be the current one.
+pub const DATASET_VERSION: u32 = 2;
+
+/// A whole universe in a file, so QA can keep several of them next to each other
+/// without touching `data.rs`. Everything points at everything else by id, not by index.
+///
+/// `StarWarsAPI::from_dataset` turns it into the in memory store,
+/// `snapshot` turns the running api back into one.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dataset {
+    #[serde(default = "current_version")]
+    pub version: u32,
+
+    #[serde(default)]
+    pub planets: Vec<PlanetData>,
+
+    #[serde(default)]
+    pub starships: Vec<StarShipData>,
+
+    #[serde(default)]
+    pub vehicles: Vec<VehicleData>,
+
+    #[serde(default)]
+    pub species: Vec<SpeciesData>,
+
+    #[serde(default)]
+    pub films: Vec<FilmData>,
+
+    #[serde(default)]
+    pub characters: Vec<CharacterData>,
+
+    /// who `hero` returns, at most one per episode
+    #[serde(default)]
+    pub heroes: Vec<HeroData>,
+
+    /// the credit accounts for when there is no database,
+    /// leave it out to get the default accounts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credits: Option<Vec<AccountData>>,
+
+    /// the transfers between those accounts, since version 2.
+    /// Leave it out to start with an empty ledger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<LedgerData>,
+}
+
+impl Default for Dataset {
+    fn default() -> Self {
+        Dataset {
+            version: DATASET_VERSION,
+            planets: Vec::new(),
+            starships: Vec::new(),
+            vehicles: Vec::new(),
+            species: Vec::new(),
+            films: Vec::new(),
+            characters: Vec::new(),
+            heroes: Vec::new(),
+            credits: None,
+            ledger: None,
+        }
+    }
+}
+
+fn current_version() -> u32 {
+    DATASET_VERSION
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanetData {
+    pub id: String,
+    pub name: String,
+    pub climate: String,
+    /// in kilometers
+    pub diameter: usize,
+    pub gravity: String,
+    pub population: usize,
+    /// standard (sw) hours
+    pub rotation_period: usize,
+    /// standard (sw) days
+    pub orbital_period: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StarShipData {
+    pub id: String,
+    pub name: String,
+    /// in meters
+    pub length: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VehicleData {
+    pub id: String,
+    pub name: String,
+    pub model: String,
+    pub manufacturer: String,
+    #[serde(default)]
+    pub cost_in_credits: Option<usize>,
+    pub crew: usize,
+    pub passengers: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpeciesData {
+    pub id: String,
+    pub name: String,
+    pub classification: String,
+    pub language: String,
+    /// in standard years, leave it out when it is indefinite or unknown
+    #[serde(default)]
+    pub average_lifespan: Option<usize>,
+    /// id of a planet
+    #[serde(default)]
+    pub homeworld: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilmData {
+    pub id: String,
+    pub episode_id: usize,
+    pub title: String,
+    pub release_date: NaiveDate,
+    pub director: String,
+    pub producer: String,
+    pub opening_crawl: String,
+    /// ids of starships
+    #[serde(default)]
+    pub starships: Vec<String>,
+    /// ids of planets
+    #[serde(default)]
+    pub planets: Vec<String>,
+    /// ids of species
+    #[serde(default)]
+    pub species: Vec<String>,
+    /// ids of vehicles
+    #[serde(default)]
+    pub vehicles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CharacterKind {
+    Human,
+    Droid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CharacterData {
+    pub id: String,
+    pub kind: CharacterKind,
+    pub name: String,
+    /// in kg
+    #[serde(default)]
+    pub mass: usize,
+    /// ids of characters, in the order `friends` returns them
+    #[serde(default)]
+    pub friends: Vec<String>,
+    /// ids of films
+    #[serde(default)]
+    pub films: Vec<String>,
+    /// id of a species
+    #[serde(default)]
+    pub species: Option<String>,
+    /// only for humans, id of a planet
+    #[serde(default)]
+    pub home_planet: Option<String>,
+    /// only for humans, ids of starships
+    #[serde(default)]
+    pub starships: Vec<String>,
+    /// only for humans, ids of vehicles
+    #[serde(default)]
+    pub vehicles: Vec<String>,
+    /// only for droids
+    #[serde(default)]
+    pub primary_function: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeroData {
+    /// the `episode_id` of a film, leave it out for the hero of the entire saga
+    #[serde(default)]
+    pub episode: Option<usize>,
+    /// id of a character
+    pub character: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountData {
+    pub user_id: String,
+    pub balance: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LedgerData {
+    /// the id the next transfer gets, above every id in `transactions`
+    pub next_id: i64,
+    /// ordered by id
+    #[serde(default)]
+    pub transactions: Vec<TransactionData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransactionData {
+    pub id: i64,
+    pub from_user_id: String,
+    pub to_user_id: String,
+    pub amount: i64,
+    #[serde(default)]
+    pub memo: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<LedgerEntry> for TransactionData {
+    fn from(entry: LedgerEntry) -> Self {
+        TransactionData {
+            id: entry.id,
+            from_user_id: entry.from_user_id,
+            to_user_id: entry.to_user_id,
+            amount: entry.amount,
+            memo: entry.memo,
+            created_at: entry.created_at,
+        }
+    }
+}
+
+impl From<TransactionData> for LedgerEntry {
+    fn from(t: TransactionData) -> Self {
+        LedgerEntry {
+            id: t.id,
+            from_user_id: t.from_user_id,
+            to_user_id: t.to_user_id,
+            amount: t.amount,
+            memo: t.memo,
+            created_at: t.created_at,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(std::io::Error),
+
+    /// the file isn't valid JSON or YAML, or doesn't have the shape of a `Dataset`
+    Parse(String),
+
+    /// only `.json`, `.yaml` and `.yml` files
+    UnknownFormat(String),
+
+    /// every reference that points to nothing, duplicate id, ...
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io(e) => write!(f, "can't read the dataset: {e}"),
+            DatasetError::Parse(e) => write!(f, "can't parse the dataset: {e}"),
+            DatasetError::UnknownFormat(path) => write!(
+                f,
+                "don't know the format of `{path}`, use a .json, .yaml or .yml file"
+            ),
+            DatasetError::Invalid(problems) => {
+                write!(f, "the dataset is invalid:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+impl Dataset {
+    /// reads a `.json` or `.yaml` file, the extension decides how it is parsed
+    pub fn load(path: &Path) -> Result<Self, DatasetError> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let contents = fs::read_to_string(path).map_err(DatasetError::Io)?;
+        match extension.as_deref() {
+            Some("json") => {
+                serde_json::from_str(&contents).map_err(|e| DatasetError::Parse(e.to_string()))
+            }
+            Some("yaml" | "yml") => {
+                serde_yaml_ng::from_str(&contents).map_err(|e| DatasetError::Parse(e.to_string()))
+            }
+            _ => Err(DatasetError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    /// Writes a `.json` or `.yaml` file, the extension decides the format like in `load`.
+    /// It is written next to `path` first and then renamed, so a crash halfway leaves
+    /// the old file as it was.
+    pub fn save(&self, path: &Path) -> Result<(), DatasetError> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let contents = match extension.as_deref() {
+            Some("json") => {
+                serde_json::to_string_pretty(self).expect("a dataset is always valid JSON") + "\n"
+            }
+            Some("yaml" | "yml") => {
+                serde_yaml_ng::to_string(self).expect("a dataset is always valid YAML")
+            }
+            _ => return Err(DatasetError::UnknownFormat(path.display().to_string())),
+        };
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let partial = path.with_file_name(format!(".{file_name}.partial"));
+        fs::write(&partial, contents).map_err(DatasetError::Io)?;
+        fs::rename(&partial, path).map_err(DatasetError::Io)
+    }
+
+    /// Everything that keeps the dataset from being loaded, empty when it is fine.
+    /// The rules are the ones the mutations enforce, so a loaded dataset looks like
+    /// something the api could have built itself.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.version > DATASET_VERSION {
+            problems.push(format!(
+                "the dataset has version {}, this server only knows up to version {DATASET_VERSION}",
+                self.version
+            ));
+        }
+        let planets = ids("planet", self.planets.iter().map(|p| &p.id), &mut problems);
+        let starships = ids(
+            "starship",
+            self.starships.iter().map(|s| &s.id),
+            &mut problems,
+        );
+        let vehicles = ids(
+            "vehicle",
+            self.vehicles.iter().map(|v| &v.id),
+            &mut problems,
+        );
+        let species = ids("species", self.species.iter().map(|s| &s.id), &mut problems);
+        let films = ids("film", self.films.iter().map(|f| &f.id), &mut problems);
+        let characters = ids(
+            "character",
+            self.characters.iter().map(|c| &c.id),
+            &mut problems,
+        );
+
+        let mut check = |owner: String, kind: &str, known: &HashSet<&str>, id: &String| {
+            if !known.contains(id.as_str()) {
+                problems.push(format!(
+                    "{owner} points to {kind} `{id}`, which doesn't exist"
+                ));
+            }
+        };
+
+        for s in &self.species {
+            if let Some(planet) = &s.homeworld {
+                check(format!("species `{}`", s.id), "planet", &planets, planet);
+            }
+        }
+        for f in &self.films {
+            let owner = || format!("film `{}`", f.id);
+            for id in &f.starships {
+                check(owner(), "starship", &starships, id);
+            }
+            for id in &f.planets {
+                check(owner(), "planet", &planets, id);
+            }
+            for id in &f.species {
+                check(owner(), "species", &species, id);
+            }
+            for id in &f.vehicles {
+                check(owner(), "vehicle", &vehicles, id);
+            }
+        }
+        for c in &self.characters {
+            let owner = || format!("character `{}`", c.id);
+            for id in &c.friends {
+                check(owner(), "character", &characters, id);
+            }
+            for id in &c.films {
+                check(owner(), "film", &films, id);
+            }
+            if let Some(id) = &c.species {
+                check(owner(), "species", &species, id);
+            }
+            if let Some(id) = &c.home_planet {
+                check(owner(), "planet", &planets, id);
+            }
+            for id in &c.starships {
+                check(owner(), "starship", &starships, id);
+            }
+            for id in &c.vehicles {
+                check(owner(), "vehicle", &vehicles, id);
+            }
+        }
+        for hero in &self.heroes {
+            check("a hero".into(), "character", &characters, &hero.character);
+        }
+
+        for c in &self.characters {
+            if c.name.trim().is_empty() {
+                problems.push(format!("character `{}` has no name", c.id));
+            }
+            if c.friends.contains(&c.id) {
+                problems.push(format!("character `{}` is friends with itself", c.id));
+            }
+            if c.kind == CharacterKind::Droid
+                && (c.home_planet.is_some() || !c.starships.is_empty() || !c.vehicles.is_empty())
+            {
+                problems.push(format!(
+                    "character `{}` is a droid, only humans have a home planet, starships or vehicles",
+                    c.id
+                ));
+            }
+            if c.kind == CharacterKind::Human && c.primary_function.is_some() {
+                problems.push(format!(
+                    "character `{}` is a human and has no primary function",
+                    c.id
+                ));
+            }
+        }
+        for s in &self.starships {
+            if !s.length.is_finite() || s.length < 0. {
+                problems.push(format!("starship `{}` has a negative length", s.id));
+            }
+        }
+
+        let mut episodes = HashSet::new();
+        for f in &self.films {
+            if !episodes.insert(f.episode_id) {
+                problems.push(format!("there is more than one episode {}", f.episode_id));
+            }
+        }
+        let mut heroes = HashSet::new();
+        for hero in &self.heroes {
+            if let Some(episode) = hero.episode.filter(|e| !episodes.contains(e)) {
+                problems.push(format!(
+                    "episode {episode} has a hero but there is no film of it"
+                ));
+            }
+            if !heroes.insert(hero.episode) {
+                problems.push(match hero.episode {
+                    Some(episode) => format!("episode {episode} has more than one hero"),
+                    None => "the saga has more than one hero".into(),
+                });
+            }
+        }
+        let mut accounts = HashSet::new();
+        for account in self.credits.iter().flatten() {
+            if !accounts.insert(&account.user_id) {
+                problems.push(format!(
+                    "there is more than one account of `{}`",
+                    account.user_id
+                ));
+            }
+            if account.balance < 0 {
+                problems.push(format!(
+                    "the account of `{}` has a negative balance",
+                    account.user_id
+                ));
+            }
+        }
+        if let Some(ledger) = &self.ledger {
+            if self.credits.is_none() {
+                problems.push("there is a ledger but no credit accounts".into());
+            }
+            let mut last_id = 0;
+            for t in &ledger.transactions {
+                if t.id <= last_id {
+                    problems.push(format!(
+                        "transaction {} comes after transaction {last_id}, order them by id",
+                        t.id
+                    ));
+                }
+                last_id = last_id.max(t.id);
+                if t.amount <= 0 {
+                    problems.push(format!("transaction {} moves no credits", t.id));
+                }
+                for user_id in [&t.from_user_id, &t.to_user_id] {
+                    if self.credits.is_some() && !accounts.contains(&user_id) {
+                        problems.push(format!(
+                            "transaction {} points to the account of `{user_id}`, which doesn't exist",
+                            t.id
+                        ));
+                    }
+                }
+            }
+            if ledger.next_id <= last_id {
+                problems.push(format!(
+                    "the next transaction gets id {}, that is already taken",
+                    ledger.next_id
+                ));
+            }
+        }
+        problems
+    }
+
+    /// the dataset itself when it has no problems
+    pub fn validate(self) -> Result<Self, DatasetError> {
+        let problems = self.problems();
+        if !problems.is_empty() {
+            return Err(DatasetError::Invalid(problems));
+        }
+        Ok(self)
+    }
+}
+
+/// Everything in a store with the indices it uses for it, the heroes and the relations
+/// point to those indices. Both stores fill one of these for `export`.
+#[derive(Default)]
+pub(crate) struct Indexed {
+    pub planets: Vec<(usize, APIPlanet)>,
+    pub starships: Vec<(usize, APIStarShip)>,
+    pub vehicles: Vec<(usize, APIVehicle)>,
+    pub species: Vec<(usize, APISpecies)>,
+    pub films: Vec<(usize, APIFilm)>,
+    pub characters: Vec<(usize, APICharacter)>,
+    pub heroes: Vec<(Option<usize>, usize)>,
+}
+
+impl From<Indexed> for Dataset {
+    /// the indices become ids again, the items keep the order of their index
+    fn from(mut all: Indexed) -> Self {
+        all.planets.sort_by_key(|&(idx, _)| idx);
+        all.starships.sort_by_key(|&(idx, _)| idx);
+        all.vehicles.sort_by_key(|&(idx, _)| idx);
+        all.species.sort_by_key(|&(idx, _)| idx);
+        all.films.sort_by_key(|&(idx, _)| idx);
+        all.characters.sort_by_key(|&(idx, _)| idx);
+        all.heroes.sort_by_key(|&(episode, _)| episode);
+
+        let planet = ids_of(all.planets.iter().map(|(idx, p)| (*idx, &p.id)));
+        let starship = ids_of(all.starships.iter().map(|(idx, s)| (*idx, &s.id)));
+        let vehicle = ids_of(all.vehicles.iter().map(|(idx, v)| (*idx, &v.id)));
+        let species = ids_of(all.species.iter().map(|(idx, s)| (*idx, &s.id)));
+        let film = ids_of(all.films.iter().map(|(idx, f)| (*idx, &f.id)));
+        let character = ids_of(all.characters.iter().map(|(idx, c)| (*idx, &c.id)));
+        // a relation to something that is gone is dropped, like the stores do when deleting
+        let one = |idx: Option<usize>, ids: &HashMap<usize, String>| ids.get(&idx?).cloned();
+        let many = |idx: &[usize], ids: &HashMap<usize, String>| -> Vec<String> {
+            idx.iter().filter_map(|idx| ids.get(idx).cloned()).collect()
+        };
+
+        Dataset {
+            version: DATASET_VERSION,
+            heroes: all
+                .heroes
+                .iter()
+                .filter_map(|&(episode, idx)| {
+                    Some(HeroData {
+                        episode,
+                        character: character.get(&idx)?.clone(),
+                    })
+                })
+                .collect(),
+            characters: all
+                .characters
+                .into_iter()
+                .map(|(_, c)| CharacterData {
+                    kind: if c.is_human {
+                        CharacterKind::Human
+                    } else {
+                        CharacterKind::Droid
+                    },
+                    friends: many(&c.friends, &character),
+                    films: many(&c.films, &film),
+                    species: one(c.species, &species),
+                    home_planet: one(c.home_planet, &planet),
+                    starships: many(&c.star_ships, &starship),
+                    vehicles: many(&c.vehicles, &vehicle),
+                    id: c.id,
+                    name: c.name,
+                    mass: c.mass,
+                    primary_function: c.primary_function,
+                })
+                .collect(),
+            films: all
+                .films
+                .into_iter()
+                .map(|(_, f)| FilmData {
+                    starships: many(&f.starships, &starship),
+                    planets: many(&f.planets, &planet),
+                    species: many(&f.species, &species),
+                    vehicles: many(&f.vehicles, &vehicle),
+                    id: f.id,
+                    episode_id: f.episode_id,
+                    title: f.title,
+                    release_date: f.release_date,
+                    director: f.director,
+                    producer: f.producer,
+                    opening_crawl: f.opening_crawl,
+                })
+                .collect(),
+            species: all
+                .species
+                .into_iter()
+                .map(|(_, s)| SpeciesData {
+                    homeworld: one(s.homeworld, &planet),
+                    id: s.id,
+                    name: s.name,
+                    classification: s.classification,
+                    language: s.language,
+                    average_lifespan: s.average_lifespan,
+                })
+                .collect(),
+            vehicles: all
+                .vehicles
+                .into_iter()
+                .map(|(_, v)| VehicleData {
+                    id: v.id,
+                    name: v.name,
+                    model: v.model,
+                    manufacturer: v.manufacturer,
+                    cost_in_credits: v.cost_in_credits,
+                    crew: v.crew,
+                    passengers: v.passengers,
+                })
+                .collect(),
+            starships: all
+                .starships
+                .into_iter()
+                .map(|(_, s)| StarShipData {
+                    id: s.id,
+                    name: s.name,
+                    length: s.length,
+                })
+                .collect(),
+            planets: all
+                .planets
+                .into_iter()
+                .map(|(_, p)| PlanetData {
+                    id: p.id,
+                    name: p.name,
+                    climate: p.climate,
+                    diameter: p.diameter,
+                    gravity: p.gravity,
+                    population: p.population,
+                    rotation_period: p.rotation_period,
+                    orbital_period: p.orbital_period,
+                })
+                .collect(),
+            credits: None,
+            ledger: None,
+        }
+    }
+}
+
+/// A versioned copy of everything in `api`, of every credit balance and of the ledger,
+/// `DATASET_PATH` loads it again. Used to reproduce a bug report with the exact state.
+pub async fn snapshot(api: &Repository, credits: &Credits) -> Result<Dataset, ApiError> {
+    let mut dataset = api.export().await?;
+    let credits = credits.export().await?;
+    dataset.credits = Some(
+        credits
+            .accounts
+            .into_iter()
+            .map(|(user_id, balance)| AccountData { user_id, balance })
+            .collect(),
+    );
+    dataset.ledger = Some(LedgerData {
+        next_id: credits.next_id,
+        transactions: credits.ledger.into_iter().map(Into::into).collect(),
+    });
+    Ok(dataset)
+}
+
+/// the ids of one kind, a duplicate is a problem
+fn ids<'a>(
+    kind: &str,
+    ids: impl Iterator<Item = &'a String>,
+    problems: &mut Vec<String>,
+) -> HashSet<&'a str> {
+    let mut seen = HashSet::new();
+    for id in ids {
+        if !seen.insert(id.as_str()) {
+            problems.push(format!("there is more than one {kind} with id `{id}`"));
+        }
+    }
+    seen
+}
+
+/// the id of every index
+fn ids_of<'a>(items: impl Iterator<Item = (usize, &'a String)>) -> HashMap<usize, String> {
+    items.map(|(idx, id)| (idx, id.clone())).collect()
+}
+
+/// key in the table of every id, for a dataset without problems
+pub(crate) fn indices<'a>(ids: impl Iterator<Item = &'a String>) -> HashMap<String, usize> {
+    ids.enumerate().map(|(idx, id)| (id.clone(), idx)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::starwars::{
+        credits::{CreditsStore, MemoryCredits},
+        data::StarWarsAPI,
+        repository::StarWarsRepository,
+    };
+
+    async fn exported() -> Dataset {
+        StarWarsAPI::new().export().await.unwrap()
+    }
+
+    fn character<'a>(dataset: &'a mut Dataset, id: &str) -> &'a mut CharacterData {
+        dataset.characters.iter_mut().find(|c| c.id == id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn problems_lists_every_broken_rule() {
+        let mut dataset = exported().await;
+        dataset.version = DATASET_VERSION + 1;
+        let planet = dataset.planets[0].id.clone();
+        dataset.planets[1].id = planet.clone();
+        character(&mut dataset, "1").friends.push("nobody".into());
+        character(&mut dataset, "3").name = " ".into();
+        character(&mut dataset, "6").home_planet = Some(planet.clone());
+        dataset.credits = Some(vec![AccountData {
+            user_id: "1".into(),
+            balance: -1,
+        }]);
+
+        assert_eq!(
+            dataset.problems(),
+            [
+                format!(
+                    "the dataset has version {}, this server only knows up to version {DATASET_VERSION}",
+                    DATASET_VERSION + 1
+                ),
+                format!("there is more than one planet with id `{planet}`"),
+                // the planet that lost its id
+                "film `1` points to planet `2`, which doesn't exist".into(),
+                "character `1` points to character `nobody`, which doesn't exist".into(),
+                "character `4` points to planet `2`, which doesn't exist".into(),
+                "character `3` has no name".into(),
+                "character `6` is a droid, only humans have a home planet, starships or vehicles"
+                    .into(),
+                "the account of `1` has a negative balance".into(),
+            ]
+        );
+        assert!(matches!(
+            StarWarsAPI::from_dataset(dataset),
+            Err(DatasetError::Invalid(problems)) if problems.len() == 8
+        ));
+    }
+
+    #[tokio::test]
+    async fn files_round_trip() {
+        let dataset = exported().await;
+        let expected = serde_json::to_value(&dataset).unwrap();
+        let dir = std::env::temp_dir();
+        for extension in ["json", "yaml"] {
+            let path = dir.join(format!("swapi_dataset_{}.{extension}", std::process::id()));
+            dataset.save(&path).unwrap();
+            let loaded = Dataset::load(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            let api = StarWarsAPI::from_dataset(loaded).unwrap();
+            let exported = api.export().await.unwrap();
+            assert_eq!(serde_json::to_value(&exported).unwrap(), expected);
+        }
+
+        let text = dir.join("swapi_dataset.txt");
+        assert!(matches!(
+            dataset.save(&text),
+            Err(DatasetError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn defaults_fill_what_is_left_out() {
+        let dataset: Dataset =
+            serde_yaml_ng::from_str("characters:\n  - id: a\n    kind: human\n    name: Anakin\n")
+                .unwrap();
+        assert_eq!(dataset.version, DATASET_VERSION);
+        assert!(dataset.characters[0].friends.is_empty());
+        assert_eq!(dataset.problems(), Vec::<String>::new());
+
+        let unknown = serde_json::from_str::<Dataset>(r#"{"starsips": []}"#);
+        assert!(unknown.is_err());
+    }
+
+    #[tokio::test]
+    async fn heroes_need_a_film() {
+        let mut dataset = exported().await;
+        assert_eq!(dataset.problems(), Vec::<String>::new());
+
+        dataset.heroes.push(HeroData {
+            episode: Some(1),
+            character: "1".into(),
+        });
+        assert_eq!(
+            dataset.problems(),
+            ["episode 1 has a hero but there is no film of it"]
+        );
+
+        let mut prequel = exported().await.films.remove(0);
+        prequel.id = "10".into();
+        prequel.episode_id = 1;
+        dataset.films.push(prequel);
+        assert_eq!(dataset.problems(), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn snapshots_keep_the_ledger() {
+        let api: Repository = Arc::new(StarWarsAPI::new());
+        let credits: Credits = Arc::new(MemoryCredits::new());
+        credits
+            .transfer("1".into(), "2".into(), 10, Some("debt".into()))
+            .await
+            .unwrap();
+        credits
+            .transfer("2".into(), "3".into(), 5, None)
+            .await
+            .unwrap();
+
+        let json = serde_json::to_string(&snapshot(&api, &credits).await.unwrap()).unwrap();
+        let mut restored: Dataset = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.problems(), Vec::<String>::new());
+        let accounts = restored.credits.take().unwrap();
+        let ledger = restored.ledger.take().unwrap();
+        assert_eq!(ledger.next_id, 3);
+        let credits = MemoryCredits::with_accounts(
+            accounts.into_iter().map(|a| (a.user_id, a.balance)),
+            ledger.transactions.into_iter().map(Into::into).collect(),
+            ledger.next_id,
+        );
+
+        let history = credits.ledger("2", None, 10).await.unwrap();
+        let ids: Vec<i64> = history.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [2, 1]);
+        assert_eq!(history[1].memo.as_deref(), Some("debt"));
+        let next = credits
+            .transfer("3".into(), "1".into(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(next.transaction.id, 3);
+        assert_eq!(next.to_balance, 91);
+    }
+
+    #[tokio::test]
+    async fn ledgers_are_checked() {
+        let transaction = |id, from: &str, amount| TransactionData {
+            id,
+            from_user_id: from.into(),
+            to_user_id: "2".into(),
+            amount,
+            memo: None,
+            created_at: Utc::now(),
+        };
+        let mut dataset = exported().await;
+        dataset.ledger = Some(LedgerData {
+            next_id: 2,
+            transactions: vec![transaction(2, "1", 5), transaction(1, "nobody", 0)],
+        });
+        assert_eq!(
+            dataset.problems(),
+            [
+                "there is a ledger but no credit accounts",
+                "transaction 1 comes after transaction 2, order them by id",
+                "transaction 1 moves no credits",
+                "the next transaction gets id 2, that is already taken",
+            ]
+        );
+
+        dataset.credits = Some(vec![
+            AccountData {
+                user_id: "1".into(),
+                balance: 0,
+            },
+            AccountData {
+                user_id: "2".into(),
+                balance: 5,
+            },
+        ]);
+        dataset.ledger = Some(LedgerData {
+            next_id: 3,
+            transactions: vec![transaction(1, "1", 5), transaction(2, "nobody", 1)],
+        });
+        assert_eq!(
+            dataset.problems(),
+            ["transaction 2 points to the account of `nobody`, which doesn't exist"]
+        );
+    }
+}
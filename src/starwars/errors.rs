@@ -0,0 +1,147 @@
+use std::fmt;
+
+use async_graphql::{Error, ErrorExtensions};
+
+/// Errors our resolvers can give back to the client.
+/// Every variant ends up with a `code` in the `extensions` of the GraphQL error
+/// so the frontend can match on it instead of parsing the message.
+#[derive(Debug)]
+pub enum ApiError {
+    /// there is no row in `credits` for this user id
+    UnknownAccount(String),
+
+    /// the sender does not have enough credits for the transfer
+    InsufficientFunds {
+        user_id: String,
+        balance: i64,
+        requested: i64,
+    },
+
+    /// amount of a transfer must be positive and fit in a BIGINT
+    InvalidAmount(usize),
+
+    /// sending credits to yourself makes no sense
+    SameAccount(String),
+
+    /// there is no `kind` (character, starship, ...) with this id
+    NotFound { kind: &'static str, id: String },
+
+    /// the input of a mutation doesn't make sense, the message says why
+    InvalidInput(String),
+
+    /// this needs a bearer token
+    Unauthenticated,
+
+    /// the caller isn't allowed to do this, the message says who is
+    Forbidden(String),
+
+    /// the api key already did its `quota` requests this hour
+    QuotaExceeded { quota: i32 },
+
+    /// this only works with another setup of the server, the message says which
+    Unavailable(&'static str),
+
+    /// the query has more levels than `QUERY_MAX_DEPTH`, checked before it runs
+    QueryTooDeep(usize),
+
+    /// the query costs more than `QUERY_MAX_COMPLEXITY`, checked before it runs
+    QueryTooComplex(usize),
+
+    /// a subscriber was too slow and this many events went past without it
+    EventsMissed(u64),
+
+    /// something went wrong talking to postgres
+    Database(sqlx::Error),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::UnknownAccount(_) => "UNKNOWN_ACCOUNT",
+            ApiError::InsufficientFunds { .. } => "INSUFFICIENT_FUNDS",
+            ApiError::InvalidAmount(_) => "INVALID_AMOUNT",
+            ApiError::SameAccount(_) => "SAME_ACCOUNT",
+            ApiError::NotFound { .. } => "NOT_FOUND",
+            ApiError::InvalidInput(_) => "BAD_USER_INPUT",
+            ApiError::Unauthenticated => "UNAUTHENTICATED",
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::QuotaExceeded { .. } => "QUOTA_EXCEEDED",
+            ApiError::Unavailable(_) => "UNAVAILABLE",
+            ApiError::QueryTooDeep(_) => "QUERY_TOO_DEEP",
+            ApiError::QueryTooComplex(_) => "QUERY_TOO_COMPLEX",
+            ApiError::EventsMissed(_) => "EVENTS_MISSED",
+            ApiError::Database(_) => "INTERNAL_SERVER_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::UnknownAccount(user_id) => {
+                write!(f, "no credits account for user `{user_id}`")
+            }
+            ApiError::InsufficientFunds {
+                user_id,
+                balance,
+                requested,
+            } => write!(
+                f,
+                "user `{user_id}` has {balance} credits but tried to send {requested}"
+            ),
+            ApiError::InvalidAmount(amount) => {
+                write!(f, "invalid transfer amount: {amount}")
+            }
+            ApiError::SameAccount(user_id) => {
+                write!(f, "user `{user_id}` can not send credits to themself")
+            }
+            ApiError::NotFound { kind, id } => write!(f, "no {kind} with id `{id}`"),
+            ApiError::InvalidInput(reason) => write!(f, "invalid input: {reason}"),
+            ApiError::Unauthenticated => write!(f, "log in with a bearer token first"),
+            ApiError::Forbidden(reason) => write!(f, "forbidden: {reason}"),
+            ApiError::QuotaExceeded { quota } => {
+                write!(f, "this api key can only do {quota} operations per hour")
+            }
+            ApiError::Unavailable(reason) => write!(f, "not available: {reason}"),
+            ApiError::QueryTooDeep(max_depth) => {
+                write!(
+                    f,
+                    "query is nested too deep, at most {max_depth} levels are allowed"
+                )
+            }
+            ApiError::QueryTooComplex(max_complexity) => write!(
+                f,
+                "query is too complex, it may cost at most {max_complexity} (every field costs 1, \
+                 a list costs what is asked of one item times the page size)"
+            ),
+            ApiError::EventsMissed(missed) => write!(
+                f,
+                "missed {missed} transfers as they came in faster than they were read"
+            ),
+            // don't leak database details to the client
+            ApiError::Database(_) => write!(f, "internal server error"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(value: sqlx::Error) -> Self {
+        ApiError::Database(value)
+    }
+}
+
+impl ErrorExtensions for ApiError {
+    fn extend(&self) -> Error {
+        if let ApiError::Database(e) = self {
+            tracing::error!("database error: {e}");
+        }
+        Error::new(self.to_string()).extend_with(|_, ext| {
+            ext.set("code", self.code());
+            if let ApiError::InsufficientFunds { balance, .. } = self {
+                ext.set("balance", *balance);
+            }
+        })
+    }
+}
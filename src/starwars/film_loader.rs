@@ -0,0 +1,18 @@
+use async_graphql::{dataloader::*, FieldError, ResultExt};
+use std::collections::HashMap;
+
+use super::{data::APIFilm, Repository};
+
+pub struct FilmLoader {
+    pub api: Repository,
+}
+
+/// Loader for the films characters appear in
+impl Loader<usize> for FilmLoader {
+    type Value = APIFilm;
+    type Error = FieldError;
+
+    async fn load(&self, keys: &[usize]) -> Result<HashMap<usize, Self::Value>, Self::Error> {
+        self.api.get_films_by_idx(keys).await.extend()
+    }
+}
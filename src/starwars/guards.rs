@@ -0,0 +1,98 @@
+use async_graphql::{Context, ErrorExtensions, Guard, GuardExt, Result};
+
+use crate::auth::{Caller, Role};
+
+use super::{
+    errors::ApiError,
+    node::{local_id, CHARACTER},
+};
+
+/// who sends the request, the handlers in `main` always put it in the data
+pub fn caller<'a>(ctx: &Context<'a>) -> &'a Caller {
+    ctx.data_unchecked::<Caller>()
+}
+
+/// Only callers with at least `role`, fails with `FORBIDDEN`
+pub struct RoleGuard {
+    role: Role,
+}
+
+impl RoleGuard {
+    pub fn new(role: Role) -> Self {
+        Self { role }
+    }
+}
+
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let caller = caller(ctx);
+        if caller.has_role(self.role) {
+            return Ok(());
+        }
+        Err(match caller {
+            Caller::Anonymous => forbidden_anonymous(),
+            _ => ApiError::Forbidden(format!("only {}s can do this", self.role)),
+        }
+        .extend())
+    }
+}
+
+/// Only the character itself, like for its credits. Fails with `FORBIDDEN`,
+/// combine it with `RoleGuard` to let admins through as well.
+pub struct OwnerGuard {
+    /// local id of the character, `None` when the id doesn't point to a character
+    user_id: Option<String>,
+}
+
+impl OwnerGuard {
+    /// `id` is the local id, like `Human.id` keeps it
+    pub fn new(id: &str) -> Self {
+        Self {
+            user_id: Some(id.into()),
+        }
+    }
+
+    /// `id` is a global id, like the mutations get them
+    pub fn global(id: &str) -> Self {
+        Self {
+            user_id: local_id(id.into(), CHARACTER).ok(),
+        }
+    }
+}
+
+impl Guard for OwnerGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let caller = caller(ctx);
+        match (caller, &self.user_id) {
+            (Caller::Unchecked, _) => Ok(()),
+            (Caller::Anonymous, _) => Err(forbidden_anonymous().extend()),
+            (_, Some(owner)) if caller.user_id() == Some(owner) => Ok(()),
+            (_, Some(owner)) => {
+                Err(ApiError::Forbidden(format!("only character `{owner}` can do this")).extend())
+            }
+            (_, None) => Err(ApiError::Forbidden("that isn't a character".into()).extend()),
+        }
+    }
+}
+
+/// the character with local id `id` itself, or an admin
+pub fn owner_or_admin(id: &str) -> impl Guard {
+    RoleGuard::new(Role::Admin).or(OwnerGuard::new(id))
+}
+
+/// For a field inside a list of objects, where the error of a `guard = ...` would take
+/// the whole list with it: the `FORBIDDEN` error is reported next to the data
+/// and only this field becomes `null`.
+pub async fn allowed(ctx: &Context<'_>, guard: impl Guard) -> bool {
+    match guard.check(ctx).await {
+        Ok(()) => true,
+        Err(e) => {
+            ctx.add_error(ctx.set_error_path(e.into_server_error(ctx.item.pos)));
+            false
+        }
+    }
+}
+
+fn forbidden_anonymous() -> ApiError {
+    ApiError::Forbidden("this needs a bearer token".into())
+}
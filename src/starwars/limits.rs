@@ -0,0 +1,302 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextValidation},
+    parser::types::{
+        ExecutableDocument, Field, FragmentDefinition, OperationType, Selection, SelectionSet,
+        VariableDefinition,
+    },
+    registry::{MetaTypeName, Registry},
+    ErrorExtensions, Name, Positioned, ServerError, ServerResult, ValidationResult, Value,
+    Variables,
+};
+
+use super::{errors::ApiError, pagination::page_cost};
+
+// the messages of `async_graphql::validation::check_rules`, the tests notice when they change
+const TOO_DEEP: &str = "Query is nested too deep.";
+const TOO_COMPLEX: &str = "Query is too complex.";
+
+/// `limit_depth` and `limit_complexity` of the schema, `QUERY_MAX_DEPTH` and
+/// `QUERY_MAX_COMPLEXITY` in the config. The friends of a character have friends as well,
+/// without a limit one query can walk the whole friends graph over and over.
+///
+/// As an extension it gives the errors of async-graphql for these limits a `code`
+/// and the limit in the message, like our other errors. It also prices the lists of
+/// the `Character` interface, which async-graphql counts as 1 whatever the page size.
+#[derive(Clone, Copy)]
+pub struct QueryLimits {
+    pub max_depth: usize,
+    pub max_complexity: usize,
+}
+
+impl ExtensionFactory for QueryLimits {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(*self)
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for QueryLimits {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let registry = &ctx.schema_env.registry;
+        for (_, operation) in document.operations.iter() {
+            let root = match operation.node.ty {
+                OperationType::Query => Some(&registry.query_type),
+                OperationType::Mutation => registry.mutation_type.as_ref(),
+                OperationType::Subscription => registry.subscription_type.as_ref(),
+            };
+            let cost = Cost {
+                registry,
+                fragments: &document.fragments,
+                variables,
+                definitions: &operation.node.variable_definitions,
+            };
+            let root = root.map_or("", String::as_str);
+            if cost.selection_set(root, &operation.node.selection_set.node, &mut Vec::new())
+                > self.max_complexity
+            {
+                let error = ServerError::new(TOO_COMPLEX, Some(operation.pos));
+                return Err(self.explain(error));
+            }
+        }
+        Ok(document)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        next.run(ctx)
+            .await
+            .map_err(|errors| errors.into_iter().map(|e| self.explain(e)).collect())
+    }
+}
+
+impl QueryLimits {
+    fn explain(&self, mut error: ServerError) -> ServerError {
+        let reason = match error.message.as_str() {
+            TOO_DEEP => ApiError::QueryTooDeep(self.max_depth),
+            TOO_COMPLEX => ApiError::QueryTooComplex(self.max_complexity),
+            _ => return error,
+        };
+        let extended = reason.extend();
+        error.message = extended.message;
+        error.extensions = extended.extensions;
+        error
+    }
+}
+
+/// The complexity of a query the way the `complexity` of our fields counts it: a field
+/// with `first` and `last` is a page and costs `page_cost`, `nodes(ids)` costs every id.
+/// async-graphql only uses the `complexity` of fields of objects, so a page reached
+/// through an interface, like `hero { friends }`, is priced here from the schema instead.
+struct Cost<'a> {
+    registry: &'a Registry,
+    fragments: &'a HashMap<Name, Positioned<FragmentDefinition>>,
+    variables: &'a Variables,
+    definitions: &'a [Positioned<VariableDefinition>],
+}
+
+impl<'a> Cost<'a> {
+    /// `spreads` are the fragments we are in, a fragment that spreads itself is an error
+    /// of the validation later on
+    fn selection_set(
+        &self,
+        type_name: &str,
+        selection_set: &SelectionSet,
+        spreads: &mut Vec<&'a Name>,
+    ) -> usize {
+        let mut cost = 0usize;
+        for selection in &selection_set.items {
+            let selection_cost = match &selection.node {
+                Selection::Field(field) => self.field(type_name, &field.node, spreads),
+                Selection::InlineFragment(fragment) => {
+                    let fragment = &fragment.node;
+                    let type_name = fragment
+                        .type_condition
+                        .as_ref()
+                        .map_or(type_name, |condition| condition.node.on.node.as_str());
+                    self.selection_set(type_name, &fragment.selection_set.node, spreads)
+                }
+                Selection::FragmentSpread(spread) => {
+                    let fragment = self
+                        .fragments
+                        .get_key_value(&spread.node.fragment_name.node);
+                    match fragment {
+                        Some((name, fragment)) if !spreads.contains(&name) => {
+                            spreads.push(name);
+                            let fragment = &fragment.node;
+                            let type_name = fragment.type_condition.node.on.node.as_str();
+                            let cost = self.selection_set(
+                                type_name,
+                                &fragment.selection_set.node,
+                                spreads,
+                            );
+                            spreads.pop();
+                            cost
+                        }
+                        _ => 0,
+                    }
+                }
+            };
+            cost = cost.saturating_add(selection_cost);
+        }
+        cost
+    }
+
+    fn field(&self, type_name: &str, field: &Field, spreads: &mut Vec<&'a Name>) -> usize {
+        let meta = self
+            .registry
+            .types
+            .get(type_name)
+            .and_then(|ty| ty.field_by_name(&field.name.node));
+        let child_type = meta.map_or("", |meta| MetaTypeName::concrete_typename(&meta.ty));
+        let children = self.selection_set(child_type, &field.selection_set.node, spreads);
+        match meta {
+            Some(meta) if meta.args.contains_key("first") => {
+                page_cost(self.int(field, "first"), self.int(field, "last"), children)
+            }
+            Some(meta) if meta.args.contains_key("ids") => match self.argument(field, "ids") {
+                Some(Value::List(ids)) => ids.len().saturating_mul(children).saturating_add(1),
+                _ => children.saturating_add(1),
+            },
+            _ => children.saturating_add(1),
+        }
+    }
+
+    fn int(&self, field: &Field, name: &str) -> Option<i32> {
+        match self.argument(field, name)? {
+            Value::Number(number) => number
+                .as_i64()
+                .map(|n| i32::try_from(n).unwrap_or(i32::MAX)),
+            _ => None,
+        }
+    }
+
+    /// the value of an argument, with the variables filled in
+    fn argument(&self, field: &Field, name: &str) -> Option<Value> {
+        let value = field.get_argument(name)?.node.clone();
+        value
+            .into_const_with(|variable| {
+                let default = || {
+                    self.definitions
+                        .iter()
+                        .find(|definition| definition.node.name.node == variable)
+                        .and_then(|definition| definition.node.default_value.as_ref())
+                        .map(|value| value.node.clone())
+                };
+                self.variables
+                    .get(&variable)
+                    .cloned()
+                    .or_else(default)
+                    .ok_or(())
+            })
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{Request, Response, Schema};
+    use serde_json::json;
+
+    use super::*;
+    use crate::starwars::{
+        schema::tests::memory_schema, MutationRoot, QueryRoot, SubscriptionRoot,
+    };
+
+    const DEEP: &str = r#"{ human(id: "1") { friends(first: 1) { edges { node {
+        friends(first: 1) { edges { node { name } } } } } } } }"#;
+    const EXPENSIVE: &str = r#"{ humans(first: 100) { edges { node { name } } } }"#;
+
+    const LIMITS: QueryLimits = QueryLimits {
+        max_depth: 5,
+        max_complexity: 20,
+    };
+
+    fn code(response: &Response) -> String {
+        let extensions = response.errors[0].extensions.as_ref().unwrap();
+        extensions.get("code").unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn async_graphql_messages() {
+        let schema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+            .limit_depth(LIMITS.max_depth)
+            .limit_complexity(LIMITS.max_complexity)
+            .finish();
+        assert_eq!(schema.execute(DEEP).await.errors[0].message, TOO_DEEP);
+        assert_eq!(
+            schema.execute(EXPENSIVE).await.errors[0].message,
+            TOO_COMPLEX
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_get_a_code() {
+        let schema = memory_schema(LIMITS);
+
+        let deep = schema.execute(DEEP).await;
+        assert_eq!(code(&deep), r#""QUERY_TOO_DEEP""#);
+        assert_eq!(
+            deep.errors[0].message,
+            "query is nested too deep, at most 5 levels are allowed"
+        );
+
+        let expensive = schema.execute(EXPENSIVE).await;
+        assert_eq!(code(&expensive), r#""QUERY_TOO_COMPLEX""#);
+        assert!(expensive.errors[0]
+            .message
+            .contains("it may cost at most 20"));
+
+        // other validation errors stay as they are
+        let unknown = schema.execute("{ jedi }").await;
+        assert!(unknown.errors[0].extensions.is_none());
+
+        let fine = schema.execute(r#"{ human(id: "1") { name } }"#).await;
+        assert!(fine.errors.is_empty(), "{:?}", fine.errors);
+    }
+
+    #[tokio::test]
+    async fn lists_of_interfaces_are_priced() {
+        let schema = memory_schema(QueryLimits {
+            max_depth: 15,
+            max_complexity: 300,
+        });
+        // `hero` is a `Character`, its friends are priced through the interface
+        let friends_of_friends = r#"{ hero { friends(first: 100) { edges { node {
+            friends(first: 100) { edges { node {
+            friends(first: 100) { edges { node { name } } } } } } } } } } }"#;
+        let response = schema.execute(friends_of_friends).await;
+        assert_eq!(code(&response), r#""QUERY_TOO_COMPLEX""#);
+
+        let fragment = r#"query Friends($size: Int = 100) { hero { ...friends } }
+            fragment friends on Character {
+                films(first: $size) { edges { node { title } } }
+                friends(last: $size) { edges { node { name } } }
+            }"#;
+        let response = schema.execute(fragment).await;
+        assert_eq!(code(&response), r#""QUERY_TOO_COMPLEX""#);
+        let request = Request::new(fragment).variables(Variables::from_json(json!({ "size": 5 })));
+        let response = schema.execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let cheap = r#"{ hero { friends(first: 2) { edges { node { name } } } } }"#;
+        let response = schema.execute(cheap).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        // the validation finds the loop, pricing it doesn't go round forever
+        let endless = "{ hero { ...again } } fragment again on Character { friends { edges { node { ...again } } } }";
+        let response = schema.execute(endless).await;
+        assert!(!response.errors.is_empty());
+    }
+}
@@ -1,7 +1,31 @@
+pub mod api_keys;
+pub mod character_loader;
+pub mod credits;
 pub mod credits_loader;
 pub mod data;
+pub mod dataset;
+pub mod errors;
+pub mod film_loader;
+pub mod guards;
+pub mod limits;
 pub mod models;
+pub mod node;
+pub mod pagination;
+pub mod pg_data;
+pub mod planet_loader;
+pub mod repository;
 pub mod roots;
+pub mod schema;
+pub mod snapshots;
+pub mod species_loader;
+pub mod starship_loader;
+pub mod swapi_dump;
+pub mod table;
+#[cfg(test)]
+mod test_db;
+pub mod vehicle_loader;
 
 pub use data::StarWarsAPI;
-pub use roots::{MutationRoot, QueryRoot};
+pub use pg_data::PgStarWarsAPI;
+pub use repository::Repository;
+pub use roots::{MutationRoot, QueryRoot, SubscriptionRoot};
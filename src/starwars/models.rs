@@ -1,15 +1,31 @@
 use async_graphql::{
-    dataloader::DataLoader, Context, DataContext, Enum, Interface, Object, Result,
+    connection::Connection, dataloader::DataLoader, Context, Enum, InputObject, Interface, Object,
+    Result, ResultExt, SimpleObject, ID,
 };
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::Executor;
 
-use crate::starwars::data::{APICharacter, APIPlanet, APIStarShip, StarWarsAPI};
-use futures::{future::Either, stream, FutureExt, StreamExt};
+use crate::starwars::{
+    data::{APICharacter, APIFilm, APIPlanet, APISpecies, APIStarShip, APIVehicle},
+    Repository,
+};
 
-use super::credits_loader::CreditsDataLoader;
-/// One of the films in the Star Wars Trilogy
-#[derive(Enum, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+use super::{
+    character_loader::CharacterLoader,
+    credits,
+    credits_loader::CreditsDataLoader,
+    film_loader::FilmLoader,
+    guards::{allowed, owner_or_admin},
+    node::{global_id, DROID, FILM, HUMAN, PLANET, SPECIES, STARSHIP, VEHICLE},
+    pagination::{connection, page_cost, KeyConnection},
+    planet_loader::PlanetLoader,
+    species_loader::SpeciesLoader,
+    starship_loader::StarshipLoader,
+    vehicle_loader::VehicleLoader,
+};
+/// One of the films in the original Star Wars Trilogy.
+/// Every other film only exists as a `Film`, this stays for `appearsIn` and `hero`.
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Episode {
     /// Released in 1977.
     NewHope,
@@ -21,6 +37,26 @@ pub enum Episode {
     Jedi,
 }
 
+impl Episode {
+    /// the `episodeId` of the film
+    pub fn number(self) -> usize {
+        match self {
+            Episode::NewHope => 4,
+            Episode::Empire => 5,
+            Episode::Jedi => 6,
+        }
+    }
+
+    pub fn from_number(number: usize) -> Option<Self> {
+        match number {
+            4 => Some(Episode::NewHope),
+            5 => Some(Episode::Empire),
+            6 => Some(Episode::Jedi),
+            _ => None,
+        }
+    }
+}
+
 pub struct Human {
     /// id of this character
     pub id: String,
@@ -32,14 +68,19 @@ pub struct Human {
     /// maps in StarWars.characters
     pub friends: Vec<usize>,
 
-    /// all the episodes this character appeared in
-    pub appears_in: Vec<Episode>,
+    /// indices of the films this character appeared in, sorted
+    pub films: Vec<usize>,
 
     /// Optional Home planet of  a Human
     pub home_planet: Option<usize>,
 
-    /// the starship of a Human
-    pub star_ship: Option<usize>,
+    /// the starships this Human flies
+    pub star_ships: Vec<usize>,
+
+    /// the vehicles this Human drives
+    pub vehicles: Vec<usize>,
+
+    pub species: Option<usize>,
 
     /// mass of character (i.e. weight) in kg
     pub mass: usize,
@@ -51,9 +92,11 @@ impl From<APICharacter> for Human {
             id: value.id,
             name: value.name,
             friends: value.friends,
-            appears_in: value.appears_in,
+            films: value.films,
             home_planet: value.home_planet,
-            star_ship: value.star_ship,
+            star_ships: value.star_ships,
+            vehicles: value.vehicles,
+            species: value.species,
             mass: value.mass,
         }
     }
@@ -61,47 +104,217 @@ impl From<APICharacter> for Human {
 
 #[Object]
 impl Human {
-    pub async fn id(&self) -> &str {
-        &self.id
+    /// global id, see `Node`
+    pub async fn id(&self) -> ID {
+        global_id(HUMAN, &self.id)
     }
     pub async fn name(&self) -> &str {
         &self.name
     }
-    pub async fn friends<'ctx>(&self, ctx: &Context<'ctx>) -> Vec<Character> {
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        stream::iter(self.friends.iter())
-            .filter_map(|&i| async move { api.get_character(i).await })
-            .map(Into::into)
-            .collect()
-            .await
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    pub async fn friends<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Character>> {
+        friends(ctx, &self.friends, after, before, first, last).await
+    }
+
+    /// the episodes of the original trilogy this character appeared in
+    #[graphql(deprecation = "only knows the original trilogy, use `episodes` or `films`")]
+    pub async fn appears_in<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<Episode>> {
+        appears_in(ctx, &self.films).await
+    }
+
+    /// the `episodeId` of every film this character appeared in, in order
+    pub async fn episodes<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<usize>> {
+        episodes(ctx, &self.films).await
     }
 
-    pub async fn appears_in(&self) -> Vec<Episode> {
-        self.appears_in.clone()
+    /// every film this character appeared in
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    pub async fn films<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Film>> {
+        films(ctx, &self.films, after, before, first, last).await
     }
 
     pub async fn mass(&self) -> usize {
         self.mass
     }
 
-    pub async fn home_planet<'ctx>(&self, ctx: &Context<'ctx>) -> Option<Planet> {
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        let home_planet = self.home_planet?;
-        api.get_planet_by_idx(home_planet).await.map(Into::into)
+    pub async fn home_planet<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<Planet>> {
+        let Some(home_planet) = self.home_planet else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DataLoader<PlanetLoader>>();
+        Ok(loader.load_one(home_planet).await?.map(Into::into))
     }
 
-    pub async fn starship<'ctx>(&self, ctx: &Context<'ctx>) -> Option<StarShip> {
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        let star_ship = self.star_ship?;
-        api.get_starship_by_idx(star_ship).await.map(Into::into)
+    #[graphql(deprecation = "a human can fly more than one starship, use `starships`")]
+    pub async fn starship<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<StarShip>> {
+        let Some(&star_ship) = self.star_ships.first() else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DataLoader<StarshipLoader>>();
+        Ok(loader.load_one(star_ship).await?.map(Into::into))
     }
 
+    /// the starships this human is a pilot of
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    pub async fn starships<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<StarShip>> {
+        let loader = ctx.data_unchecked::<DataLoader<StarshipLoader>>();
+        connection(after, before, first, last, |page| async move {
+            let page = page.apply(self.star_ships.iter().map(|&idx| (idx, ())).collect());
+            let starships = loader
+                .load_many(page.items.iter().map(|&(idx, ())| idx))
+                .await?;
+            Ok::<_, async_graphql::Error>(page.fill(starships))
+        })
+        .await
+    }
+
+    /// the vehicles this human drives
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    pub async fn vehicles<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Vehicle>> {
+        let loader = ctx.data_unchecked::<DataLoader<VehicleLoader>>();
+        connection(after, before, first, last, |page| async move {
+            let page = page.apply(self.vehicles.iter().map(|&idx| (idx, ())).collect());
+            let vehicles = loader
+                .load_many(page.items.iter().map(|&(idx, ())| idx))
+                .await?;
+            Ok::<_, async_graphql::Error>(page.fill(vehicles))
+        })
+        .await
+    }
+
+    pub async fn species<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<Species>> {
+        let Some(species) = self.species else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DataLoader<SpeciesLoader>>();
+        Ok(loader.load_one(species).await?.map(Into::into))
+    }
+
+    /// only the human itself or an admin can see the balance, for anyone else it is `null`
     pub async fn credits<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<i64>> {
+        if !allowed(ctx, owner_or_admin(&self.id)).await {
+            return Ok(None);
+        }
         // we know it exists
         let loader = ctx.data_unchecked::<DataLoader<CreditsDataLoader>>();
         loader.load_one(self.id.clone()).await
     }
+
+    /// the credit transfers this human sent or received, newest first,
+    /// only the human itself or an admin can see them, for anyone else it is `null`
+    #[graphql(complexity = "page_cost(first, None, child_complexity)")]
+    pub async fn transactions<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Option<Connection<i64, CreditHistoryEntry>>> {
+        if !allowed(ctx, owner_or_admin(&self.id)).await {
+            return Ok(None);
+        }
+        let store = ctx.data_unchecked::<credits::Credits>();
+        credits::history(store, self.id.clone(), first, after)
+            .await
+            .map(Some)
+    }
+}
+/// Friends of a human or droid in the order they were added, the cursor is the key of the friend.
+/// Only the friends in the requested page are loaded.
+async fn friends(
+    ctx: &Context<'_>,
+    friends: &[usize],
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> Result<KeyConnection<Character>> {
+    // we weten dat de loader bestaat duhhhh
+    // in het echt moeten we dit doen:
+    // let loader = ctx.data::<DataLoader<CharacterLoader>>()?; // return error
+    let loader = ctx.data_unchecked::<DataLoader<CharacterLoader>>();
+    connection(after, before, first, last, |page| async move {
+        let page = page
+            .apply_ordered(friends.iter().map(|&idx| (idx, ())).collect())
+            .extend()?;
+        let characters = loader
+            .load_many(page.items.iter().map(|&(idx, ())| idx))
+            .await?;
+        // friends that got deleted are left out
+        Ok::<_, async_graphql::Error>(page.fill(characters))
+    })
+    .await
+}
+
+/// The films of a human or droid, ordered by key like every other list of a store.
+async fn films(
+    ctx: &Context<'_>,
+    films: &[usize],
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> Result<KeyConnection<Film>> {
+    let loader = ctx.data_unchecked::<DataLoader<FilmLoader>>();
+    connection(after, before, first, last, |page| async move {
+        let page = page.apply(films.iter().map(|&idx| (idx, ())).collect());
+        let films = loader
+            .load_many(page.items.iter().map(|&(idx, ())| idx))
+            .await?;
+        Ok::<_, async_graphql::Error>(page.fill(films))
+    })
+    .await
+}
+
+/// the `episodeId` of every film, sorted
+async fn episodes(ctx: &Context<'_>, films: &[usize]) -> Result<Vec<usize>> {
+    let loader = ctx.data_unchecked::<DataLoader<FilmLoader>>();
+    let mut episodes: Vec<usize> = loader
+        .load_many(films.iter().copied())
+        .await?
+        .into_values()
+        .map(|film| film.episode_id)
+        .collect();
+    episodes.sort_unstable();
+    Ok(episodes)
+}
+
+/// `appearsIn` from the films, films outside the original trilogy have no `Episode`
+async fn appears_in(ctx: &Context<'_>, films: &[usize]) -> Result<Vec<Episode>> {
+    Ok(episodes(ctx, films)
+        .await?
+        .into_iter()
+        .filter_map(Episode::from_number)
+        .collect())
 }
+
 pub struct Droid {
     /// id of this character
     pub id: String,
@@ -113,14 +326,13 @@ pub struct Droid {
     /// maps in StarWars.characters
     pub friends: Vec<usize>,
 
-    /// all the episodes this character appeared in
-    pub appears_in: Vec<Episode>,
+    /// indices of the films this character appeared in, sorted
+    pub films: Vec<usize>,
 
     /// primary function of droid
     pub primary_function: Option<String>,
 
-    /// mass of character (i.e. weight) in kg
-    pub mass: usize,
+    pub species: Option<usize>,
 }
 
 impl From<APICharacter> for Droid {
@@ -129,41 +341,70 @@ impl From<APICharacter> for Droid {
             id: value.id,
             name: value.name,
             friends: value.friends,
-            appears_in: value.appears_in,
-            mass: value.mass,
+            films: value.films,
             primary_function: value.primary_function,
+            species: value.species,
         }
     }
 }
 
 #[Object]
 impl Droid {
-    pub async fn id(&self) -> &str {
-        &self.id
+    /// global id, see `Node`
+    pub async fn id(&self) -> ID {
+        global_id(DROID, &self.id)
     }
     pub async fn name(&self) -> &str {
         &self.name
     }
-    pub async fn friends<'ctx>(&self, ctx: &Context<'ctx>) -> Vec<Character> {
-        // we weten dat StarWarsAPI bestaat duhhhh
-        // in het echt moeten we dit doen:
-        // let api = ctx.data::<StarWarsAPI>()?; // return error
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        stream::iter(self.friends.iter())
-            .filter_map(|&i| api.get_character(i))
-            .map(Into::into)
-            .collect()
-            .await
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    pub async fn friends<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Character>> {
+        friends(ctx, &self.friends, after, before, first, last).await
+    }
+
+    /// the episodes of the original trilogy this droid appeared in
+    #[graphql(deprecation = "only knows the original trilogy, use `episodes` or `films`")]
+    pub async fn appears_in<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<Episode>> {
+        appears_in(ctx, &self.films).await
     }
 
-    pub async fn appears_in<'ctx>(&self) -> Vec<Episode> {
-        self.appears_in.clone()
+    /// the `episodeId` of every film this droid appeared in, in order
+    pub async fn episodes<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<usize>> {
+        episodes(ctx, &self.films).await
+    }
+
+    /// every film this droid appeared in
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    pub async fn films<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Film>> {
+        films(ctx, &self.films, after, before, first, last).await
     }
 
     /// The primary function of the droid.
     async fn primary_function(&self) -> Option<&str> {
         self.primary_function.as_deref()
     }
+
+    pub async fn species<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<Species>> {
+        let Some(species) = self.species else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DataLoader<SpeciesLoader>>();
+        Ok(loader.load_one(species).await?.map(Into::into))
+    }
 }
 
 /// A Star Wars starship
@@ -177,9 +418,9 @@ impl From<APIStarShip> for StarShip {
 }
 #[Object]
 impl StarShip {
-    /// the Id of the Starship
-    async fn id(&self) -> String {
-        self.0.id.clone()
+    /// global id of the Starship, see `Node`
+    pub async fn id(&self) -> ID {
+        global_id(STARSHIP, &self.0.id)
     }
 
     /// name of StarShip
@@ -190,6 +431,300 @@ impl StarShip {
     async fn length(&self) -> f64 {
         self.0.length
     }
+
+    /// the humans that fly this starship
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn pilots<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Human>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let filter = CharacterFilter {
+            starship_id: Some(self.0.id.clone()),
+            ..Default::default()
+        };
+        connection(after, before, first, last, |page| {
+            api.get_characters(filter, Vec::new(), page)
+        })
+        .await
+    }
+}
+
+/// One of the Star Wars films, the characters, starships and planets link to it
+pub struct Film(APIFilm);
+
+impl From<APIFilm> for Film {
+    fn from(value: APIFilm) -> Self {
+        Self(value)
+    }
+}
+
+#[Object]
+impl Film {
+    /// global id, see `Node`
+    pub async fn id(&self) -> ID {
+        global_id(FILM, &self.0.id)
+    }
+
+    /// number of the film in the saga, A New Hope is episode 4
+    async fn episode_id(&self) -> usize {
+        self.0.episode_id
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn release_date(&self) -> NaiveDate {
+        self.0.release_date
+    }
+
+    async fn director(&self) -> &str {
+        &self.0.director
+    }
+
+    /// comma separated
+    async fn producer(&self) -> &str {
+        &self.0.producer
+    }
+
+    async fn opening_crawl(&self) -> &str {
+        &self.0.opening_crawl
+    }
+
+    /// the humans and droids that appear in this film
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn characters<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Character>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let filter = CharacterFilter {
+            film_id: Some(self.0.id.clone()),
+            ..Default::default()
+        };
+        connection(after, before, first, last, |page| {
+            api.get_characters(filter, Vec::new(), page)
+        })
+        .await
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn starships<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<StarShip>> {
+        let loader = ctx.data_unchecked::<DataLoader<StarshipLoader>>();
+        connection(after, before, first, last, |page| async move {
+            let page = page.apply(self.0.starships.iter().map(|&idx| (idx, ())).collect());
+            let starships = loader
+                .load_many(page.items.iter().map(|&(idx, ())| idx))
+                .await?;
+            Ok::<_, async_graphql::Error>(page.fill(starships))
+        })
+        .await
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn planets<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Planet>> {
+        let loader = ctx.data_unchecked::<DataLoader<PlanetLoader>>();
+        connection(after, before, first, last, |page| async move {
+            let page = page.apply(self.0.planets.iter().map(|&idx| (idx, ())).collect());
+            let planets = loader
+                .load_many(page.items.iter().map(|&(idx, ())| idx))
+                .await?;
+            Ok::<_, async_graphql::Error>(page.fill(planets))
+        })
+        .await
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn species<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Species>> {
+        let loader = ctx.data_unchecked::<DataLoader<SpeciesLoader>>();
+        connection(after, before, first, last, |page| async move {
+            let page = page.apply(self.0.species.iter().map(|&idx| (idx, ())).collect());
+            let species = loader
+                .load_many(page.items.iter().map(|&(idx, ())| idx))
+                .await?;
+            Ok::<_, async_graphql::Error>(page.fill(species))
+        })
+        .await
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn vehicles<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Vehicle>> {
+        let loader = ctx.data_unchecked::<DataLoader<VehicleLoader>>();
+        connection(after, before, first, last, |page| async move {
+            let page = page.apply(self.0.vehicles.iter().map(|&idx| (idx, ())).collect());
+            let vehicles = loader
+                .load_many(page.items.iter().map(|&(idx, ())| idx))
+                .await?;
+            Ok::<_, async_graphql::Error>(page.fill(vehicles))
+        })
+        .await
+    }
+}
+
+/// a vehicle that stays in the atmosphere, think of a landspeeder or a speeder bike
+pub struct Vehicle(APIVehicle);
+
+impl From<APIVehicle> for Vehicle {
+    fn from(value: APIVehicle) -> Self {
+        Self(value)
+    }
+}
+
+#[Object]
+impl Vehicle {
+    /// global id, see `Node`
+    pub async fn id(&self) -> ID {
+        global_id(VEHICLE, &self.0.id)
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn model(&self) -> &str {
+        &self.0.model
+    }
+
+    /// comma separated
+    async fn manufacturer(&self) -> &str {
+        &self.0.manufacturer
+    }
+
+    /// `null` when nobody knows
+    async fn cost_in_credits(&self) -> Option<usize> {
+        self.0.cost_in_credits
+    }
+
+    async fn crew(&self) -> usize {
+        self.0.crew
+    }
+
+    async fn passengers(&self) -> usize {
+        self.0.passengers
+    }
+
+    /// the humans that drive this vehicle
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn pilots<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Human>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let filter = CharacterFilter {
+            vehicle_id: Some(self.0.id.clone()),
+            ..Default::default()
+        };
+        connection(after, before, first, last, |page| {
+            api.get_characters(filter, Vec::new(), page)
+        })
+        .await
+    }
+}
+
+/// a species of the Star Wars universe, droids are a species too
+pub struct Species(APISpecies);
+
+impl From<APISpecies> for Species {
+    fn from(value: APISpecies) -> Self {
+        Self(value)
+    }
+}
+
+#[Object]
+impl Species {
+    /// global id, see `Node`
+    pub async fn id(&self) -> ID {
+        global_id(SPECIES, &self.0.id)
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// mammal, artificial, ...
+    async fn classification(&self) -> &str {
+        &self.0.classification
+    }
+
+    async fn language(&self) -> &str {
+        &self.0.language
+    }
+
+    /// in standard years, `null` when it is indefinite or unknown
+    async fn average_lifespan(&self) -> Option<usize> {
+        self.0.average_lifespan
+    }
+
+    /// the planet the species comes from
+    async fn homeworld<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<Planet>> {
+        let Some(homeworld) = self.0.homeworld else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DataLoader<PlanetLoader>>();
+        Ok(loader.load_one(homeworld).await?.map(Into::into))
+    }
+
+    /// the humans and droids of this species
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn people<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Character>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let filter = CharacterFilter {
+            species_id: Some(self.0.id.clone()),
+            ..Default::default()
+        };
+        connection(after, before, first, last, |page| {
+            api.get_characters(filter, Vec::new(), page)
+        })
+        .await
+    }
 }
 
 /// a Star Wars planet, think of Alderaan or Coruscant
@@ -203,22 +738,187 @@ impl From<APIPlanet> for Planet {
 
 #[Object]
 impl Planet {
-    async fn id(&self) -> String {
-        self.0.id.clone()
+    /// global id, see `Node`
+    pub async fn id(&self) -> ID {
+        global_id(PLANET, &self.0.id)
     }
     async fn name(&self) -> String {
         self.0.name.clone()
     }
+
+    async fn climate(&self) -> &str {
+        &self.0.climate
+    }
+
+    /// in kilometers
+    async fn diameter(&self) -> usize {
+        self.0.diameter
+    }
+
+    async fn gravity(&self) -> &str {
+        &self.0.gravity
+    }
+
+    async fn population(&self) -> usize {
+        self.0.population
+    }
+
+    /// standard (sw) hours
+    async fn rotation_period(&self) -> usize {
+        self.0.rotation_period
+    }
+
+    /// standard (sw) days
+    async fn orbital_period(&self) -> usize {
+        self.0.orbital_period
+    }
+
+    /// the humans that have this planet as home planet
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn residents<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Human>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let filter = CharacterFilter {
+            home_planet_id: Some(self.0.id.clone()),
+            ..Default::default()
+        };
+        connection(after, before, first, last, |page| {
+            api.get_characters(filter, Vec::new(), page)
+        })
+        .await
+    }
+}
+
+/// The result of a successful `transact`, with the balances after the transfer
+#[derive(SimpleObject, Clone)]
+pub struct Transfer {
+    pub from_user_id: String,
+    pub to_user_id: String,
+    pub amount: i64,
+
+    /// credits left on the account of the sender
+    pub from_balance: i64,
+
+    /// credits on the account of the receiver
+    pub to_balance: i64,
+
+    /// the entry that was written to the ledger for this transfer
+    pub transaction: LedgerEntry,
+}
+
+/// One row of the `transactions` ledger
+#[derive(SimpleObject, sqlx::FromRow, Clone)]
+pub struct LedgerEntry {
+    pub id: i64,
+    pub from_user_id: String,
+    pub to_user_id: String,
+    pub amount: i64,
+    pub memo: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// New balance of an account after a transfer touched it
+#[derive(SimpleObject)]
+pub struct BalanceChange {
+    pub user_id: String,
+
+    /// credits on the account after the transfer
+    pub credits: i64,
+
+    /// the transfer that changed the balance
+    pub transaction: LedgerEntry,
+}
+
+/// Whether credits left or entered the account
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum TransferDirection {
+    Incoming,
+    Outgoing,
+}
+
+/// A ledger entry seen from the side of one account
+pub struct CreditHistoryEntry {
+    /// the account we are looking from
+    pub user_id: String,
+    pub entry: LedgerEntry,
+}
+
+#[Object]
+impl CreditHistoryEntry {
+    async fn id(&self) -> i64 {
+        self.entry.id
+    }
+
+    async fn direction(&self) -> TransferDirection {
+        if self.entry.from_user_id == self.user_id {
+            TransferDirection::Outgoing
+        } else {
+            TransferDirection::Incoming
+        }
+    }
+
+    /// change of the balance, negative when the credits were sent
+    async fn amount(&self) -> i64 {
+        if self.entry.from_user_id == self.user_id {
+            -self.entry.amount
+        } else {
+            self.entry.amount
+        }
+    }
+
+    /// user id of the other side of the transfer
+    async fn counterparty(&self) -> &str {
+        if self.entry.from_user_id == self.user_id {
+            &self.entry.to_user_id
+        } else {
+            &self.entry.from_user_id
+        }
+    }
+
+    async fn memo(&self) -> Option<&str> {
+        self.entry.memo.as_deref()
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.entry.created_at
+    }
 }
 
 // wou gwn weten hoe het werkt met interfaces
 #[derive(Interface)]
 #[allow(clippy::duplicated_attributes)]
 #[graphql(
-    field(name = "id", ty = "&str"),
+    field(name = "id", ty = "ID"),
     field(name = "name", ty = "&str"),
-    field(name = "friends", ty = "Vec<Character>"),
-    field(name = "appears_in", ty = "Vec<Episode>")
+    field(
+        name = "friends",
+        ty = "KeyConnection<Character>",
+        arg(name = "after", ty = "Option<String>"),
+        arg(name = "before", ty = "Option<String>"),
+        arg(name = "first", ty = "Option<i32>"),
+        arg(name = "last", ty = "Option<i32>")
+    ),
+    field(
+        name = "appears_in",
+        ty = "Vec<Episode>",
+        deprecation = "only knows the original trilogy, use `episodes` or `films`"
+    ),
+    field(name = "episodes", ty = "Vec<usize>"),
+    field(
+        name = "films",
+        ty = "KeyConnection<Film>",
+        arg(name = "after", ty = "Option<String>"),
+        arg(name = "before", ty = "Option<String>"),
+        arg(name = "first", ty = "Option<i32>"),
+        arg(name = "last", ty = "Option<i32>")
+    ),
+    field(name = "species", ty = "Option<Species>")
 )]
 pub enum Character {
     Human(Human),
@@ -234,3 +934,273 @@ impl From<APICharacter> for Character {
         }
     }
 }
+
+/// Input for `createHuman`
+#[derive(InputObject)]
+pub struct NewHuman {
+    pub name: String,
+
+    /// deprecated, use `episodes`
+    #[graphql(default)]
+    pub appears_in: Vec<Episode>,
+
+    /// the `episodeId` of every film this character appears in
+    #[graphql(default)]
+    pub episodes: Vec<usize>,
+
+    /// ids of the characters this human is friends with
+    #[graphql(default)]
+    pub friend_ids: Vec<String>,
+
+    pub home_planet_id: Option<String>,
+
+    pub species_id: Option<String>,
+
+    /// deprecated, use `starshipIds`
+    pub starship_id: Option<String>,
+
+    /// ids of the starships this human flies
+    #[graphql(default)]
+    pub starship_ids: Vec<String>,
+
+    /// mass in kg
+    #[graphql(default)]
+    pub mass: usize,
+}
+
+/// Input for `createDroid`
+#[derive(InputObject)]
+pub struct NewDroid {
+    pub name: String,
+
+    /// deprecated, use `episodes`
+    #[graphql(default)]
+    pub appears_in: Vec<Episode>,
+
+    /// the `episodeId` of every film this character appears in
+    #[graphql(default)]
+    pub episodes: Vec<usize>,
+
+    /// ids of the characters this droid is friends with
+    #[graphql(default)]
+    pub friend_ids: Vec<String>,
+
+    pub primary_function: Option<String>,
+
+    pub species_id: Option<String>,
+
+    /// mass in kg
+    #[graphql(default)]
+    pub mass: usize,
+}
+
+/// Input for `updateCharacter`, fields that are left out stay the same
+#[derive(InputObject)]
+pub struct UpdateCharacter {
+    pub name: Option<String>,
+
+    /// deprecated, use `episodes`
+    pub appears_in: Option<Vec<Episode>>,
+
+    /// replaces the films of the character by the films of these `episodeId`s
+    pub episodes: Option<Vec<usize>>,
+
+    /// replaces all friends of the character
+    pub friend_ids: Option<Vec<String>>,
+
+    pub mass: Option<usize>,
+
+    /// only for droids
+    pub primary_function: Option<String>,
+
+    pub species_id: Option<String>,
+}
+
+impl NewHuman {
+    /// the episodes of `appearsIn` and `episodes` together
+    pub fn film_episodes(&self) -> Vec<usize> {
+        film_episodes(&self.appears_in, &self.episodes)
+    }
+}
+
+impl NewDroid {
+    /// the episodes of `appearsIn` and `episodes` together
+    pub fn film_episodes(&self) -> Vec<usize> {
+        film_episodes(&self.appears_in, &self.episodes)
+    }
+}
+
+impl UpdateCharacter {
+    /// the episodes of `appearsIn` and `episodes` together, `None` when the films stay
+    pub fn film_episodes(&self) -> Option<Vec<usize>> {
+        if self.appears_in.is_none() && self.episodes.is_none() {
+            return None;
+        }
+        Some(film_episodes(
+            self.appears_in.as_deref().unwrap_or_default(),
+            self.episodes.as_deref().unwrap_or_default(),
+        ))
+    }
+}
+
+fn film_episodes(appears_in: &[Episode], episodes: &[usize]) -> Vec<usize> {
+    let mut all: Vec<usize> = appears_in
+        .iter()
+        .map(|episode| episode.number())
+        .chain(episodes.iter().copied())
+        .collect();
+    all.sort_unstable();
+    all.dedup();
+    all
+}
+
+/// Input for `createFilm`
+#[derive(InputObject)]
+pub struct NewFilm {
+    /// number of the film in the saga, every film has its own
+    pub episode_id: usize,
+
+    pub title: String,
+
+    pub release_date: NaiveDate,
+
+    pub director: String,
+
+    /// comma separated
+    pub producer: String,
+
+    #[graphql(default)]
+    pub opening_crawl: String,
+
+    /// ids of the humans and droids that appear in the film
+    #[graphql(default)]
+    pub character_ids: Vec<String>,
+
+    /// ids of the starships in the film
+    #[graphql(default)]
+    pub starship_ids: Vec<String>,
+
+    /// ids of the planets in the film
+    #[graphql(default)]
+    pub planet_ids: Vec<String>,
+}
+
+/// Input for `createStarship`
+#[derive(InputObject)]
+pub struct NewStarShip {
+    pub name: String,
+
+    /// length in meters
+    pub length: f64,
+}
+
+/// Input for `updateStarship`, fields that are left out stay the same
+#[derive(InputObject)]
+pub struct UpdateStarShip {
+    pub name: Option<String>,
+
+    /// length in meters
+    pub length: Option<f64>,
+}
+
+/// Input for `createPlanet`
+#[derive(InputObject)]
+pub struct NewPlanet {
+    pub name: String,
+
+    pub climate: String,
+
+    /// in kilometers
+    pub diameter: usize,
+
+    pub gravity: String,
+
+    pub population: usize,
+
+    /// standard (sw) hours
+    pub rotation_period: usize,
+
+    /// standard (sw) days
+    pub orbital_period: usize,
+}
+
+/// Input for `updatePlanet`, fields that are left out stay the same
+#[derive(InputObject)]
+pub struct UpdatePlanet {
+    pub name: Option<String>,
+
+    pub climate: Option<String>,
+
+    /// in kilometers
+    pub diameter: Option<usize>,
+
+    pub gravity: Option<String>,
+
+    pub population: Option<usize>,
+
+    /// standard (sw) hours
+    pub rotation_period: Option<usize>,
+
+    /// standard (sw) days
+    pub orbital_period: Option<usize>,
+}
+
+/// Which characters `characters` returns, every field that is set has to match
+#[derive(InputObject, Default)]
+pub struct CharacterFilter {
+    /// only humans (`true`) or only droids (`false`)
+    pub is_human: Option<bool>,
+
+    /// appeared in this episode
+    pub episode: Option<Episode>,
+
+    /// at least this heavy, in kg
+    pub min_mass: Option<usize>,
+
+    /// at most this heavy, in kg
+    pub max_mass: Option<usize>,
+
+    /// part of the name, upper or lower case doesn't matter
+    pub name_contains: Option<String>,
+
+    /// whether the character has a starship, droids never have one
+    pub has_starship: Option<bool>,
+
+    /// lives on the planet with this id
+    pub home_planet_id: Option<String>,
+
+    /// is one of the pilots of the starship with this id
+    pub starship_id: Option<String>,
+
+    /// appeared in the film with this id
+    pub film_id: Option<String>,
+
+    /// is of the species with this id
+    pub species_id: Option<String>,
+
+    /// drives the vehicle with this id
+    pub vehicle_id: Option<String>,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum CharacterOrderField {
+    Id,
+    Name,
+    Mass,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Default)]
+pub enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// One sort key of `characters`, the first one in `orderBy` weighs the most
+#[derive(InputObject, Copy, Clone)]
+pub struct CharacterOrder {
+    pub field: CharacterOrderField,
+
+    #[graphql(default)]
+    pub direction: OrderDirection,
+}
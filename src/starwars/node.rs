@@ -0,0 +1,62 @@
+use async_graphql::{Interface, ID};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use super::{
+    errors::ApiError,
+    models::{Droid, Film, Human, Planet, Species, StarShip, Vehicle},
+};
+
+// the GraphQL type names, they are the prefix of the global ids
+pub const HUMAN: &str = "Human";
+pub const DROID: &str = "Droid";
+pub const STARSHIP: &str = "StarShip";
+pub const PLANET: &str = "Planet";
+pub const FILM: &str = "Film";
+pub const SPECIES: &str = "Species";
+pub const VEHICLE: &str = "Vehicle";
+
+pub const CHARACTER: &[&str] = &[HUMAN, DROID];
+
+/// Relay global object identification.
+/// The ids of characters, starships, planets, ... are only unique per type ("1" is luke and the
+/// X-Wing), so `id` is `base64("Human:1")` and `node(id)` can find anything back from it.
+#[derive(Interface)]
+#[graphql(field(name = "id", ty = "ID"))]
+pub enum Node {
+    Human(Human),
+    Droid(Droid),
+    StarShip(StarShip),
+    Planet(Planet),
+    Film(Film),
+    Species(Species),
+    Vehicle(Vehicle),
+}
+
+pub fn global_id(kind: &str, id: &str) -> ID {
+    ID(STANDARD.encode(format!("{kind}:{id}")))
+}
+
+/// the type and the id in the store of a global id, `None` if it isn't one
+pub fn parse_global_id(id: &str) -> Option<(String, String)> {
+    let decoded = String::from_utf8(STANDARD.decode(id).ok()?).ok()?;
+    let (kind, id) = decoded.split_once(':')?;
+    Some((kind.into(), id.into()))
+}
+
+/// The id in the store for an argument that has to be one of `kinds`.
+/// The plain ids from before the global ids are still accepted so older clients keep working,
+/// a global id of another type is an error.
+pub fn local_id(id: String, kinds: &[&str]) -> Result<String, ApiError> {
+    match parse_global_id(&id) {
+        Some((kind, local)) if kinds.contains(&kind.as_str()) => Ok(local),
+        Some((kind, _)) => Err(ApiError::InvalidInput(format!(
+            "`{id}` is the id of a {kind}, expected a {}",
+            kinds.join(" or ")
+        ))),
+        None => Ok(id),
+    }
+}
+
+pub fn local_ids(ids: Vec<String>, kinds: &[&str]) -> Result<Vec<String>, ApiError> {
+    ids.into_iter().map(|id| local_id(id, kinds)).collect()
+}
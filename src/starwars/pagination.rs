@@ -0,0 +1,300 @@
+use std::{collections::HashMap, future::Future, ops::Range};
+
+use async_graphql::{
+    connection::{query, Connection, Edge, OpaqueCursor},
+    ErrorExtensions, OutputType, Result, SimpleObject,
+};
+
+use super::errors::ApiError;
+
+/// page size of a list when the client passes neither `first` nor `last`
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 20;
+pub(crate) const MAX_PAGE_SIZE: usize = 100;
+
+/// The cursors are the keys the store uses for the item (key in the table or `key` column),
+/// base64 encoded so clients don't start doing math with them.
+pub type KeyCursor = OpaqueCursor<usize>;
+
+/// Relay connection over a list of the store, with `totalCount` next to `pageInfo`
+pub type KeyConnection<Node> = Connection<KeyCursor, Node, TotalCount>;
+
+#[derive(SimpleObject)]
+pub struct TotalCount {
+    /// number of items in the whole list, not only in this page
+    pub total_count: usize,
+}
+
+/// Which part of a list the client asked for, `after` and `before` are store keys.
+/// At most one of `first` and `last` is set, the `query` helper of async-graphql checks that.
+#[derive(Debug, Clone, Copy)]
+pub struct PageRequest {
+    pub after: Option<usize>,
+    pub before: Option<usize>,
+    pub first: Option<usize>,
+    pub last: Option<usize>,
+}
+
+/// A page of a list, every item comes with its key so it can be used as cursor
+pub struct Page<T> {
+    pub items: Vec<(usize, T)>,
+    pub total_count: usize,
+    pub has_previous_page: bool,
+    pub has_next_page: bool,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self
+                .items
+                .into_iter()
+                .map(|(key, item)| (key, f(item)))
+                .collect(),
+            total_count: self.total_count,
+            has_previous_page: self.has_previous_page,
+            has_next_page: self.has_next_page,
+        }
+    }
+}
+
+impl Page<()> {
+    /// Puts the loaded items in a page of keys.
+    /// Keys without an item (deleted after the page was made) are left out.
+    pub fn fill<T>(self, mut loaded: HashMap<usize, T>) -> Page<T> {
+        Page {
+            items: self
+                .items
+                .into_iter()
+                .filter_map(|(key, ())| Some((key, loaded.remove(&key)?)))
+                .collect(),
+            total_count: self.total_count,
+            has_previous_page: self.has_previous_page,
+            has_next_page: self.has_next_page,
+        }
+    }
+}
+
+impl PageRequest {
+    /// Pages `items` that are sorted by key, like a table or `ORDER BY key`.
+    /// A cursor of an item that got deleted in the meantime still works.
+    pub fn apply<T>(&self, items: Vec<(usize, T)>) -> Page<T> {
+        let start = self
+            .after
+            .map_or(0, |after| items.partition_point(|&(key, _)| key <= after));
+        let end = self.before.map_or(items.len(), |before| {
+            items.partition_point(|&(key, _)| key < before)
+        });
+        self.window(items, start..end)
+    }
+
+    /// Pages `items` in the order they are in, like the friends of a character.
+    /// Here a cursor has to be one of the keys in the list.
+    pub fn apply_ordered<T>(&self, items: Vec<(usize, T)>) -> Result<Page<T>, ApiError> {
+        let position = |cursor: usize| {
+            items
+                .iter()
+                .position(|&(key, _)| key == cursor)
+                .ok_or_else(|| ApiError::InvalidInput("cursor is not part of this list".into()))
+        };
+        let start = match self.after {
+            Some(after) => position(after)? + 1,
+            None => 0,
+        };
+        let end = match self.before {
+            Some(before) => position(before)?,
+            None => items.len(),
+        };
+        Ok(self.window(items, start..end))
+    }
+
+    /// `range` is what lies between the cursors, `first` or `last` cut it down further
+    fn window<T>(&self, mut items: Vec<(usize, T)>, mut range: Range<usize>) -> Page<T> {
+        range.end = range.end.max(range.start);
+        if let Some(first) = self.first {
+            range.end = range.end.min(range.start + first);
+        }
+        if let Some(last) = self.last {
+            range.start = range.start.max(range.end.saturating_sub(last));
+        }
+        let total_count = items.len();
+        let has_previous_page = range.start > 0;
+        let has_next_page = range.end < total_count;
+        items.truncate(range.end);
+        items.drain(..range.start);
+        Page {
+            items,
+            total_count,
+            has_previous_page,
+            has_next_page,
+        }
+    }
+}
+
+/// Cost of a list for `limit_complexity`: every item of the page costs what the query asks
+/// of it, so `friends(first: 100)` costs a hundred times `friends(first: 1)`.
+/// Without `first` or `last` the page is `DEFAULT_PAGE_SIZE` items, like `connection` does.
+pub fn page_cost(first: Option<i32>, last: Option<i32>, child_complexity: usize) -> usize {
+    let size = match first.or(last) {
+        Some(size) => usize::try_from(size).unwrap_or(0).min(MAX_PAGE_SIZE),
+        None => DEFAULT_PAGE_SIZE,
+    };
+    size.saturating_mul(child_complexity).saturating_add(1)
+}
+
+/// Runs `fetch` for the page the client asked for and turns it into a connection.
+/// Without `first` or `last` you get the first `DEFAULT_PAGE_SIZE` items,
+/// a page is never bigger than `MAX_PAGE_SIZE`.
+pub async fn connection<T, Node, E, F, Fut>(
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+    fetch: F,
+) -> Result<KeyConnection<Node>>
+where
+    T: Into<Node>,
+    Node: OutputType,
+    F: FnOnce(PageRequest) -> Fut,
+    E: ErrorExtensions,
+    Fut: Future<Output = Result<Page<T>, E>>,
+{
+    query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<KeyCursor>, before: Option<KeyCursor>, first, last| async move {
+            let first = match (first, last) {
+                (None, None) => Some(DEFAULT_PAGE_SIZE),
+                (first, _) => first.map(|first| first.min(MAX_PAGE_SIZE)),
+            };
+            let page = fetch(PageRequest {
+                after: after.map(|cursor| cursor.0),
+                before: before.map(|cursor| cursor.0),
+                first,
+                last: last.map(|last| last.min(MAX_PAGE_SIZE)),
+            })
+            .await
+            .map_err(|e| e.extend())?;
+
+            let mut connection = Connection::with_additional_fields(
+                page.has_previous_page,
+                page.has_next_page,
+                TotalCount {
+                    total_count: page.total_count,
+                },
+            );
+            connection.edges.extend(
+                page.items
+                    .into_iter()
+                    .map(|(key, item)| Edge::new(OpaqueCursor(key), item.into())),
+            );
+            Ok::<_, async_graphql::Error>(connection)
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        after: Option<usize>,
+        before: Option<usize>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> PageRequest {
+        PageRequest {
+            after,
+            before,
+            first,
+            last,
+        }
+    }
+
+    /// the keys of the page, `hasPreviousPage` and `hasNextPage`
+    fn keys(page: Page<char>) -> (Vec<usize>, bool, bool) {
+        (
+            page.items.iter().map(|&(key, _)| key).collect(),
+            page.has_previous_page,
+            page.has_next_page,
+        )
+    }
+
+    /// keys with holes, like after some deletes
+    fn items() -> Vec<(usize, char)> {
+        vec![(1, 'a'), (3, 'b'), (4, 'c'), (7, 'd'), (9, 'e')]
+    }
+
+    #[test]
+    fn apply_pages_from_the_start_and_the_end() {
+        let all = request(None, None, None, None).apply(items());
+        assert_eq!(keys(all), (vec![1, 3, 4, 7, 9], false, false));
+        let first = request(None, None, Some(2), None).apply(items());
+        assert_eq!(first.total_count, 5);
+        assert_eq!(keys(first), (vec![1, 3], false, true));
+        let last = request(None, None, None, Some(2)).apply(items());
+        assert_eq!(keys(last), (vec![7, 9], true, false));
+        let none = request(None, None, Some(0), None).apply(items());
+        assert_eq!(keys(none), (vec![], false, true));
+    }
+
+    #[test]
+    fn apply_pages_between_cursors() {
+        let next = request(Some(3), None, Some(2), None).apply(items());
+        assert_eq!(keys(next), (vec![4, 7], true, true));
+        let previous = request(None, Some(7), None, Some(2)).apply(items());
+        assert_eq!(keys(previous), (vec![3, 4], true, true));
+        let between = request(Some(1), Some(9), None, None).apply(items());
+        assert_eq!(keys(between), (vec![3, 4, 7], true, true));
+        let both = request(Some(1), None, Some(3), Some(2)).apply(items());
+        assert_eq!(keys(both), (vec![4, 7], true, true));
+    }
+
+    #[test]
+    fn apply_takes_cursors_of_deleted_items() {
+        let after_hole = request(Some(5), None, Some(1), None).apply(items());
+        assert_eq!(keys(after_hole), (vec![7], true, true));
+        let before_hole = request(None, Some(2), None, None).apply(items());
+        assert_eq!(keys(before_hole), (vec![1], false, true));
+        let past_the_end = request(Some(100), None, Some(2), None).apply(items());
+        assert_eq!(keys(past_the_end), (vec![], true, false));
+        let crossed = request(Some(7), Some(3), None, None).apply(items());
+        assert_eq!(keys(crossed), (vec![], true, true));
+    }
+
+    #[test]
+    fn apply_ordered_keeps_the_order_of_the_list() {
+        let ordered = || vec![(9, 'e'), (1, 'a'), (4, 'c')];
+        let next = request(Some(9), None, Some(1), None).apply_ordered(ordered());
+        assert_eq!(keys(next.unwrap()), (vec![1], true, true));
+        let previous = request(None, Some(4), None, Some(5)).apply_ordered(ordered());
+        assert_eq!(keys(previous.unwrap()), (vec![9, 1], false, true));
+    }
+
+    #[test]
+    fn apply_ordered_rejects_cursors_outside_the_list() {
+        let after = request(Some(5), None, None, None).apply_ordered(items());
+        assert_eq!(after.err().unwrap().code(), "BAD_USER_INPUT");
+        let before = request(None, Some(2), None, None).apply_ordered(items());
+        assert_eq!(before.err().unwrap().code(), "BAD_USER_INPUT");
+    }
+
+    #[test]
+    fn fill_leaves_out_what_is_gone() {
+        let page = request(None, None, Some(3), None).apply(vec![(1, ()), (2, ()), (3, ())]);
+        let filled = page.fill(HashMap::from([(1, 'a'), (3, 'c')]));
+        assert_eq!(filled.items, [(1, 'a'), (3, 'c')]);
+        assert!(!filled.has_next_page);
+    }
+
+    #[test]
+    fn page_cost_counts_the_page_size() {
+        assert_eq!(page_cost(Some(10), None, 3), 31);
+        assert_eq!(page_cost(None, Some(10), 3), 31);
+        assert_eq!(page_cost(None, None, 3), 1 + DEFAULT_PAGE_SIZE * 3);
+        assert_eq!(page_cost(Some(10_000), None, 3), 1 + MAX_PAGE_SIZE * 3);
+        assert_eq!(page_cost(Some(-5), None, 3), 1);
+    }
+}
@@ -0,0 +1,1622 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+
+use crate::starwars::{
+    data::{APICharacter, APIFilm, APIPlanet, APISpecies, APIStarShip, APIVehicle},
+    dataset::{Dataset, Indexed},
+    errors::ApiError,
+    models::{
+        CharacterFilter, CharacterOrder, CharacterOrderField, NewDroid, NewFilm, NewHuman,
+        NewPlanet, NewStarShip, OrderDirection, UpdateCharacter, UpdatePlanet, UpdateStarShip,
+    },
+    pagination::{Page, PageRequest},
+    repository::{
+        episode_taken, validate_film, validate_length, validate_name, validate_update,
+        StarWarsRepository,
+    },
+};
+
+/// every column of `APICharacter`, friends, films and starships are folded into arrays
+const SELECT_CHARACTER: &str = "
+    SELECT c.key, c.id, c.is_human, c.name, c.home_planet, c.species, c.primary_function, c.mass,
+        ARRAY(SELECT f.friend FROM friendships f WHERE f.character = c.key ORDER BY f.position)
+            AS friends,
+        ARRAY(SELECT fc.film FROM film_characters fc WHERE fc.character = c.key ORDER BY fc.film)
+            AS films,
+        ARRAY(SELECT p.starship FROM pilots p WHERE p.pilot = c.key ORDER BY p.starship)
+            AS star_ships,
+        ARRAY(SELECT v.vehicle FROM vehicle_pilots v WHERE v.pilot = c.key ORDER BY v.vehicle)
+            AS vehicles
+    FROM characters c";
+
+const SELECT_STARSHIP: &str = "SELECT key, id, name, length FROM starships";
+
+const SELECT_PLANET: &str = "
+    SELECT key, id, name, climate, diameter, gravity, population, rotation_period, orbital_period
+    FROM planets";
+
+const SELECT_FILM: &str = "
+    SELECT f.key, f.id, f.episode_id, f.title, f.release_date, f.director, f.producer,
+        f.opening_crawl,
+        ARRAY(SELECT fs.starship FROM film_starships fs WHERE fs.film = f.key ORDER BY fs.starship)
+            AS starships,
+        ARRAY(SELECT fp.planet FROM film_planets fp WHERE fp.film = f.key ORDER BY fp.planet)
+            AS planets,
+        ARRAY(SELECT fs.species FROM film_species fs WHERE fs.film = f.key ORDER BY fs.species)
+            AS species,
+        ARRAY(SELECT fv.vehicle FROM film_vehicles fv WHERE fv.film = f.key ORDER BY fv.vehicle)
+            AS vehicles
+    FROM films f";
+
+const SELECT_SPECIES: &str = "
+    SELECT key, id, name, classification, language, average_lifespan, homeworld FROM species";
+
+const SELECT_VEHICLE: &str = "
+    SELECT key, id, name, model, manufacturer, cost_in_credits, crew, passengers FROM vehicles";
+
+/// The same api as `StarWarsAPI`, but everything lives in postgres so every replica sees
+/// the same data and mutations survive a restart.
+/// The `key` columns of the tables are the indices the resolvers pass around.
+pub struct PgStarWarsAPI {
+    pool: PgPool,
+}
+
+impl PgStarWarsAPI {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct CharacterRow {
+    key: i32,
+    id: String,
+    is_human: bool,
+    name: String,
+    home_planet: Option<i32>,
+    species: Option<i32>,
+    star_ships: Vec<i32>,
+    vehicles: Vec<i32>,
+    primary_function: Option<String>,
+    mass: i64,
+    friends: Vec<i32>,
+    films: Vec<i32>,
+}
+
+impl From<CharacterRow> for APICharacter {
+    fn from(row: CharacterRow) -> Self {
+        APICharacter {
+            is_human: row.is_human,
+            id: row.id,
+            name: row.name,
+            friends: row.friends.into_iter().map(|key| key as usize).collect(),
+            films: row.films.into_iter().map(|key| key as usize).collect(),
+            home_planet: row.home_planet.map(|key| key as usize),
+            star_ships: row.star_ships.into_iter().map(|key| key as usize).collect(),
+            vehicles: row.vehicles.into_iter().map(|key| key as usize).collect(),
+            species: row.species.map(|key| key as usize),
+            primary_function: row.primary_function,
+            mass: row.mass as usize,
+        }
+    }
+}
+
+/// a row together with its `key`, for lookups by index
+#[derive(sqlx::FromRow)]
+struct Keyed<T> {
+    key: i32,
+    #[sqlx(flatten)]
+    row: T,
+}
+
+#[derive(sqlx::FromRow)]
+struct StarShipRow {
+    id: String,
+    name: String,
+    length: f64,
+}
+
+impl From<StarShipRow> for APIStarShip {
+    fn from(row: StarShipRow) -> Self {
+        APIStarShip {
+            id: row.id,
+            name: row.name,
+            length: row.length,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PlanetRow {
+    id: String,
+    name: String,
+    climate: String,
+    diameter: i64,
+    gravity: String,
+    population: i64,
+    rotation_period: i64,
+    orbital_period: i64,
+}
+
+impl From<PlanetRow> for APIPlanet {
+    fn from(row: PlanetRow) -> Self {
+        APIPlanet {
+            id: row.id,
+            name: row.name,
+            climate: row.climate,
+            diameter: row.diameter as usize,
+            gravity: row.gravity,
+            population: row.population as usize,
+            rotation_period: row.rotation_period as usize,
+            orbital_period: row.orbital_period as usize,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct FilmRow {
+    id: String,
+    episode_id: i32,
+    title: String,
+    release_date: NaiveDate,
+    director: String,
+    producer: String,
+    opening_crawl: String,
+    starships: Vec<i32>,
+    planets: Vec<i32>,
+    species: Vec<i32>,
+    vehicles: Vec<i32>,
+}
+
+impl From<FilmRow> for APIFilm {
+    fn from(row: FilmRow) -> Self {
+        APIFilm {
+            id: row.id,
+            episode_id: row.episode_id as usize,
+            title: row.title,
+            release_date: row.release_date,
+            director: row.director,
+            producer: row.producer,
+            opening_crawl: row.opening_crawl,
+            starships: row.starships.into_iter().map(|key| key as usize).collect(),
+            planets: row.planets.into_iter().map(|key| key as usize).collect(),
+            species: row.species.into_iter().map(|key| key as usize).collect(),
+            vehicles: row.vehicles.into_iter().map(|key| key as usize).collect(),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SpeciesRow {
+    id: String,
+    name: String,
+    classification: String,
+    language: String,
+    average_lifespan: Option<i64>,
+    homeworld: Option<i32>,
+}
+
+impl From<SpeciesRow> for APISpecies {
+    fn from(row: SpeciesRow) -> Self {
+        APISpecies {
+            id: row.id,
+            name: row.name,
+            classification: row.classification,
+            language: row.language,
+            average_lifespan: row.average_lifespan.map(|years| years as usize),
+            homeworld: row.homeworld.map(|key| key as usize),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct VehicleRow {
+    id: String,
+    name: String,
+    model: String,
+    manufacturer: String,
+    cost_in_credits: Option<i64>,
+    crew: i64,
+    passengers: i64,
+}
+
+impl From<VehicleRow> for APIVehicle {
+    fn from(row: VehicleRow) -> Self {
+        APIVehicle {
+            id: row.id,
+            name: row.name,
+            model: row.model,
+            manufacturer: row.manufacturer,
+            cost_in_credits: row.cost_in_credits.map(|cost| cost as usize),
+            crew: row.crew as usize,
+            passengers: row.passengers as usize,
+        }
+    }
+}
+
+#[async_trait]
+impl StarWarsRepository for PgStarWarsAPI {
+    async fn get_saga_hero(&self) -> Result<APICharacter, ApiError> {
+        hero(&self.pool, None).await
+    }
+
+    async fn get_hero(&self, episode: usize) -> Result<APICharacter, ApiError> {
+        hero(&self.pool, Some(episode)).await
+    }
+
+    async fn set_hero(
+        &self,
+        episode: Option<usize>,
+        character_id: String,
+    ) -> Result<APICharacter, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let (key, _) = lock_character(&mut tx, &character_id).await?;
+        if let Some(episode) = episode {
+            let film: Option<i32> =
+                sqlx::query_scalar("SELECT key FROM films WHERE episode_id = $1 FOR SHARE")
+                    .bind(hero_episode_id(Some(episode)))
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            film.ok_or_else(|| ApiError::NotFound {
+                kind: "film",
+                id: format!("episode {episode}"),
+            })?;
+        }
+        sqlx::query(
+            "INSERT INTO heroes(episode_id, character) VALUES ($1, $2)
+             ON CONFLICT (episode_id) DO UPDATE SET character = EXCLUDED.character",
+        )
+        .bind(hero_episode_id(episode))
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+        let character = character_by_key(&mut tx, key).await?;
+        tx.commit().await?;
+        Ok(character)
+    }
+
+    async fn export(&self) -> Result<Dataset, ApiError> {
+        // one snapshot of the database for every query, even when mutations come in
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+        let characters: Vec<CharacterRow> =
+            sqlx::query_as(&format!("{SELECT_CHARACTER} ORDER BY c.key"))
+                .fetch_all(&mut *tx)
+                .await?;
+        let heroes: Vec<(i32, i32)> = sqlx::query_as("SELECT episode_id, character FROM heroes")
+            .fetch_all(&mut *tx)
+            .await?;
+        let all = Indexed {
+            planets: keyed::<PlanetRow, _>(&mut tx, SELECT_PLANET).await?,
+            starships: keyed::<StarShipRow, _>(&mut tx, SELECT_STARSHIP).await?,
+            vehicles: keyed::<VehicleRow, _>(&mut tx, SELECT_VEHICLE).await?,
+            species: keyed::<SpeciesRow, _>(&mut tx, SELECT_SPECIES).await?,
+            films: keyed::<FilmRow, _>(&mut tx, SELECT_FILM).await?,
+            characters: characters
+                .into_iter()
+                .map(|row| (row.key as usize, row.into()))
+                .collect(),
+            heroes: heroes
+                .into_iter()
+                .map(|(episode_id, key)| {
+                    (
+                        (episode_id > 0).then_some(episode_id as usize),
+                        key as usize,
+                    )
+                })
+                .collect(),
+        };
+        tx.commit().await?;
+        Ok(all.into())
+    }
+
+    async fn get_human(&self, id: String) -> Result<Option<APICharacter>, ApiError> {
+        Ok(sqlx::query_as::<_, CharacterRow>(&format!(
+            "{SELECT_CHARACTER} WHERE c.id = $1 AND c.is_human"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Into::into))
+    }
+
+    async fn get_humans(&self, page: PageRequest) -> Result<Page<APICharacter>, ApiError> {
+        characters_page(&self.pool, true, page).await
+    }
+
+    async fn get_droid(&self, id: String) -> Result<Option<APICharacter>, ApiError> {
+        Ok(sqlx::query_as::<_, CharacterRow>(&format!(
+            "{SELECT_CHARACTER} WHERE c.id = $1 AND NOT c.is_human"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Into::into))
+    }
+
+    async fn get_droids(&self, page: PageRequest) -> Result<Page<APICharacter>, ApiError> {
+        characters_page(&self.pool, false, page).await
+    }
+
+    async fn get_characters(
+        &self,
+        filter: CharacterFilter,
+        order_by: Vec<CharacterOrder>,
+        page: PageRequest,
+    ) -> Result<Page<APICharacter>, ApiError> {
+        let page = filtered_characters(filter, &order_by)?
+            .page(&self.pool, page)
+            .await?;
+        load_page(&self.pool, page).await
+    }
+
+    async fn get_characters_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APICharacter>, ApiError> {
+        characters_by_key(&self.pool, &to_keys(idx)).await
+    }
+
+    async fn get_starship(&self, id: String) -> Result<Option<APIStarShip>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, StarShipRow>(&format!("{SELECT_STARSHIP} WHERE id = $1"))
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(Into::into),
+        )
+    }
+
+    async fn get_planet(&self, id: String) -> Result<Option<APIPlanet>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, PlanetRow>(&format!("{SELECT_PLANET} WHERE id = $1"))
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(Into::into),
+        )
+    }
+
+    async fn get_planets(&self, page: PageRequest) -> Result<Page<APIPlanet>, ApiError> {
+        let page = key_page(&self.pool, "planets", page).await?;
+        let idx: Vec<usize> = page.items.iter().map(|&(key, _)| key).collect();
+        Ok(page.fill(self.get_planets_by_idx(&idx).await?))
+    }
+
+    async fn get_film(&self, id: String) -> Result<Option<APIFilm>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, FilmRow>(&format!("{SELECT_FILM} WHERE f.id = $1"))
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(Into::into),
+        )
+    }
+
+    async fn get_films(&self, page: PageRequest) -> Result<Page<APIFilm>, ApiError> {
+        let page = key_page(&self.pool, "films", page).await?;
+        let idx: Vec<usize> = page.items.iter().map(|&(key, _)| key).collect();
+        Ok(page.fill(self.get_films_by_idx(&idx).await?))
+    }
+
+    async fn get_films_by_idx(&self, idx: &[usize]) -> Result<HashMap<usize, APIFilm>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, Keyed<FilmRow>>(&format!("{SELECT_FILM} WHERE f.key = ANY($1)"))
+                .bind(to_keys(idx))
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|keyed| (keyed.key as usize, keyed.row.into()))
+                .collect(),
+        )
+    }
+
+    async fn get_species(&self, id: String) -> Result<Option<APISpecies>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, SpeciesRow>(&format!("{SELECT_SPECIES} WHERE id = $1"))
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(Into::into),
+        )
+    }
+
+    async fn get_all_species(&self, page: PageRequest) -> Result<Page<APISpecies>, ApiError> {
+        let page = key_page(&self.pool, "species", page).await?;
+        let idx: Vec<usize> = page.items.iter().map(|&(key, _)| key).collect();
+        Ok(page.fill(self.get_species_by_idx(&idx).await?))
+    }
+
+    async fn get_species_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APISpecies>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, Keyed<SpeciesRow>>(&format!(
+                "{SELECT_SPECIES} WHERE key = ANY($1)"
+            ))
+            .bind(to_keys(idx))
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|keyed| (keyed.key as usize, keyed.row.into()))
+            .collect(),
+        )
+    }
+
+    async fn get_vehicle(&self, id: String) -> Result<Option<APIVehicle>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, VehicleRow>(&format!("{SELECT_VEHICLE} WHERE id = $1"))
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(Into::into),
+        )
+    }
+
+    async fn get_vehicles(&self, page: PageRequest) -> Result<Page<APIVehicle>, ApiError> {
+        let page = key_page(&self.pool, "vehicles", page).await?;
+        let idx: Vec<usize> = page.items.iter().map(|&(key, _)| key).collect();
+        Ok(page.fill(self.get_vehicles_by_idx(&idx).await?))
+    }
+
+    async fn get_vehicles_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIVehicle>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, Keyed<VehicleRow>>(&format!(
+                "{SELECT_VEHICLE} WHERE key = ANY($1)"
+            ))
+            .bind(to_keys(idx))
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|keyed| (keyed.key as usize, keyed.row.into()))
+            .collect(),
+        )
+    }
+
+    async fn get_starships(&self, page: PageRequest) -> Result<Page<APIStarShip>, ApiError> {
+        let page = key_page(&self.pool, "starships", page).await?;
+        let idx: Vec<usize> = page.items.iter().map(|&(key, _)| key).collect();
+        Ok(page.fill(self.get_starships_by_idx(&idx).await?))
+    }
+
+    async fn get_starships_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIStarShip>, ApiError> {
+        Ok(sqlx::query_as::<_, Keyed<StarShipRow>>(&format!(
+            "{SELECT_STARSHIP} WHERE key = ANY($1)"
+        ))
+        .bind(to_keys(idx))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|keyed| (keyed.key as usize, keyed.row.into()))
+        .collect())
+    }
+
+    async fn get_planets_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIPlanet>, ApiError> {
+        Ok(
+            sqlx::query_as::<_, Keyed<PlanetRow>>(&format!("{SELECT_PLANET} WHERE key = ANY($1)"))
+                .bind(to_keys(idx))
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|keyed| (keyed.key as usize, keyed.row.into()))
+                .collect(),
+        )
+    }
+
+    async fn create_human(&self, input: NewHuman) -> Result<APICharacter, ApiError> {
+        validate_name(&input.name)?;
+        let mut tx = self.pool.begin().await?;
+        let home_planet = match &input.home_planet_id {
+            Some(id) => Some(key_of(&mut tx, "planets", "planet", id).await?),
+            None => None,
+        };
+        let mut star_ships = Vec::new();
+        for id in input.starship_id.iter().chain(&input.starship_ids) {
+            star_ships.push(key_of(&mut tx, "starships", "starship", id).await?);
+        }
+        let friends = character_keys(&mut tx, &input.friend_ids).await?;
+        let species = match &input.species_id {
+            Some(id) => Some(key_of(&mut tx, "species", "species", id).await?),
+            None => None,
+        };
+
+        let key: i32 = sqlx::query_scalar(
+            "WITH next AS (SELECT nextval(pg_get_serial_sequence('characters', 'key'))::INTEGER AS key)
+             INSERT INTO characters(key, id, is_human, name, home_planet, species, mass)
+             SELECT key, key::TEXT, TRUE, $1, $2, $3, $4 FROM next
+             RETURNING key",
+        )
+        .bind(&input.name)
+        .bind(home_planet)
+        .bind(species)
+        .bind(to_bigint(input.mass)?)
+        .fetch_one(&mut *tx)
+        .await?;
+        set_starships(&mut tx, key, &star_ships).await?;
+        set_friends(&mut tx, key, &friends).await?;
+        set_films(&mut tx, key, &input.film_episodes()).await?;
+
+        let human = character_by_key(&mut tx, key).await?;
+        tx.commit().await?;
+        Ok(human)
+    }
+
+    async fn create_droid(&self, input: NewDroid) -> Result<APICharacter, ApiError> {
+        validate_name(&input.name)?;
+        let mut tx = self.pool.begin().await?;
+        let friends = character_keys(&mut tx, &input.friend_ids).await?;
+        let species = match &input.species_id {
+            Some(id) => Some(key_of(&mut tx, "species", "species", id).await?),
+            None => None,
+        };
+
+        let key: i32 = sqlx::query_scalar(
+            "WITH next AS (SELECT nextval(pg_get_serial_sequence('characters', 'key'))::INTEGER AS key)
+             INSERT INTO characters(key, id, is_human, name, primary_function, species, mass)
+             SELECT key, key::TEXT, FALSE, $1, $2, $3, $4 FROM next
+             RETURNING key",
+        )
+        .bind(&input.name)
+        .bind(&input.primary_function)
+        .bind(species)
+        .bind(to_bigint(input.mass)?)
+        .fetch_one(&mut *tx)
+        .await?;
+        set_friends(&mut tx, key, &friends).await?;
+        set_films(&mut tx, key, &input.film_episodes()).await?;
+
+        let droid = character_by_key(&mut tx, key).await?;
+        tx.commit().await?;
+        Ok(droid)
+    }
+
+    async fn update_character(
+        &self,
+        id: String,
+        input: UpdateCharacter,
+    ) -> Result<APICharacter, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let (key, is_human) = lock_character(&mut tx, &id).await?;
+        validate_update(&id, is_human, &input)?;
+        let friends = match &input.friend_ids {
+            Some(ids) => Some(character_keys(&mut tx, ids).await?),
+            None => None,
+        };
+        let mass = input.mass.map(to_bigint).transpose()?;
+        let species = match &input.species_id {
+            Some(id) => Some(key_of(&mut tx, "species", "species", id).await?),
+            None => None,
+        };
+
+        sqlx::query(
+            "UPDATE characters
+             SET name = COALESCE($2, name),
+                 mass = COALESCE($3, mass),
+                 primary_function = COALESCE($4, primary_function),
+                 species = COALESCE($5, species)
+             WHERE key = $1",
+        )
+        .bind(key)
+        .bind(&input.name)
+        .bind(mass)
+        .bind(&input.primary_function)
+        .bind(species)
+        .execute(&mut *tx)
+        .await?;
+        if let Some(episodes) = input.film_episodes() {
+            set_films(&mut tx, key, &episodes).await?;
+        }
+        if let Some(friends) = &friends {
+            set_friends(&mut tx, key, friends).await?;
+        }
+
+        let character = character_by_key(&mut tx, key).await?;
+        tx.commit().await?;
+        Ok(character)
+    }
+
+    async fn delete_character(&self, id: String) -> Result<APICharacter, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let (key, _) = lock_character(&mut tx, &id).await?;
+        let is_hero: bool =
+            sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM heroes WHERE character = $1)")
+                .bind(key)
+                .fetch_one(&mut *tx)
+                .await?;
+        if is_hero {
+            return Err(ApiError::InvalidInput(format!(
+                "character `{id}` is an episode hero and can't be deleted"
+            )));
+        }
+        let deleted = character_by_key(&mut tx, key).await?;
+        // friendships, films and pilots go with it (ON DELETE CASCADE)
+        sqlx::query("DELETE FROM characters WHERE key = $1")
+            .bind(key)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn create_starship(&self, input: NewStarShip) -> Result<APIStarShip, ApiError> {
+        validate_name(&input.name)?;
+        validate_length(input.length)?;
+        Ok(sqlx::query_as::<_, StarShipRow>(
+            "WITH next AS (SELECT nextval(pg_get_serial_sequence('starships', 'key'))::INTEGER AS key)
+             INSERT INTO starships(key, id, name, length)
+             SELECT key, key::TEXT, $1, $2 FROM next
+             RETURNING id, name, length",
+        )
+        .bind(&input.name)
+        .bind(input.length)
+        .fetch_one(&self.pool)
+        .await?
+        .into())
+    }
+
+    async fn update_starship(
+        &self,
+        id: String,
+        input: UpdateStarShip,
+    ) -> Result<APIStarShip, ApiError> {
+        if let Some(name) = &input.name {
+            validate_name(name)?;
+        }
+        if let Some(length) = input.length {
+            validate_length(length)?;
+        }
+        sqlx::query_as::<_, StarShipRow>(
+            "UPDATE starships SET name = COALESCE($2, name), length = COALESCE($3, length)
+             WHERE id = $1
+             RETURNING id, name, length",
+        )
+        .bind(&id)
+        .bind(&input.name)
+        .bind(input.length)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Into::into)
+        .ok_or(ApiError::NotFound {
+            kind: "starship",
+            id,
+        })
+    }
+
+    async fn delete_starship(&self, id: String) -> Result<APIStarShip, ApiError> {
+        // the pilots go with it (ON DELETE CASCADE)
+        sqlx::query_as::<_, StarShipRow>(
+            "DELETE FROM starships WHERE id = $1 RETURNING id, name, length",
+        )
+        .bind(&id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Into::into)
+        .ok_or(ApiError::NotFound {
+            kind: "starship",
+            id,
+        })
+    }
+
+    async fn create_planet(&self, input: NewPlanet) -> Result<APIPlanet, ApiError> {
+        validate_name(&input.name)?;
+        Ok(sqlx::query_as::<_, PlanetRow>(
+            "WITH next AS (SELECT nextval(pg_get_serial_sequence('planets', 'key'))::INTEGER AS key)
+             INSERT INTO planets(key, id, name, climate, diameter, gravity, population,
+                                 rotation_period, orbital_period)
+             SELECT key, key::TEXT, $1, $2, $3, $4, $5, $6, $7 FROM next
+             RETURNING id, name, climate, diameter, gravity, population,
+                       rotation_period, orbital_period",
+        )
+        .bind(&input.name)
+        .bind(&input.climate)
+        .bind(to_bigint(input.diameter)?)
+        .bind(&input.gravity)
+        .bind(to_bigint(input.population)?)
+        .bind(to_bigint(input.rotation_period)?)
+        .bind(to_bigint(input.orbital_period)?)
+        .fetch_one(&self.pool)
+        .await?
+        .into())
+    }
+
+    async fn update_planet(&self, id: String, input: UpdatePlanet) -> Result<APIPlanet, ApiError> {
+        if let Some(name) = &input.name {
+            validate_name(name)?;
+        }
+        sqlx::query_as::<_, PlanetRow>(
+            "UPDATE planets
+             SET name = COALESCE($2, name),
+                 climate = COALESCE($3, climate),
+                 diameter = COALESCE($4, diameter),
+                 gravity = COALESCE($5, gravity),
+                 population = COALESCE($6, population),
+                 rotation_period = COALESCE($7, rotation_period),
+                 orbital_period = COALESCE($8, orbital_period)
+             WHERE id = $1
+             RETURNING id, name, climate, diameter, gravity, population,
+                       rotation_period, orbital_period",
+        )
+        .bind(&id)
+        .bind(&input.name)
+        .bind(&input.climate)
+        .bind(input.diameter.map(to_bigint).transpose()?)
+        .bind(&input.gravity)
+        .bind(input.population.map(to_bigint).transpose()?)
+        .bind(input.rotation_period.map(to_bigint).transpose()?)
+        .bind(input.orbital_period.map(to_bigint).transpose()?)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Into::into)
+        .ok_or(ApiError::NotFound { kind: "planet", id })
+    }
+
+    async fn delete_planet(&self, id: String) -> Result<APIPlanet, ApiError> {
+        // characters.home_planet and species.homeworld are ON DELETE SET NULL
+        sqlx::query_as::<_, PlanetRow>(
+            "DELETE FROM planets WHERE id = $1
+             RETURNING id, name, climate, diameter, gravity, population,
+                       rotation_period, orbital_period",
+        )
+        .bind(&id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Into::into)
+        .ok_or(ApiError::NotFound { kind: "planet", id })
+    }
+
+    async fn create_film(&self, input: NewFilm) -> Result<APIFilm, ApiError> {
+        validate_film(&input)?;
+        let episode_id = i32::try_from(input.episode_id)
+            .map_err(|_| ApiError::InvalidInput(format!("{} is too large", input.episode_id)))?;
+        let mut tx = self.pool.begin().await?;
+        let cast = character_keys(&mut tx, &input.character_ids).await?;
+        let mut starships = Vec::new();
+        for id in &input.starship_ids {
+            starships.push(key_of(&mut tx, "starships", "starship", id).await?);
+        }
+        let mut planets = Vec::new();
+        for id in &input.planet_ids {
+            planets.push(key_of(&mut tx, "planets", "planet", id).await?);
+        }
+
+        // the unique constraint on episode_id catches a film that is created at the same time
+        let key: Option<i32> = sqlx::query_scalar(
+            "WITH next AS (SELECT nextval(pg_get_serial_sequence('films', 'key'))::INTEGER AS key)
+             INSERT INTO films(key, id, episode_id, title, release_date, director, producer,
+                               opening_crawl)
+             SELECT key, key::TEXT, $1, $2, $3, $4, $5, $6 FROM next
+             ON CONFLICT (episode_id) DO NOTHING
+             RETURNING key",
+        )
+        .bind(episode_id)
+        .bind(&input.title)
+        .bind(input.release_date)
+        .bind(&input.director)
+        .bind(&input.producer)
+        .bind(&input.opening_crawl)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let key = key.ok_or_else(|| episode_taken(input.episode_id))?;
+
+        for (table, column, keys) in [
+            ("film_characters", "character", &cast),
+            ("film_starships", "starship", &starships),
+            ("film_planets", "planet", &planets),
+        ] {
+            sqlx::query(&format!(
+                "INSERT INTO {table}(film, {column})
+                 SELECT $1, k FROM unnest($2::INTEGER[]) AS k
+                 ON CONFLICT DO NOTHING"
+            ))
+            .bind(key)
+            .bind(keys)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let film: FilmRow = sqlx::query_as(&format!("{SELECT_FILM} WHERE f.key = $1"))
+            .bind(key)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(film.into())
+    }
+
+    async fn assign_starship(
+        &self,
+        character_id: String,
+        starship_id: Option<String>,
+    ) -> Result<APICharacter, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let key = lock_human(&mut tx, &character_id).await?;
+        let starship = match &starship_id {
+            Some(id) => Some(key_of(&mut tx, "starships", "starship", id).await?),
+            None => None,
+        };
+        set_starships(&mut tx, key, starship.as_slice()).await?;
+        let human = character_by_key(&mut tx, key).await?;
+        tx.commit().await?;
+        Ok(human)
+    }
+
+    async fn add_pilot(
+        &self,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<APIStarShip, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let pilot = lock_human(&mut tx, &character_id).await?;
+        let starship = key_of(&mut tx, "starships", "starship", &starship_id).await?;
+        sqlx::query("INSERT INTO pilots(pilot, starship) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(pilot)
+            .bind(starship)
+            .execute(&mut *tx)
+            .await?;
+        let starship = starship_by_key(&mut tx, starship).await?;
+        tx.commit().await?;
+        Ok(starship)
+    }
+
+    async fn remove_pilot(
+        &self,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<APIStarShip, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let pilot = lock_human(&mut tx, &character_id).await?;
+        let starship = key_of(&mut tx, "starships", "starship", &starship_id).await?;
+        sqlx::query("DELETE FROM pilots WHERE pilot = $1 AND starship = $2")
+            .bind(pilot)
+            .bind(starship)
+            .execute(&mut *tx)
+            .await?;
+        let starship = starship_by_key(&mut tx, starship).await?;
+        tx.commit().await?;
+        Ok(starship)
+    }
+
+    async fn set_home_planet(
+        &self,
+        character_id: String,
+        planet_id: Option<String>,
+    ) -> Result<APICharacter, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let key = lock_human(&mut tx, &character_id).await?;
+        let planet = match &planet_id {
+            Some(id) => Some(key_of(&mut tx, "planets", "planet", id).await?),
+            None => None,
+        };
+        sqlx::query("UPDATE characters SET home_planet = $2 WHERE key = $1")
+            .bind(key)
+            .bind(planet)
+            .execute(&mut *tx)
+            .await?;
+        let human = character_by_key(&mut tx, key).await?;
+        tx.commit().await?;
+        Ok(human)
+    }
+}
+
+/// every row of `select` with its key, as what the store hands out
+async fn keyed<R, T>(conn: &mut PgConnection, select: &str) -> Result<Vec<(usize, T)>, ApiError>
+where
+    R: for<'r> sqlx::FromRow<'r, sqlx::postgres::PgRow> + Send + Unpin + Into<T>,
+{
+    Ok(sqlx::query_as::<_, Keyed<R>>(select)
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|keyed| (keyed.key as usize, keyed.row.into()))
+        .collect())
+}
+
+/// indices that don't fit in an INTEGER can't be a key, so they are dropped
+fn to_keys(idx: &[usize]) -> Vec<i32> {
+    idx.iter().filter_map(|&i| i32::try_from(i).ok()).collect()
+}
+
+/// `heroes.episode_id` of an episode, 0 is the saga as a whole
+fn hero_episode_id(episode: Option<usize>) -> i32 {
+    episode.map_or(0, |e| i32::try_from(e).unwrap_or(i32::MAX))
+}
+
+/// The hero of `episode`, an episode without a hero of its own has the hero of the saga
+async fn hero(pool: &PgPool, episode: Option<usize>) -> Result<APICharacter, ApiError> {
+    let key: Option<i32> = sqlx::query_scalar(
+        "SELECT character FROM heroes WHERE episode_id = $1 OR episode_id = 0
+         ORDER BY episode_id DESC LIMIT 1",
+    )
+    .bind(hero_episode_id(episode))
+    .fetch_optional(pool)
+    .await?;
+    let not_found = || ApiError::NotFound {
+        kind: "hero",
+        id: episode.map_or("saga".into(), |e| format!("episode {e}")),
+    };
+    let key = key.ok_or_else(not_found)?;
+    characters_by_key(pool, &[key])
+        .await?
+        .remove(&(key as usize))
+        .ok_or_else(not_found)
+}
+
+fn to_bigint(n: usize) -> Result<i64, ApiError> {
+    i64::try_from(n).map_err(|_| ApiError::InvalidInput(format!("{n} is too large")))
+}
+
+/// Only the keys are needed to know which characters are in the page,
+/// so only the rows of that page get loaded with their friends and films.
+async fn characters_page(
+    pool: &PgPool,
+    is_human: bool,
+    page: PageRequest,
+) -> Result<Page<APICharacter>, ApiError> {
+    let keyset = Keyset {
+        table: "characters",
+        filter: |query: &mut QueryBuilder<'static, Postgres>| {
+            query.push(" AND c.is_human = ").push_bind(is_human);
+        },
+        order: Vec::new(),
+    };
+    load_page(pool, keyset.page(pool, page).await?).await
+}
+
+/// loads the characters of a page of keys
+async fn load_page(pool: &PgPool, page: Page<()>) -> Result<Page<APICharacter>, ApiError> {
+    let keys: Vec<usize> = page.items.iter().map(|&(key, _)| key).collect();
+    Ok(page.fill(characters_by_key(pool, &to_keys(&keys)).await?))
+}
+
+async fn characters_by_key(
+    pool: &PgPool,
+    keys: &[i32],
+) -> Result<HashMap<usize, APICharacter>, ApiError> {
+    Ok(
+        sqlx::query_as::<_, CharacterRow>(&format!("{SELECT_CHARACTER} WHERE c.key = ANY($1)"))
+            .bind(keys)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.key as usize, row.into()))
+            .collect(),
+    )
+}
+
+/// the keys of a page of a table that is listed by key, without a filter
+async fn key_page(
+    pool: &PgPool,
+    table: &'static str,
+    page: PageRequest,
+) -> Result<Page<()>, ApiError> {
+    Keyset {
+        table,
+        filter: |_: &mut QueryBuilder<'static, Postgres>| {},
+        order: Vec::new(),
+    }
+    .page(pool, page)
+    .await
+}
+
+/// One column a list is sorted on, `{t}` in `expr` stands for the table
+struct OrderColumn {
+    expr: &'static str,
+    direction: OrderDirection,
+}
+
+/// A list of `table` as `c`, the rows that match `filter` in the order of `order`,
+/// the key breaks the ties. A page is one query for the keys between the cursors,
+/// `LIMIT` one more than asked to know if there are more, and one for the counts.
+/// A cursor compares on the values of its row, a cursor of a deleted row only
+/// works when the list is sorted on the key alone.
+struct Keyset<F> {
+    table: &'static str,
+
+    /// pushes ` AND ...` for every condition
+    filter: F,
+
+    order: Vec<OrderColumn>,
+}
+
+impl<F> Keyset<F>
+where
+    F: Fn(&mut QueryBuilder<'static, Postgres>),
+{
+    async fn page(&self, pool: &PgPool, page: PageRequest) -> Result<Page<()>, ApiError> {
+        let after = page.after.map(to_key).transpose()?;
+        let before = page.before.map(to_key).transpose()?;
+        // only `last` pages from the end
+        let backwards = page.first.is_none() && page.last.is_some();
+
+        let mut query = QueryBuilder::new(format!("SELECT c.key FROM {} c WHERE TRUE", self.table));
+        (self.filter)(&mut query);
+        if let Some(after) = after {
+            query.push(" AND ");
+            self.push_beyond(&mut query, after, true);
+        }
+        if let Some(before) = before {
+            query.push(" AND ");
+            self.push_beyond(&mut query, before, false);
+        }
+        self.push_order(&mut query, backwards);
+        let limit = if backwards { page.last } else { page.first };
+        if let Some(limit) = limit {
+            query.push(" LIMIT ").push_bind(to_bigint(limit)? + 1);
+        }
+        let mut keys: Vec<i32> = query.build_query_scalar().fetch_all(pool).await?;
+
+        let mut query = QueryBuilder::new("SELECT COUNT(*), ");
+        self.push_counts(&mut query, after, before);
+        query.push(format!(" FROM {} c WHERE TRUE", self.table));
+        (self.filter)(&mut query);
+        let (total_count, until_after, from_before): (i64, i64, i64) =
+            query.build_query_as().fetch_one(pool).await?;
+
+        let mut has_previous_page = until_after > 0;
+        let mut has_next_page = from_before > 0;
+        if let Some(limit) = limit {
+            if keys.len() > limit {
+                keys.truncate(limit);
+                if backwards {
+                    has_previous_page = true;
+                } else {
+                    has_next_page = true;
+                }
+            }
+        }
+        if backwards {
+            keys.reverse();
+        }
+        if let (Some(last), false) = (page.last, backwards) {
+            if keys.len() > last {
+                keys.drain(..keys.len() - last);
+                has_previous_page = true;
+            }
+        }
+        Ok(Page {
+            items: keys.into_iter().map(|key| (key as usize, ())).collect(),
+            total_count: total_count as usize,
+            has_previous_page,
+            has_next_page,
+        })
+    }
+
+    /// The rows after the row of `cursor` in the order of the list, or before it.
+    /// `(a, b) > (x, y)` is `a > x OR (a = x AND b > y)`, with `<` for the descending columns.
+    fn push_beyond(&self, query: &mut QueryBuilder<'static, Postgres>, cursor: i32, after: bool) {
+        let key = OrderColumn {
+            expr: "{t}.key",
+            direction: OrderDirection::Asc,
+        };
+        let columns: Vec<&OrderColumn> = self.order.iter().chain([&key]).collect();
+        query.push("(");
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                query.push(" OR ");
+            }
+            query.push("(");
+            for equal in &columns[..i] {
+                query.push(format!("{} = ", equal.expr.replace("{t}", "c")));
+                self.push_cursor_value(query, equal, cursor);
+                query.push(" AND ");
+            }
+            let greater = (column.direction == OrderDirection::Asc) == after;
+            query.push(format!(
+                "{} {} ",
+                column.expr.replace("{t}", "c"),
+                if greater { ">" } else { "<" }
+            ));
+            self.push_cursor_value(query, column, cursor);
+            query.push(")");
+        }
+        query.push(")");
+    }
+
+    /// the value of `column` in the row of `cursor`, NULL when that row is gone
+    fn push_cursor_value(
+        &self,
+        query: &mut QueryBuilder<'static, Postgres>,
+        column: &OrderColumn,
+        cursor: i32,
+    ) {
+        if column.expr == "{t}.key" {
+            query.push_bind(cursor);
+        } else {
+            query
+                .push(format!(
+                    "(SELECT {} FROM {} x WHERE x.key = ",
+                    column.expr.replace("{t}", "x"),
+                    self.table
+                ))
+                .push_bind(cursor)
+                .push(")");
+        }
+    }
+
+    /// How many rows are up to the `after` cursor, and how many from the `before` cursor on.
+    /// When `before` comes first, the rows after both cursors are what comes next.
+    fn push_counts(
+        &self,
+        query: &mut QueryBuilder<'static, Postgres>,
+        after: Option<i32>,
+        before: Option<i32>,
+    ) {
+        match after {
+            Some(after) => {
+                query.push("COUNT(*) FILTER (WHERE NOT ");
+                self.push_beyond(query, after, true);
+                query.push("), ");
+            }
+            None => {
+                query.push("0::BIGINT, ");
+            }
+        }
+        match before {
+            Some(before) => {
+                query.push("COUNT(*) FILTER (WHERE NOT ");
+                self.push_beyond(query, before, false);
+                if let Some(after) = after {
+                    query.push(" AND ");
+                    self.push_beyond(query, after, true);
+                }
+                query.push(")");
+            }
+            None => {
+                query.push("0::BIGINT");
+            }
+        }
+    }
+
+    fn push_order(&self, query: &mut QueryBuilder<'static, Postgres>, backwards: bool) {
+        query.push(" ORDER BY ");
+        for column in &self.order {
+            let ascending = (column.direction == OrderDirection::Asc) != backwards;
+            query.push(format!(
+                "{} {}, ",
+                column.expr.replace("{t}", "c"),
+                if ascending { "ASC" } else { "DESC" }
+            ));
+        }
+        query.push(if backwards { "c.key DESC" } else { "c.key ASC" });
+    }
+}
+
+/// the keys are INTEGERs, a bigger cursor can't come from this list
+fn to_key(cursor: usize) -> Result<i32, ApiError> {
+    i32::try_from(cursor)
+        .map_err(|_| ApiError::InvalidInput("cursor is not part of this list".into()))
+}
+
+/// the characters of `characters(filter, orderBy)`
+fn filtered_characters(
+    filter: CharacterFilter,
+    order_by: &[CharacterOrder],
+) -> Result<Keyset<impl Fn(&mut QueryBuilder<'static, Postgres>)>, ApiError> {
+    let min_mass = filter.min_mass.map(to_bigint).transpose()?;
+    let max_mass = filter.max_mass.map(to_bigint).transpose()?;
+    let order = order_by
+        .iter()
+        .map(|order| OrderColumn {
+            // COLLATE "C" compares bytes, like the in memory api does
+            expr: match order.field {
+                CharacterOrderField::Id => "{t}.id COLLATE \"C\"",
+                CharacterOrderField::Name => "{t}.name COLLATE \"C\"",
+                CharacterOrderField::Mass => "{t}.mass",
+            },
+            direction: order.direction,
+        })
+        .collect();
+    let filter = move |query: &mut QueryBuilder<'static, Postgres>| {
+        push_character_filter(query, &filter, min_mass, max_mass)
+    };
+    Ok(Keyset {
+        table: "characters",
+        filter,
+        order,
+    })
+}
+
+/// a ` AND ...` for every field of the filter that is set
+fn push_character_filter(
+    query: &mut QueryBuilder<'static, Postgres>,
+    filter: &CharacterFilter,
+    min_mass: Option<i64>,
+    max_mass: Option<i64>,
+) {
+    if let Some(is_human) = filter.is_human {
+        query.push(" AND c.is_human = ").push_bind(is_human);
+    }
+    if let Some(episode) = filter.episode {
+        query
+            .push(
+                " AND EXISTS (SELECT 1 FROM film_characters fc JOIN films f ON f.key = fc.film
+                              WHERE fc.character = c.key AND f.episode_id = ",
+            )
+            .push_bind(episode.number() as i32)
+            .push(")");
+    }
+    if let Some(film_id) = &filter.film_id {
+        query
+            .push(
+                " AND EXISTS (SELECT 1 FROM film_characters fc JOIN films f ON f.key = fc.film
+                              WHERE fc.character = c.key AND f.id = ",
+            )
+            .push_bind(film_id.clone())
+            .push(")");
+    }
+    if let Some(min) = min_mass {
+        query.push(" AND c.mass >= ").push_bind(min);
+    }
+    if let Some(max) = max_mass {
+        query.push(" AND c.mass <= ").push_bind(max);
+    }
+    if let Some(name) = &filter.name_contains {
+        // strpos instead of LIKE, so `%` and `_` in the name don't need escaping
+        query
+            .push(" AND strpos(lower(c.name), lower(")
+            .push_bind(name.clone())
+            .push(")) > 0");
+    }
+    if let Some(has_starship) = filter.has_starship {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM pilots p WHERE p.pilot = c.key) = ")
+            .push_bind(has_starship);
+    }
+    if let Some(starship_id) = &filter.starship_id {
+        query
+            .push(
+                " AND EXISTS (SELECT 1 FROM pilots p JOIN starships s ON s.key = p.starship
+                              WHERE p.pilot = c.key AND s.id = ",
+            )
+            .push_bind(starship_id.clone())
+            .push(")");
+    }
+    if let Some(vehicle_id) = &filter.vehicle_id {
+        query
+            .push(
+                " AND EXISTS (SELECT 1 FROM vehicle_pilots p JOIN vehicles v ON v.key = p.vehicle
+                              WHERE p.pilot = c.key AND v.id = ",
+            )
+            .push_bind(vehicle_id.clone())
+            .push(")");
+    }
+    if let Some(species_id) = &filter.species_id {
+        query
+            .push(" AND c.species = (SELECT key FROM species WHERE id = ")
+            .push_bind(species_id.clone())
+            .push(")");
+    }
+    if let Some(planet_id) = &filter.home_planet_id {
+        query
+            .push(" AND c.home_planet = (SELECT key FROM planets WHERE id = ")
+            .push_bind(planet_id.clone())
+            .push(")");
+    }
+}
+
+async fn character_by_key(conn: &mut PgConnection, key: i32) -> Result<APICharacter, ApiError> {
+    Ok(
+        sqlx::query_as::<_, CharacterRow>(&format!("{SELECT_CHARACTER} WHERE c.key = $1"))
+            .bind(key)
+            .fetch_one(conn)
+            .await?
+            .into(),
+    )
+}
+
+async fn starship_by_key(conn: &mut PgConnection, key: i32) -> Result<APIStarShip, ApiError> {
+    Ok(
+        sqlx::query_as::<_, StarShipRow>(&format!("{SELECT_STARSHIP} WHERE key = $1"))
+            .bind(key)
+            .fetch_one(conn)
+            .await?
+            .into(),
+    )
+}
+
+/// `key` of the row with `id` in `table`
+async fn key_of(
+    conn: &mut PgConnection,
+    table: &str,
+    kind: &'static str,
+    id: &str,
+) -> Result<i32, ApiError> {
+    sqlx::query_scalar(&format!("SELECT key FROM {table} WHERE id = $1"))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| ApiError::NotFound {
+            kind,
+            id: id.into(),
+        })
+}
+
+/// locks the row of the character for the rest of the transaction
+async fn lock_character(conn: &mut PgConnection, id: &str) -> Result<(i32, bool), ApiError> {
+    sqlx::query_as("SELECT key, is_human FROM characters WHERE id = $1 FOR UPDATE")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| ApiError::NotFound {
+            kind: "character",
+            id: id.into(),
+        })
+}
+
+/// like `lock_character`, but droids have no starship or home planet
+async fn lock_human(conn: &mut PgConnection, id: &str) -> Result<i32, ApiError> {
+    let (key, is_human) = lock_character(conn, id).await?;
+    if !is_human {
+        return Err(ApiError::InvalidInput(format!(
+            "character `{id}` is a droid, only humans have a starship or home planet"
+        )));
+    }
+    Ok(key)
+}
+
+/// keys of the characters with `ids`, in the same order
+async fn character_keys(conn: &mut PgConnection, ids: &[String]) -> Result<Vec<i32>, ApiError> {
+    let found: Vec<(String, i32)> =
+        sqlx::query_as("SELECT id, key FROM characters WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_all(conn)
+            .await?;
+    ids.iter()
+        .map(|id| {
+            found
+                .iter()
+                .find(|(found_id, _)| found_id == id)
+                .map(|&(_, key)| key)
+                .ok_or_else(|| ApiError::NotFound {
+                    kind: "character",
+                    id: id.clone(),
+                })
+        })
+        .collect()
+}
+
+/// replaces the friends of `key`
+async fn set_friends(conn: &mut PgConnection, key: i32, friends: &[i32]) -> Result<(), ApiError> {
+    let mut unique = Vec::with_capacity(friends.len());
+    for &friend in friends {
+        if !unique.contains(&friend) {
+            unique.push(friend);
+        }
+    }
+    sqlx::query("DELETE FROM friendships WHERE character = $1")
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "INSERT INTO friendships(character, friend, position)
+         SELECT $1, friend, position - 1
+         FROM unnest($2::INTEGER[]) WITH ORDINALITY AS f(friend, position)",
+    )
+    .bind(key)
+    .bind(unique)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// replaces the starships `key` flies
+async fn set_starships(
+    conn: &mut PgConnection,
+    key: i32,
+    starships: &[i32],
+) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM pilots WHERE pilot = $1")
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "INSERT INTO pilots(pilot, starship)
+         SELECT $1, starship FROM unnest($2::INTEGER[]) AS starship
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(key)
+    .bind(starships)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// replaces the films `key` appeared in by the films of `episodes`
+async fn set_films(conn: &mut PgConnection, key: i32, episodes: &[usize]) -> Result<(), ApiError> {
+    let missing = |episode: &dyn std::fmt::Display| ApiError::NotFound {
+        kind: "film",
+        id: format!("episode {episode}"),
+    };
+    let numbers = episodes
+        .iter()
+        .map(|&episode| i32::try_from(episode).map_err(|_| missing(&episode)))
+        .collect::<Result<Vec<i32>, _>>()?;
+    let films: Vec<(i32, i32)> =
+        sqlx::query_as("SELECT key, episode_id FROM films WHERE episode_id = ANY($1)")
+            .bind(&numbers)
+            .fetch_all(&mut *conn)
+            .await?;
+    if let Some(number) = numbers
+        .iter()
+        .find(|&&number| films.iter().all(|&(_, episode_id)| episode_id != number))
+    {
+        return Err(missing(number));
+    }
+
+    sqlx::query("DELETE FROM film_characters WHERE character = $1")
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "INSERT INTO film_characters(film, character)
+         SELECT film, $1 FROM unnest($2::INTEGER[]) AS film
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(key)
+    .bind(films.iter().map(|&(film, _)| film).collect::<Vec<_>>())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starwars::test_db;
+
+    type Summary = (Vec<usize>, usize, bool, bool);
+
+    fn summary<T>(page: Page<T>) -> Summary {
+        (
+            page.items.into_iter().map(|(key, _)| key).collect(),
+            page.total_count,
+            page.has_previous_page,
+            page.has_next_page,
+        )
+    }
+
+    /// every combination of cursors and sizes, with cursors of rows that don't exist
+    fn requests(keys: &[usize]) -> Vec<PageRequest> {
+        let cursors: Vec<Option<usize>> = [None, Some(0), Some(1000)]
+            .into_iter()
+            .chain(keys.iter().map(|&key| Some(key)))
+            .collect();
+        let sizes = [None, Some(0), Some(1), Some(2), Some(100)];
+        let mut requests = Vec::new();
+        for &after in &cursors {
+            for &before in &cursors {
+                for first in sizes {
+                    for last in sizes {
+                        requests.push(PageRequest {
+                            after,
+                            before,
+                            first,
+                            last,
+                        });
+                    }
+                }
+            }
+        }
+        requests
+    }
+
+    /// the keyset pages are the same as paging the whole list in memory
+    #[tokio::test]
+    async fn keyset_pages_match_the_whole_list() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        sqlx::query(
+            "INSERT INTO planets(id, name, climate, diameter, gravity, population,
+                                 rotation_period, orbital_period)
+             SELECT 'test' || n, 'Test ' || n, 'arid', 0, '1', 0, 0, 0
+             FROM generate_series(1, 4) n",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        // a hole in the keys, like after a delete
+        sqlx::query("DELETE FROM planets WHERE key = 2")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let keys: Vec<i32> = sqlx::query_scalar("SELECT key FROM planets ORDER BY key")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let keys: Vec<usize> = keys.into_iter().map(|key| key as usize).collect();
+        let all: Vec<(usize, ())> = keys.iter().map(|&key| (key, ())).collect();
+
+        for request in requests(&[keys[0], 2, keys[2], keys[keys.len() - 1]]) {
+            let page = key_page(&pool, "planets", request).await.unwrap();
+            assert_eq!(
+                summary(page),
+                summary(request.apply(all.clone())),
+                "{request:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn keyset_pages_follow_the_order() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        let filter = || CharacterFilter {
+            is_human: Some(true),
+            ..Default::default()
+        };
+        let order_by = [
+            CharacterOrder {
+                field: CharacterOrderField::Mass,
+                direction: OrderDirection::Desc,
+            },
+            CharacterOrder {
+                field: CharacterOrderField::Name,
+                direction: OrderDirection::Asc,
+            },
+        ];
+        // two humans with the same mass, so the name decides
+        sqlx::query("UPDATE characters SET mass = 80 WHERE id IN ('1', '3')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let ordered: Vec<i32> = sqlx::query_scalar(
+            "SELECT key FROM characters WHERE is_human
+             ORDER BY mass DESC, name COLLATE \"C\" ASC, key",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let all: Vec<(usize, ())> = ordered.iter().map(|&key| (key as usize, ())).collect();
+        let keys: Vec<usize> = all.iter().map(|&(key, _)| key).collect();
+
+        for request in requests(&keys) {
+            // the cursors that aren't in the list can't be compared in memory
+            let in_list = |cursor: Option<usize>| cursor.is_none_or(|c| keys.contains(&c));
+            if !in_list(request.after) || !in_list(request.before) {
+                continue;
+            }
+            let page = filtered_characters(filter(), &order_by)
+                .unwrap()
+                .page(&pool, request)
+                .await
+                .unwrap();
+            assert_eq!(
+                summary(page),
+                summary(request.apply_ordered(all.clone()).unwrap()),
+                "{request:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn films_can_be_added() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        crate::starwars::data::tests::check_films(&PgStarWarsAPI::new(pool)).await;
+    }
+
+    #[tokio::test]
+    async fn every_film_can_have_a_hero() {
+        let Some(pool) = test_db::pool().await else {
+            return;
+        };
+        crate::starwars::data::tests::check_heroes(&PgStarWarsAPI::new(pool)).await;
+    }
+}
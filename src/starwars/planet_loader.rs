@@ -0,0 +1,18 @@
+use async_graphql::{dataloader::*, FieldError, ResultExt};
+use std::collections::HashMap;
+
+use super::{data::APIPlanet, Repository};
+
+pub struct PlanetLoader {
+    pub api: Repository,
+}
+
+/// Loader for the home planets of humans
+impl Loader<usize> for PlanetLoader {
+    type Value = APIPlanet;
+    type Error = FieldError;
+
+    async fn load(&self, keys: &[usize]) -> Result<HashMap<usize, Self::Value>, Self::Error> {
+        self.api.get_planets_by_idx(keys).await.extend()
+    }
+}
@@ -0,0 +1,302 @@
+use std::{collections::HashMap, path::Path, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use super::{
+    data::{APICharacter, APIFilm, APIPlanet, APISpecies, APIStarShip, APIVehicle},
+    dataset::Dataset,
+    errors::ApiError,
+    models::{
+        CharacterFilter, CharacterOrder, NewDroid, NewFilm, NewHuman, NewPlanet, NewStarShip,
+        UpdateCharacter, UpdatePlanet, UpdateStarShip,
+    },
+    pagination::{Page, PageRequest},
+    PgStarWarsAPI, StarWarsAPI,
+};
+
+/// What the resolvers put in and get out of "the database".
+///
+/// `StarWarsAPI` keeps everything in tables in memory, `PgStarWarsAPI` keeps it in postgres.
+/// The `usize` indices (friends, home planet, starship) are whatever key the store uses
+/// internally, resolvers only pass them back to the same store.
+#[async_trait]
+pub trait StarWarsRepository: Send + Sync {
+    /// the hero of the entire saga
+    async fn get_saga_hero(&self) -> Result<APICharacter, ApiError>;
+
+    /// the hero of the episode, or the saga hero when the episode has none of its own
+    async fn get_hero(&self, episode: usize) -> Result<APICharacter, ApiError>;
+
+    /// Makes the character the hero of `episode`, `None` is the hero of the entire saga.
+    /// There has to be a film with that episode.
+    async fn set_hero(
+        &self,
+        episode: Option<usize>,
+        character_id: String,
+    ) -> Result<APICharacter, ApiError>;
+
+    /// everything in the store as a dataset, the credits are not part of it
+    async fn export(&self) -> Result<Dataset, ApiError>;
+
+    async fn get_human(&self, id: String) -> Result<Option<APICharacter>, ApiError>;
+
+    /// the humans in the requested page, ordered by key
+    async fn get_humans(&self, page: PageRequest) -> Result<Page<APICharacter>, ApiError>;
+
+    async fn get_droid(&self, id: String) -> Result<Option<APICharacter>, ApiError>;
+
+    /// the droids in the requested page, ordered by key
+    async fn get_droids(&self, page: PageRequest) -> Result<Page<APICharacter>, ApiError>;
+
+    /// The characters that match `filter`, sorted on `order_by` and then on key.
+    /// The page is taken from that sorted list, so the cursors are the keys of the characters.
+    async fn get_characters(
+        &self,
+        filter: CharacterFilter,
+        order_by: Vec<CharacterOrder>,
+        page: PageRequest,
+    ) -> Result<Page<APICharacter>, ApiError>;
+
+    async fn get_planet(&self, id: String) -> Result<Option<APIPlanet>, ApiError>;
+
+    /// the planets in the requested page, ordered by key
+    async fn get_planets(&self, page: PageRequest) -> Result<Page<APIPlanet>, ApiError>;
+
+    async fn get_film(&self, id: String) -> Result<Option<APIFilm>, ApiError>;
+
+    /// the films in the requested page, ordered by key
+    async fn get_films(&self, page: PageRequest) -> Result<Page<APIFilm>, ApiError>;
+
+    async fn get_films_by_idx(&self, idx: &[usize]) -> Result<HashMap<usize, APIFilm>, ApiError>;
+
+    async fn get_species(&self, id: String) -> Result<Option<APISpecies>, ApiError>;
+
+    /// the species in the requested page, ordered by key
+    async fn get_all_species(&self, page: PageRequest) -> Result<Page<APISpecies>, ApiError>;
+
+    async fn get_species_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APISpecies>, ApiError>;
+
+    async fn get_vehicle(&self, id: String) -> Result<Option<APIVehicle>, ApiError>;
+
+    /// the vehicles in the requested page, ordered by key
+    async fn get_vehicles(&self, page: PageRequest) -> Result<Page<APIVehicle>, ApiError>;
+
+    async fn get_vehicles_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIVehicle>, ApiError>;
+
+    /// the characters at `idx`, indices that point to nothing are left out
+    async fn get_characters_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APICharacter>, ApiError>;
+
+    async fn get_starship(&self, id: String) -> Result<Option<APIStarShip>, ApiError>;
+
+    async fn get_starships_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIStarShip>, ApiError>;
+
+    async fn get_planets_by_idx(
+        &self,
+        idx: &[usize],
+    ) -> Result<HashMap<usize, APIPlanet>, ApiError>;
+
+    async fn create_human(&self, input: NewHuman) -> Result<APICharacter, ApiError>;
+
+    async fn create_droid(&self, input: NewDroid) -> Result<APICharacter, ApiError>;
+
+    async fn update_character(
+        &self,
+        id: String,
+        input: UpdateCharacter,
+    ) -> Result<APICharacter, ApiError>;
+
+    /// Removes the character and takes it out of the friends of everyone else.
+    /// A hero can't be deleted, give the episode another hero first.
+    async fn delete_character(&self, id: String) -> Result<APICharacter, ApiError>;
+
+    async fn create_starship(&self, input: NewStarShip) -> Result<APIStarShip, ApiError>;
+
+    async fn update_starship(
+        &self,
+        id: String,
+        input: UpdateStarShip,
+    ) -> Result<APIStarShip, ApiError>;
+
+    /// removes the starship, it is taken out of the starships of its pilots
+    async fn delete_starship(&self, id: String) -> Result<APIStarShip, ApiError>;
+
+    async fn create_planet(&self, input: NewPlanet) -> Result<APIPlanet, ApiError>;
+
+    async fn update_planet(&self, id: String, input: UpdatePlanet) -> Result<APIPlanet, ApiError>;
+
+    /// removes the planet, characters that lived there no longer have a home planet
+    async fn delete_planet(&self, id: String) -> Result<APIPlanet, ApiError>;
+
+    /// a new film with its characters, starships and planets, no other film may have its episode
+    async fn create_film(&self, input: NewFilm) -> Result<APIFilm, ApiError>;
+
+    /// the starships in the requested page, ordered by key
+    async fn get_starships(&self, page: PageRequest) -> Result<Page<APIStarShip>, ApiError>;
+
+    /// the human only flies this starship from now on, `None` takes all its starships away
+    async fn assign_starship(
+        &self,
+        character_id: String,
+        starship_id: Option<String>,
+    ) -> Result<APICharacter, ApiError>;
+
+    /// the human becomes one of the pilots of the starship, returns the starship
+    async fn add_pilot(
+        &self,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<APIStarShip, ApiError>;
+
+    /// the human no longer flies the starship, returns the starship
+    async fn remove_pilot(
+        &self,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<APIStarShip, ApiError>;
+
+    /// set the home planet of a human, `None` clears it
+    async fn set_home_planet(
+        &self,
+        character_id: String,
+        planet_id: Option<String>,
+    ) -> Result<APICharacter, ApiError>;
+}
+
+/// The store the resolvers get out of the schema data
+pub type Repository = Arc<dyn StarWarsRepository>;
+
+/// Which `StarWarsRepository` the server runs with, picked at startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// `StarWarsAPI`: tables in memory, every restart starts from the built-in universe
+    Memory,
+
+    /// `PgStarWarsAPI`: the tables from the migrations, shared by every replica
+    Postgres,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            other => Err(format!(
+                "unknown storage backend `{other}`, expected `memory` or `postgres`"
+            )),
+        }
+    }
+}
+
+impl StorageBackend {
+    /// `pool` is only used by `Postgres`, the config makes sure there is one in that case.
+    /// `dataset` is only used by `Memory`, postgres gets its data from the migrations.
+    pub fn repository(self, pool: Option<&PgPool>, dataset: Option<Dataset>) -> Repository {
+        match (self, pool) {
+            (StorageBackend::Postgres, Some(pool)) => {
+                if dataset.is_some() {
+                    tracing::warn!("ignoring DATASET_PATH, it is only for the memory backend");
+                }
+                Arc::new(PgStarWarsAPI::new(pool.clone()))
+            }
+            (StorageBackend::Postgres, None) => {
+                panic!("the postgres storage backend needs a database")
+            }
+            (StorageBackend::Memory, _) => Arc::new(match dataset {
+                Some(dataset) => StarWarsAPI::from_dataset(dataset)
+                    .expect("the dataset is validated when it is loaded"),
+                None => StarWarsAPI::new(),
+            }),
+        }
+    }
+}
+
+/// The dataset at `path` when it can be loaded, `None` falls back to the built-in universe
+/// and the default credit accounts. Everything that is wrong with it is logged.
+pub fn load_dataset(path: &Path) -> Option<Dataset> {
+    match Dataset::load(path).and_then(Dataset::validate) {
+        Ok(dataset) => {
+            tracing::info!("loaded the dataset {}", path.display());
+            Some(dataset)
+        }
+        Err(e) => {
+            tracing::error!(
+                "{e}\nfalling back to the built-in universe instead of {}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+pub(crate) fn validate_name(name: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() {
+        return Err(ApiError::InvalidInput("name can't be empty".into()));
+    }
+    Ok(())
+}
+
+/// checks that don't depend on how the character is stored
+pub(crate) fn validate_update(
+    id: &str,
+    is_human: bool,
+    input: &UpdateCharacter,
+) -> Result<(), ApiError> {
+    if let Some(name) = &input.name {
+        validate_name(name)?;
+    }
+    if input.primary_function.is_some() && is_human {
+        return Err(ApiError::InvalidInput(format!(
+            "character `{id}` is a human and has no primary function"
+        )));
+    }
+    if input
+        .friend_ids
+        .as_ref()
+        .is_some_and(|ids| ids.iter().any(|friend| friend == id))
+    {
+        return Err(ApiError::InvalidInput(format!(
+            "character `{id}` can't be friends with itself"
+        )));
+    }
+    Ok(())
+}
+
+/// checks that don't depend on how the films are stored, the episode has to be free as well
+pub(crate) fn validate_film(input: &NewFilm) -> Result<(), ApiError> {
+    validate_name(&input.title)?;
+    if input.episode_id == 0 {
+        return Err(ApiError::InvalidInput(
+            "the episode of a film starts at 1".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// a second film of the same episode would make `episodes` and `hero` ambiguous
+pub(crate) fn episode_taken(episode_id: usize) -> ApiError {
+    ApiError::InvalidInput(format!("there is already a film of episode {episode_id}"))
+}
+
+pub(crate) fn validate_length(length: f64) -> Result<(), ApiError> {
+    if !length.is_finite() || length < 0. {
+        return Err(ApiError::InvalidInput(format!(
+            "length must be a positive number of meters, got {length}"
+        )));
+    }
+    Ok(())
+}
@@ -1,11 +1,33 @@
-use async_graphql::{Context, Object};
-use futures::future::Either;
+use async_graphql::{
+    connection::Connection, Context, Json, Object, Result, ResultExt, Subscription, ID,
+};
+use futures::{
+    future::{self, Either},
+    Stream, StreamExt,
+};
 
-use crate::starwars::models::Droid;
+use crate::auth::Role;
+use crate::starwars::models::{
+    CharacterFilter, CharacterOrder, Droid, Film, NewDroid, NewFilm, NewHuman, NewPlanet,
+    NewStarShip, Planet, Species, UpdateCharacter, UpdatePlanet, UpdateStarShip, Vehicle,
+};
 
 use super::{
-    models::{Character, Episode, Human, StarShip},
-    StarWarsAPI,
+    api_keys::{api_keys, ApiKey, IssuedApiKey, NewApiKey},
+    credits::{self, CreditEvents, Credits},
+    dataset::{self, Dataset},
+    errors::ApiError,
+    guards::{caller, OwnerGuard, RoleGuard},
+    models::{
+        BalanceChange, Character, CreditHistoryEntry, Episode, Human, LedgerEntry, StarShip,
+        Transfer,
+    },
+    node::{
+        local_id, local_ids, parse_global_id, Node, CHARACTER, DROID, FILM, HUMAN, PLANET, SPECIES,
+        STARSHIP, VEHICLE,
+    },
+    pagination::{connection, page_cost, KeyConnection},
+    Repository,
 };
 
 /// The query object for starwars
@@ -14,48 +36,266 @@ pub struct QueryRoot;
 #[Object]
 impl QueryRoot {
     // returns hero based on episode, else it just returns the hero of the entire star wars sage, aka luke SKYWALKER
-    async fn hero<'ctx>(&self, ctx: &Context<'ctx>, episode: Option<Episode>) -> Character {
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        episode
+    async fn hero<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        #[graphql(desc = "deprecated, use `episodeId`")] episode: Option<Episode>,
+        #[graphql(desc = "wins over `episode`")] episode_id: Option<usize>,
+    ) -> Result<Character> {
+        let api = ctx.data_unchecked::<Repository>();
+        episode_id
+            .or(episode.map(Episode::number))
             .map_or_else(
                 || Either::Left(async { api.get_saga_hero().await }),
                 |ep| Either::Right(async move { api.get_hero(ep).await }),
             )
             .await
-            .into()
+            .map(Into::into)
+            .extend()
     }
 
-    async fn human<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Option<Human> {
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        api.get_human(id).await.map(Into::into)
+    /// the character of whoever sends the bearer token, `null` without one
+    async fn viewer<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Option<Character>> {
+        let Some(user_id) = caller(ctx).user_id() else {
+            return Ok(None);
+        };
+        let api = ctx.data_unchecked::<Repository>();
+        if let Some(human) = api.get_human(user_id.into()).await.extend()? {
+            return Ok(Some(human.into()));
+        }
+        Ok(api
+            .get_droid(user_id.into())
+            .await
+            .extend()?
+            .map(Into::into))
     }
 
-    async fn droid<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Option<Droid> {
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        api.get_droid(id).await.map(Into::into)
+    async fn human<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Option<Human>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[HUMAN]).extend()?;
+        Ok(api.get_human(id).await.extend()?.map(Into::into))
     }
 
-    async fn starship<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Option<StarShip> {
-        let api = ctx.data_unchecked::<StarWarsAPI>();
-        api.get_starship(id).await.map(StarShip)
+    async fn droid<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Option<Droid>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[DROID]).extend()?;
+        Ok(api.get_droid(id).await.extend()?.map(Into::into))
     }
 
-    async fn humans<'ctx>(&self, ctx: &Context<'ctx>) -> Vec<Human> {
-        ctx.data_unchecked::<StarWarsAPI>()
-            .get_humans()
-            .await
-            .into_iter()
-            .map(Into::into)
-            .collect()
+    async fn starship<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Option<StarShip>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[STARSHIP]).extend()?;
+        Ok(api.get_starship(id).await.extend()?.map(StarShip))
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn starships<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<StarShip>> {
+        let api = ctx.data_unchecked::<Repository>();
+        connection(after, before, first, last, |page| api.get_starships(page)).await
+    }
+
+    async fn planet<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Option<Planet>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[PLANET]).extend()?;
+        Ok(api.get_planet(id).await.extend()?.map(Into::into))
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn planets<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Planet>> {
+        let api = ctx.data_unchecked::<Repository>();
+        connection(after, before, first, last, |page| api.get_planets(page)).await
+    }
+
+    async fn film<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Option<Film>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[FILM]).extend()?;
+        Ok(api.get_film(id).await.extend()?.map(Into::into))
+    }
+
+    /// every film, in the order they were added
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn films<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Film>> {
+        let api = ctx.data_unchecked::<Repository>();
+        connection(after, before, first, last, |page| api.get_films(page)).await
+    }
+
+    async fn species<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Option<Species>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[SPECIES]).extend()?;
+        Ok(api.get_species(id).await.extend()?.map(Into::into))
+    }
+
+    /// every species, `species` is already taken by the lookup of one
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn all_species<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Species>> {
+        let api = ctx.data_unchecked::<Repository>();
+        connection(after, before, first, last, |page| api.get_all_species(page)).await
     }
 
-    async fn droids<'ctx>(&self, ctx: &Context<'ctx>) -> Vec<Droid> {
-        ctx.data_unchecked::<StarWarsAPI>()
-            .get_humans()
+    async fn vehicle<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Option<Vehicle>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[VEHICLE]).extend()?;
+        Ok(api.get_vehicle(id).await.extend()?.map(Into::into))
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn vehicles<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Vehicle>> {
+        let api = ctx.data_unchecked::<Repository>();
+        connection(after, before, first, last, |page| api.get_vehicles(page)).await
+    }
+
+    /// Anything with a global id, `null` when there is nothing with that id
+    async fn node<'ctx>(&self, ctx: &Context<'ctx>, id: ID) -> Result<Option<Node>> {
+        let api = ctx.data_unchecked::<Repository>();
+        node(api, &id).await.extend()
+    }
+
+    /// `node` for a list of ids, in the same order
+    #[graphql(complexity = "1 + ids.len() * child_complexity")]
+    async fn nodes<'ctx>(&self, ctx: &Context<'ctx>, ids: Vec<ID>) -> Result<Vec<Option<Node>>> {
+        let api = ctx.data_unchecked::<Repository>();
+        future::try_join_all(ids.iter().map(|id| node(api, id)))
             .await
-            .into_iter()
-            .map(Into::into)
-            .collect()
+            .extend()
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn humans<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Human>> {
+        let api = ctx.data_unchecked::<Repository>();
+        connection(after, before, first, last, |page| api.get_humans(page)).await
+    }
+
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn droids<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Droid>> {
+        let api = ctx.data_unchecked::<Repository>();
+        connection(after, before, first, last, |page| api.get_droids(page)).await
+    }
+
+    /// Humans and droids that match `filter`, sorted on `orderBy`.
+    /// Characters that are equal on every order stay in the order they were added.
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(complexity = "page_cost(first, last, child_complexity)")]
+    async fn characters<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        #[graphql(default)] mut filter: CharacterFilter,
+        #[graphql(default)] order_by: Vec<CharacterOrder>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeyConnection<Character>> {
+        let api = ctx.data_unchecked::<Repository>();
+        filter.home_planet_id = filter
+            .home_planet_id
+            .map(|id| local_id(id, &[PLANET]))
+            .transpose()
+            .extend()?;
+        filter.starship_id = filter
+            .starship_id
+            .map(|id| local_id(id, &[STARSHIP]))
+            .transpose()
+            .extend()?;
+        filter.film_id = filter
+            .film_id
+            .map(|id| local_id(id, &[FILM]))
+            .transpose()
+            .extend()?;
+        filter.species_id = filter
+            .species_id
+            .map(|id| local_id(id, &[SPECIES]))
+            .transpose()
+            .extend()?;
+        filter.vehicle_id = filter
+            .vehicle_id
+            .map(|id| local_id(id, &[VEHICLE]))
+            .transpose()
+            .extend()?;
+        connection(after, before, first, last, |page| {
+            api.get_characters(filter, order_by, page)
+        })
+        .await
+    }
+
+    /// the ledger of credit transfers of a user, newest first, only for the user or an admin
+    #[graphql(
+        guard = "RoleGuard::new(Role::Admin).or(OwnerGuard::global(&user_id))",
+        complexity = "page_cost(first, None, child_complexity)"
+    )]
+    async fn credit_history<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        user_id: String,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<i64, CreditHistoryEntry>> {
+        let store = ctx.data_unchecked::<Credits>();
+        let user_id = local_id(user_id, &[HUMAN]).extend()?;
+        credits::history(store, user_id, first, after).await
+    }
+
+    /// Admin: everything in the api and every credit balance as a versioned dataset,
+    /// start a server with it in `DATASET_PATH` to get the exact same state.
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn snapshot<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Json<Dataset>> {
+        let api = ctx.data_unchecked::<Repository>();
+        let store = ctx.data_unchecked::<Credits>();
+        Ok(Json(dataset::snapshot(api, store).await.extend()?))
+    }
+
+    /// Admin: every api key, the revoked ones as well, newest first
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn api_keys<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<ApiKey>> {
+        api_keys(ctx).extend()?.list().await.extend()
     }
 }
 
@@ -63,14 +303,497 @@ pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
-    async fn transact<'a, 'ctx>(
+    /// Send `amount` credits from one account to another, with an optional memo for the ledger.
+    /// Only the owner of `from_user_id` can send its credits.
+    /// Fails with `UNKNOWN_ACCOUNT`, `INSUFFICIENT_FUNDS`, `INVALID_AMOUNT`, `SAME_ACCOUNT`
+    /// or `FORBIDDEN` in the error extensions, in which case no balance was touched.
+    #[graphql(guard = "OwnerGuard::global(&from_user_id)")]
+    async fn transact<'ctx>(
         &self,
         ctx: &Context<'ctx>,
         from_user_id: String,
         to_user_id: String,
         amount: usize,
-    ) -> Result<bool, String> {
-        let db = ctx.data_unchecked::<sqlx::PgPool>();
-        Ok(true)
+        memo: Option<String>,
+    ) -> Result<Transfer> {
+        let store = ctx.data_unchecked::<Credits>();
+        let from_user_id = local_id(from_user_id, &[HUMAN]).extend()?;
+        let to_user_id = local_id(to_user_id, &[HUMAN]).extend()?;
+        let transfer = store
+            .transfer(from_user_id, to_user_id, amount, memo)
+            .await
+            .extend()?;
+        ctx.data_unchecked::<CreditEvents>()
+            .publish(transfer.clone());
+        Ok(transfer)
+    }
+
+    /// add a new human, the id is assigned by the api
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_human<'ctx>(&self, ctx: &Context<'ctx>, input: NewHuman) -> Result<Human> {
+        let api = ctx.data_unchecked::<Repository>();
+        let input = NewHuman {
+            friend_ids: local_ids(input.friend_ids, CHARACTER).extend()?,
+            home_planet_id: input
+                .home_planet_id
+                .map(|id| local_id(id, &[PLANET]))
+                .transpose()
+                .extend()?,
+            starship_id: input
+                .starship_id
+                .map(|id| local_id(id, &[STARSHIP]))
+                .transpose()
+                .extend()?,
+            starship_ids: local_ids(input.starship_ids, &[STARSHIP]).extend()?,
+            species_id: input
+                .species_id
+                .map(|id| local_id(id, &[SPECIES]))
+                .transpose()
+                .extend()?,
+            ..input
+        };
+        api.create_human(input).await.map(Into::into).extend()
+    }
+
+    /// add a new droid, the id is assigned by the api
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_droid<'ctx>(&self, ctx: &Context<'ctx>, input: NewDroid) -> Result<Droid> {
+        let api = ctx.data_unchecked::<Repository>();
+        let input = NewDroid {
+            friend_ids: local_ids(input.friend_ids, CHARACTER).extend()?,
+            species_id: input
+                .species_id
+                .map(|id| local_id(id, &[SPECIES]))
+                .transpose()
+                .extend()?,
+            ..input
+        };
+        api.create_droid(input).await.map(Into::into).extend()
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin).or(OwnerGuard::global(&id))")]
+    async fn update_character<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: String,
+        input: UpdateCharacter,
+    ) -> Result<Character> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, CHARACTER).extend()?;
+        let input = UpdateCharacter {
+            friend_ids: input
+                .friend_ids
+                .map(|ids| local_ids(ids, CHARACTER))
+                .transpose()
+                .extend()?,
+            species_id: input
+                .species_id
+                .map(|id| local_id(id, &[SPECIES]))
+                .transpose()
+                .extend()?,
+            ..input
+        };
+        api.update_character(id, input)
+            .await
+            .map(Into::into)
+            .extend()
+    }
+
+    /// deletes the character and removes it from the friends of other characters,
+    /// returns the deleted character
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn delete_character<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Character> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, CHARACTER).extend()?;
+        api.delete_character(id).await.map(Into::into).extend()
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_starship<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: NewStarShip,
+    ) -> Result<StarShip> {
+        let api = ctx.data_unchecked::<Repository>();
+        api.create_starship(input).await.map(Into::into).extend()
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn update_starship<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: String,
+        input: UpdateStarShip,
+    ) -> Result<StarShip> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[STARSHIP]).extend()?;
+        api.update_starship(id, input)
+            .await
+            .map(Into::into)
+            .extend()
+    }
+
+    /// deletes the starship, humans that flew it are left without a starship
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn delete_starship<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<StarShip> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[STARSHIP]).extend()?;
+        api.delete_starship(id).await.map(Into::into).extend()
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_planet<'ctx>(&self, ctx: &Context<'ctx>, input: NewPlanet) -> Result<Planet> {
+        let api = ctx.data_unchecked::<Repository>();
+        api.create_planet(input).await.map(Into::into).extend()
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn update_planet<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: String,
+        input: UpdatePlanet,
+    ) -> Result<Planet> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[PLANET]).extend()?;
+        api.update_planet(id, input).await.map(Into::into).extend()
+    }
+
+    /// deletes the planet, humans that lived there are left without a home planet
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn delete_planet<'ctx>(&self, ctx: &Context<'ctx>, id: String) -> Result<Planet> {
+        let api = ctx.data_unchecked::<Repository>();
+        let id = local_id(id, &[PLANET]).extend()?;
+        api.delete_planet(id).await.map(Into::into).extend()
+    }
+
+    /// add a new film, like a prequel or a sequel, the id is assigned by the api
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_film<'ctx>(&self, ctx: &Context<'ctx>, input: NewFilm) -> Result<Film> {
+        let api = ctx.data_unchecked::<Repository>();
+        let input = NewFilm {
+            character_ids: local_ids(input.character_ids, CHARACTER).extend()?,
+            starship_ids: local_ids(input.starship_ids, &[STARSHIP]).extend()?,
+            planet_ids: local_ids(input.planet_ids, &[PLANET]).extend()?,
+            ..input
+        };
+        api.create_film(input).await.map(Into::into).extend()
+    }
+
+    /// let a human fly only this starship, leave `starshipId` out to take all its starships away
+    #[graphql(
+        deprecation = "a human can fly more than one starship, use `addPilot` and `removePilot`",
+        guard = "RoleGuard::new(Role::Admin).or(OwnerGuard::global(&character_id))"
+    )]
+    async fn assign_starship<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        character_id: String,
+        starship_id: Option<String>,
+    ) -> Result<Human> {
+        let api = ctx.data_unchecked::<Repository>();
+        let character_id = local_id(character_id, &[HUMAN]).extend()?;
+        let starship_id = starship_id
+            .map(|id| local_id(id, &[STARSHIP]))
+            .transpose()
+            .extend()?;
+        api.assign_starship(character_id, starship_id)
+            .await
+            .map(Into::into)
+            .extend()
+    }
+
+    /// let a human fly a starship, next to the starships it already flies
+    #[graphql(guard = "RoleGuard::new(Role::Admin).or(OwnerGuard::global(&character_id))")]
+    async fn add_pilot<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<StarShip> {
+        let api = ctx.data_unchecked::<Repository>();
+        let starship_id = local_id(starship_id, &[STARSHIP]).extend()?;
+        let character_id = local_id(character_id, &[HUMAN]).extend()?;
+        api.add_pilot(starship_id, character_id)
+            .await
+            .map(Into::into)
+            .extend()
+    }
+
+    /// the human no longer flies the starship
+    #[graphql(guard = "RoleGuard::new(Role::Admin).or(OwnerGuard::global(&character_id))")]
+    async fn remove_pilot<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        starship_id: String,
+        character_id: String,
+    ) -> Result<StarShip> {
+        let api = ctx.data_unchecked::<Repository>();
+        let starship_id = local_id(starship_id, &[STARSHIP]).extend()?;
+        let character_id = local_id(character_id, &[HUMAN]).extend()?;
+        api.remove_pilot(starship_id, character_id)
+            .await
+            .map(Into::into)
+            .extend()
+    }
+
+    /// set where a human lives, leave `planetId` out to clear it
+    #[graphql(guard = "RoleGuard::new(Role::Admin).or(OwnerGuard::global(&character_id))")]
+    async fn set_home_planet<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        character_id: String,
+        planet_id: Option<String>,
+    ) -> Result<Human> {
+        let api = ctx.data_unchecked::<Repository>();
+        let character_id = local_id(character_id, &[HUMAN]).extend()?;
+        let planet_id = planet_id
+            .map(|id| local_id(id, &[PLANET]))
+            .transpose()
+            .extend()?;
+        api.set_home_planet(character_id, planet_id)
+            .await
+            .map(Into::into)
+            .extend()
+    }
+
+    /// Admin: makes the character the hero of the film with `episodeId`, what `hero` returns
+    /// from now on. Leave `episodeId` out to set the hero of the entire saga.
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn set_episode_hero<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        #[graphql(desc = "deprecated, use `episodeId`")] episode: Option<Episode>,
+        episode_id: Option<usize>,
+        character_id: String,
+    ) -> Result<Character> {
+        let api = ctx.data_unchecked::<Repository>();
+        let character_id = local_id(character_id, CHARACTER).extend()?;
+        let episode_id = episode_id.or(episode.map(Episode::number));
+        api.set_hero(episode_id, character_id)
+            .await
+            .map(Into::into)
+            .extend()
+    }
+
+    /// Admin: a new api key for a partner, the key itself is only in this response
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn issue_api_key<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: NewApiKey,
+    ) -> Result<IssuedApiKey> {
+        let store = api_keys(ctx).extend()?;
+        let api = ctx.data_unchecked::<Repository>();
+        let owner_id = local_id(input.owner_id.clone(), CHARACTER).extend()?;
+        let exists = api.get_human(owner_id.clone()).await.extend()?.is_some()
+            || api.get_droid(owner_id.clone()).await.extend()?.is_some();
+        if !exists {
+            return Err(ApiError::NotFound {
+                kind: "character",
+                id: owner_id,
+            })
+            .extend();
+        }
+        store.issue(input, owner_id).await.extend()
+    }
+
+    /// Admin: the key stops working right away, returns the revoked key
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn revoke_api_key<'ctx>(&self, ctx: &Context<'ctx>, id: i64) -> Result<ApiKey> {
+        api_keys(ctx).extend()?.revoke(id).await.extend()
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// The new balance of `user_id` every time a transfer sends or receives credits.
+    /// A subscriber that can't keep up gets an `EVENTS_MISSED` error, then the next balances.
+    /// Only for the character itself and admins, like `credits`.
+    #[graphql(guard = "RoleGuard::new(Role::Admin).or(OwnerGuard::global(&user_id))")]
+    async fn credits_changed<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        user_id: String,
+    ) -> Result<impl Stream<Item = Result<BalanceChange>>> {
+        let user_id = local_id(user_id, &[HUMAN]).extend()?;
+        Ok(ctx
+            .data_unchecked::<CreditEvents>()
+            .subscribe()
+            .filter_map(move |event| {
+                let change = match event {
+                    Ok(transfer) => if transfer.from_user_id == user_id {
+                        Some(transfer.from_balance)
+                    } else if transfer.to_user_id == user_id {
+                        Some(transfer.to_balance)
+                    } else {
+                        None
+                    }
+                    .map(|credits| {
+                        Ok(BalanceChange {
+                            user_id: user_id.clone(),
+                            credits,
+                            transaction: transfer.transaction,
+                        })
+                    }),
+                    Err(e) => Some(Err(e)),
+                };
+                async move { change.map(|change| change.extend()) }
+            }))
+    }
+
+    /// Admin: every transfer that gets written to the ledger,
+    /// or `EVENTS_MISSED` when some went past
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn transaction_posted<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> impl Stream<Item = Result<LedgerEntry>> {
+        ctx.data_unchecked::<CreditEvents>()
+            .subscribe()
+            .map(|event| event.map(|transfer| transfer.transaction).extend())
+    }
+}
+
+/// the `Node` behind a global id, ids that aren't global ids find nothing
+async fn node(api: &Repository, id: &str) -> Result<Option<Node>, ApiError> {
+    let Some((kind, id)) = parse_global_id(id) else {
+        return Ok(None);
+    };
+    Ok(match kind.as_str() {
+        HUMAN => api.get_human(id).await?.map(|c| Node::Human(c.into())),
+        DROID => api.get_droid(id).await?.map(|c| Node::Droid(c.into())),
+        STARSHIP => api
+            .get_starship(id)
+            .await?
+            .map(|s| Node::StarShip(s.into())),
+        PLANET => api.get_planet(id).await?.map(|p| Node::Planet(p.into())),
+        FILM => api.get_film(id).await?.map(|f| Node::Film(f.into())),
+        SPECIES => api.get_species(id).await?.map(|s| Node::Species(s.into())),
+        VEHICLE => api.get_vehicle(id).await?.map(|v| Node::Vehicle(v.into())),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_graphql::{Request, Response};
+    use futures::StreamExt;
+    use tokio::time::timeout;
+
+    use crate::{
+        auth::{Caller, Role},
+        starwars::schema::{
+            tests::{schema, user},
+            StarWarsSchema,
+        },
+    };
+
+    async fn run(schema: &StarWarsSchema, caller: Caller, query: &str) -> Response {
+        schema.execute(Request::new(query).data(caller)).await
+    }
+
+    fn codes(response: &Response) -> Vec<String> {
+        response
+            .errors
+            .iter()
+            .map(|e| {
+                e.extensions
+                    .as_ref()
+                    .unwrap()
+                    .get("code")
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn admins_only() {
+        let schema = schema();
+        let set_hero = r#"mutation { setEpisodeHero(episodeId: 4, characterId: "1") { name } }"#;
+        for caller in [Caller::Anonymous, user("1", Role::Viewer)] {
+            let response = run(&schema, caller, set_hero).await;
+            assert_eq!(codes(&response), [r#""FORBIDDEN""#]);
+        }
+        let response = run(&schema, user("2", Role::Admin), set_hero).await;
+        assert_eq!(codes(&response), Vec::<String>::new());
+        let response = run(
+            &schema,
+            Caller::Anonymous,
+            "{ hero(episodeId: 4) { name } }",
+        )
+        .await;
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({ "hero": { "name": "Luke Skywalker" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_sees_and_spends_credits() {
+        let schema = schema();
+        let credits = r#"{ luke: human(id: "1") { credits } han: human(id: "3") { credits } }"#;
+        let response = run(&schema, user("1", Role::Viewer), credits).await;
+        assert_eq!(codes(&response), [r#""FORBIDDEN""#]);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({ "luke": { "credits": 100 }, "han": { "credits": null } })
+        );
+
+        let transact = |from: &str| {
+            format!(
+                r#"mutation {{ transact(fromUserId: "{from}", toUserId: "4", amount: 5) {{ fromBalance }} }}"#
+            )
+        };
+        let response = run(&schema, user("1", Role::Viewer), &transact("3")).await;
+        assert_eq!(codes(&response), [r#""FORBIDDEN""#]);
+        let response = run(&schema, user("1", Role::Viewer), &transact("1")).await;
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({ "transact": { "fromBalance": 95 } })
+        );
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_guarded() {
+        let schema = schema();
+        let subscribe =
+            |caller: Caller, query: &str| schema.execute_stream(Request::new(query).data(caller));
+        let posted = "subscription { transactionPosted { amount } }";
+        let mut refused = subscribe(user("1", Role::Viewer), posted);
+        assert_eq!(codes(&refused.next().await.unwrap()), [r#""FORBIDDEN""#]);
+
+        let changed = r#"subscription { creditsChanged(userId: "3") { credits } }"#;
+        let mut refused = subscribe(user("1", Role::Viewer), changed);
+        assert_eq!(codes(&refused.next().await.unwrap()), [r#""FORBIDDEN""#]);
+        let mut refused = subscribe(Caller::Anonymous, changed);
+        assert_eq!(codes(&refused.next().await.unwrap()), [r#""FORBIDDEN""#]);
+
+        // the subscriptions start listening when they are polled
+        let mut own = subscribe(user("3", Role::Viewer), changed);
+        let own = tokio::spawn(async move { own.next().await });
+        let mut all = subscribe(user("2", Role::Admin), posted);
+        let all = tokio::spawn(async move { all.next().await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let transfer =
+            r#"mutation { transact(fromUserId: "3", toUserId: "4", amount: 7) { amount } }"#;
+        run(&schema, user("3", Role::Viewer), transfer).await;
+        let next = |task| async { timeout(Duration::from_secs(5), task).await.unwrap() };
+        let change: Option<Response> = next(own).await.unwrap();
+        assert_eq!(
+            change.unwrap().data.into_json().unwrap(),
+            serde_json::json!({ "creditsChanged": { "credits": 93 } })
+        );
+        let posted: Option<Response> = next(all).await.unwrap();
+        assert_eq!(
+            posted.unwrap().data.into_json().unwrap(),
+            serde_json::json!({ "transactionPosted": { "amount": 7 } })
+        );
     }
 }
@@ -0,0 +1,111 @@
+use async_graphql::{dataloader::DataLoader, Schema};
+
+use super::{
+    api_keys::{ApiKeys, WebSocketQuota},
+    character_loader::CharacterLoader,
+    credits::{CreditEvents, Credits},
+    credits_loader::CreditsDataLoader,
+    film_loader::FilmLoader,
+    limits::QueryLimits,
+    planet_loader::PlanetLoader,
+    species_loader::SpeciesLoader,
+    starship_loader::StarshipLoader,
+    vehicle_loader::VehicleLoader,
+    MutationRoot, QueryRoot, Repository, SubscriptionRoot,
+};
+
+pub type StarWarsSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+/// The schema with everything the resolvers get out of the data,
+/// only the `Caller` is added per request
+pub fn build(
+    swapi: Repository,
+    credits: Credits,
+    events: CreditEvents,
+    api_keys: Option<ApiKeys>,
+    limits: QueryLimits,
+) -> StarWarsSchema {
+    let mut schema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        // checked before a query runs, the costs of the lists are on the fields
+        .limit_depth(limits.max_depth)
+        .limit_complexity(limits.max_complexity)
+        .extension(limits)
+        .extension(WebSocketQuota)
+        .data(swapi.clone())
+        .data(events) // transfers for the subscriptions
+        .data(credits.clone()) // the credits, in the database or in memory
+        .data(DataLoader::new(
+            CreditsDataLoader { store: credits },
+            tokio::task::spawn,
+        ))
+        // one batched lookup per level of every relation
+        .data(DataLoader::new(
+            CharacterLoader { api: swapi.clone() },
+            tokio::task::spawn,
+        ))
+        .data(DataLoader::new(
+            StarshipLoader { api: swapi.clone() },
+            tokio::task::spawn,
+        ))
+        .data(DataLoader::new(
+            PlanetLoader { api: swapi.clone() },
+            tokio::task::spawn,
+        ))
+        .data(DataLoader::new(
+            FilmLoader { api: swapi.clone() },
+            tokio::task::spawn,
+        ))
+        .data(DataLoader::new(
+            SpeciesLoader { api: swapi.clone() },
+            tokio::task::spawn,
+        ))
+        .data(DataLoader::new(
+            VehicleLoader { api: swapi },
+            tokio::task::spawn,
+        ))
+        //.data(DatabasePool) // kunt een database toevoegen
+        //.data(FacebookAPI) // kunt een api toevoegen
+        //.data(s3Bucket) // s3 buckets
+        // ...etc
+        ;
+    if let Some(api_keys) = api_keys {
+        schema = schema.data(api_keys);
+    }
+    schema.finish()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        auth::{Caller, Role},
+        starwars::{credits::MemoryCredits, StarWarsAPI},
+    };
+
+    /// the schema on the memory stores, with these limits
+    pub(crate) fn memory_schema(limits: QueryLimits) -> StarWarsSchema {
+        build(
+            Arc::new(StarWarsAPI::new()),
+            Arc::new(MemoryCredits::new()),
+            CreditEvents::new(),
+            None,
+            limits,
+        )
+    }
+
+    pub(crate) fn schema() -> StarWarsSchema {
+        memory_schema(QueryLimits {
+            max_depth: 15,
+            max_complexity: 5000,
+        })
+    }
+
+    pub(crate) fn user(user_id: &str, role: Role) -> Caller {
+        Caller::User {
+            user_id: user_id.into(),
+            role,
+        }
+    }
+}
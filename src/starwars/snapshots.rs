@@ -0,0 +1,58 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::time::{interval, MissedTickBehavior};
+
+use super::{
+    credits::Credits,
+    dataset::{self, Dataset, DatasetError},
+    Repository,
+};
+
+/// Keeps a snapshot of the memory store on disk, so a server without a database
+/// gets its characters, starships, planets, ... credit balances and ledger back after a restart.
+///
+/// The file is a dataset like `export` writes, `DATASET_PATH` can load it as well.
+#[derive(Clone)]
+pub struct Snapshots {
+    pub path: PathBuf,
+    pub api: Repository,
+    pub credits: Credits,
+}
+
+impl Snapshots {
+    /// The last snapshot, `None` when there isn't one yet.
+    /// A snapshot that can't be loaded is an error: starting without it would overwrite it.
+    pub fn restore(path: &Path) -> Result<Option<Dataset>, DatasetError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Dataset::load(path).and_then(Dataset::validate).map(Some)
+    }
+
+    /// writes everything in the store to the snapshot
+    pub async fn write(&self) -> Result<(), String> {
+        let data = dataset::snapshot(&self.api, &self.credits)
+            .await
+            .map_err(|e| e.to_string())?;
+        data.save(&self.path).map_err(|e| e.to_string())
+    }
+
+    /// a snapshot every `period` in the background, a failed one is logged and retried next time
+    pub fn every(self, period: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::task::spawn(async move {
+            let mut ticks = interval(period);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // the first tick is right away, there is nothing new to write yet
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                if let Err(e) = self.write().await {
+                    tracing::error!("can't write the snapshot {}: {e}", self.path.display());
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,18 @@
+use async_graphql::{dataloader::*, FieldError, ResultExt};
+use std::collections::HashMap;
+
+use super::{data::APISpecies, Repository};
+
+pub struct SpeciesLoader {
+    pub api: Repository,
+}
+
+/// Loader for the species of characters and films
+impl Loader<usize> for SpeciesLoader {
+    type Value = APISpecies;
+    type Error = FieldError;
+
+    async fn load(&self, keys: &[usize]) -> Result<HashMap<usize, Self::Value>, Self::Error> {
+        self.api.get_species_by_idx(keys).await.extend()
+    }
+}
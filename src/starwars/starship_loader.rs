@@ -0,0 +1,18 @@
+use async_graphql::{dataloader::*, FieldError, ResultExt};
+use std::collections::HashMap;
+
+use super::{data::APIStarShip, Repository};
+
+pub struct StarshipLoader {
+    pub api: Repository,
+}
+
+/// Loader for the starships humans fly
+impl Loader<usize> for StarshipLoader {
+    type Value = APIStarShip;
+    type Error = FieldError;
+
+    async fn load(&self, keys: &[usize]) -> Result<HashMap<usize, Self::Value>, Self::Error> {
+        self.api.get_starships_by_idx(keys).await.extend()
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+use chrono::NaiveDate;
+use serde_json::{Map, Value};
+
+use super::dataset::{
+    CharacterData, CharacterKind, Dataset, DatasetError, FilmData, PlanetData, SpeciesData,
+    StarShipData, VehicleData,
+};
+
+/// Fields that are the other side of a link the import already gets from the owning record,
+/// e.g. `residents` of a planet is the `homeworld` of the people. They aren't reported.
+const DERIVED: &[(&str, &str)] = &[
+    ("planets", "residents"),
+    ("planets", "films"),
+    ("starships", "pilots"),
+    ("starships", "films"),
+    ("vehicles", "pilots"),
+    ("vehicles", "films"),
+    ("species", "people"),
+    ("species", "films"),
+    ("films", "characters"),
+];
+
+/// What didn't make it from the dump into the dataset.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// `file.field` that isn't in the model, with the number of records that had it
+    pub dropped: BTreeMap<String, usize>,
+
+    /// values and links that couldn't be mapped, one line per value
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dropped.is_empty() && self.problems.is_empty() {
+            return write!(f, "everything in the dump was imported");
+        }
+        if !self.dropped.is_empty() {
+            write!(f, "fields that aren't in the model:")?;
+            for (field, records) in &self.dropped {
+                write!(f, "\n  - {field} ({records} records)")?;
+            }
+        }
+        if !self.problems.is_empty() {
+            if !self.dropped.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "values that couldn't be mapped:")?;
+            for problem in &self.problems {
+                write!(f, "\n  - {problem}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads an archived swapi.dev dump, the `people.json`, `planets.json`, `starships.json`,
+/// `vehicles.json`, `species.json` and `films.json` in `dir`. A missing file is skipped.
+///
+/// The links like `https://swapi.dev/api/people/1/` become the ids of the dataset, `1` here.
+pub fn import(dir: &Path) -> Result<(Dataset, ImportReport), DatasetError> {
+    let mut report = ImportReport::default();
+    let people = read(dir, "people", &mut report)?;
+    let planets = read(dir, "planets", &mut report)?;
+    let starships = read(dir, "starships", &mut report)?;
+    let vehicles = read(dir, "vehicles", &mut report)?;
+    let species = read(dir, "species", &mut report)?;
+    let films = read(dir, "films", &mut report)?;
+
+    let known = Known {
+        planets: planets.iter().map(|r| r.id.clone()).collect(),
+        starships: starships.iter().map(|r| r.id.clone()).collect(),
+        vehicles: vehicles.iter().map(|r| r.id.clone()).collect(),
+        species: species.iter().map(|r| r.id.clone()).collect(),
+        films: films.iter().map(|r| r.id.clone()).collect(),
+    };
+    // swapi has no droids, only people of the droid species
+    let droid_species: HashSet<String> = species
+        .iter()
+        .filter(|r| {
+            r.fields
+                .get("name")
+                .and_then(Value::as_str)
+                .is_some_and(|name| name.eq_ignore_ascii_case("droid"))
+        })
+        .map(|r| r.id.clone())
+        .collect();
+
+    let mut dataset = Dataset::default();
+    for mut r in planets {
+        dataset.planets.push(PlanetData {
+            name: r.string("name", &mut report),
+            climate: r.string("climate", &mut report),
+            diameter: r.count("diameter", &mut report),
+            gravity: r.string("gravity", &mut report),
+            population: r.count("population", &mut report),
+            rotation_period: r.count("rotation_period", &mut report),
+            orbital_period: r.count("orbital_period", &mut report),
+            id: r.finish(&mut report),
+        });
+    }
+    for mut r in starships {
+        dataset.starships.push(StarShipData {
+            name: r.string("name", &mut report),
+            length: r.number("length", &mut report).unwrap_or_else(|| {
+                r.problem("length", "is unknown, using 0", &mut report);
+                0.
+            }),
+            id: r.finish(&mut report),
+        });
+    }
+    for mut r in vehicles {
+        dataset.vehicles.push(VehicleData {
+            name: r.string("name", &mut report),
+            model: r.string("model", &mut report),
+            manufacturer: r.string("manufacturer", &mut report),
+            cost_in_credits: r.number("cost_in_credits", &mut report).map(|n| n as usize),
+            crew: r.count("crew", &mut report),
+            passengers: r.count("passengers", &mut report),
+            id: r.finish(&mut report),
+        });
+    }
+    for mut r in species {
+        dataset.species.push(SpeciesData {
+            name: r.string("name", &mut report),
+            classification: r.string("classification", &mut report),
+            language: r.string("language", &mut report),
+            average_lifespan: r
+                .number("average_lifespan", &mut report)
+                .map(|n| n as usize),
+            homeworld: r.link("homeworld", "planets", &known.planets, &mut report),
+            id: r.finish(&mut report),
+        });
+    }
+    for mut r in films {
+        let release_date = r.string("release_date", &mut report);
+        dataset.films.push(FilmData {
+            episode_id: r.count("episode_id", &mut report),
+            title: r.string("title", &mut report),
+            release_date: NaiveDate::parse_from_str(&release_date, "%Y-%m-%d").unwrap_or_else(
+                |_| {
+                    r.problem(
+                        "release_date",
+                        "isn't a date, using 1977-05-25",
+                        &mut report,
+                    );
+                    NaiveDate::from_ymd_opt(1977, 5, 25).unwrap()
+                },
+            ),
+            director: r.string("director", &mut report),
+            producer: r.string("producer", &mut report),
+            opening_crawl: r.string("opening_crawl", &mut report),
+            starships: r.links("starships", &known.starships, &mut report),
+            planets: r.links("planets", &known.planets, &mut report),
+            species: r.links("species", &known.species, &mut report),
+            vehicles: r.links("vehicles", &known.vehicles, &mut report),
+            id: r.finish(&mut report),
+        });
+    }
+    for mut r in people {
+        let name = r.string("name", &mut report);
+        let mass = r.count("mass", &mut report);
+        let films = r.links("films", &known.films, &mut report);
+        let mut species = r.links("species", &known.species, &mut report);
+        if species.len() > 1 {
+            r.problem(
+                "species",
+                "has more than one species, keeping the first",
+                &mut report,
+            );
+        }
+        species.truncate(1);
+        let species = species.pop();
+        let home_planet = r.link("homeworld", "planets", &known.planets, &mut report);
+        let starships = r.links("starships", &known.starships, &mut report);
+        let vehicles = r.links("vehicles", &known.vehicles, &mut report);
+
+        let kind = match &species {
+            Some(species) if droid_species.contains(species) => CharacterKind::Droid,
+            _ => CharacterKind::Human,
+        };
+        let mut character = CharacterData {
+            id: String::new(),
+            kind,
+            name,
+            mass,
+            friends: Vec::new(),
+            films,
+            species,
+            home_planet,
+            starships,
+            vehicles,
+            primary_function: None,
+        };
+        // only humans have these
+        if kind == CharacterKind::Droid {
+            if character.home_planet.take().is_some() {
+                r.problem("homeworld", "belongs to a droid, dropped it", &mut report);
+            }
+            if !std::mem::take(&mut character.starships).is_empty() {
+                r.problem("starships", "belong to a droid, dropped them", &mut report);
+            }
+            if !std::mem::take(&mut character.vehicles).is_empty() {
+                r.problem("vehicles", "belong to a droid, dropped them", &mut report);
+            }
+        }
+        character.id = r.finish(&mut report);
+        dataset.characters.push(character);
+    }
+
+    if dataset.heroes.is_empty() && !dataset.characters.is_empty() {
+        report
+            .problems
+            .push("the dump has no heroes, pick one with setEpisodeHero".into());
+    }
+    Ok((dataset, report))
+}
+
+/// the ids of every file, to resolve the links. swapi has no friends, so no people
+struct Known {
+    planets: HashSet<String>,
+    starships: HashSet<String>,
+    vehicles: HashSet<String>,
+    species: HashSet<String>,
+    films: HashSet<String>,
+}
+
+/// one record of a dump file, the fields are taken out of it while mapping
+/// and whatever is left over didn't make it into the dataset
+struct Record {
+    file: &'static str,
+    id: String,
+    fields: Map<String, Value>,
+}
+
+/// The records of `<file>.json`, either a plain list or a page of the api with `results`
+fn read(
+    dir: &Path,
+    file: &'static str,
+    report: &mut ImportReport,
+) -> Result<Vec<Record>, DatasetError> {
+    let path = dir.join(format!("{file}.json"));
+    if !path.exists() {
+        report
+            .problems
+            .push(format!("there is no {}, skipped it", path.display()));
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(&path).map_err(DatasetError::Io)?;
+    let parse = |e: String| DatasetError::Parse(format!("{}: {e}", path.display()));
+    let records = match serde_json::from_str(&contents).map_err(|e| parse(e.to_string()))? {
+        Value::Array(records) => records,
+        Value::Object(mut page) => match page.remove("results") {
+            Some(Value::Array(records)) => records,
+            _ => return Err(parse("expected a list of records or `results`".into())),
+        },
+        _ => return Err(parse("expected a list of records or `results`".into())),
+    };
+
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    for (position, record) in records.into_iter().enumerate() {
+        let Value::Object(mut fields) = record else {
+            return Err(parse(format!("record {position} isn't an object")));
+        };
+        let url = fields.remove("url");
+        let Some((_, id)) = url.as_ref().and_then(Value::as_str).and_then(split_url) else {
+            report.problems.push(format!(
+                "{file}.json record {position} has no swapi `url`, skipped it"
+            ));
+            continue;
+        };
+        if !seen.insert(id.clone()) {
+            report
+                .problems
+                .push(format!("{file}/{id} is in {file}.json twice, skipped it"));
+            continue;
+        }
+        result.push(Record { file, id, fields });
+    }
+    Ok(result)
+}
+
+/// `https://swapi.dev/api/people/1/` is `("people", "1")`
+fn split_url(url: &str) -> Option<(String, String)> {
+    let mut parts = url.trim_end_matches('/').rsplit('/');
+    let id = parts.next()?;
+    let kind = parts.next()?;
+    (!id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| (kind.to_string(), id.to_string()))
+}
+
+impl Record {
+    fn problem(&self, field: &str, problem: &str, report: &mut ImportReport) {
+        report
+            .problems
+            .push(format!("{}/{} `{field}` {problem}", self.file, self.id));
+    }
+
+    fn string(&mut self, field: &str, report: &mut ImportReport) -> String {
+        match self.fields.remove(field) {
+            Some(Value::String(s)) => s,
+            Some(Value::Number(n)) => n.to_string(),
+            _ => {
+                self.problem(field, "is missing, left it empty", report);
+                String::new()
+            }
+        }
+    }
+
+    /// swapi writes numbers as `"1,358"`, `None` when it is `"unknown"` or `"n/a"`
+    fn number(&mut self, field: &str, report: &mut ImportReport) -> Option<f64> {
+        let value = self.fields.remove(field)?;
+        let text = match &value {
+            Value::Number(n) => return n.as_f64(),
+            Value::String(s) => s.replace(',', ""),
+            _ => String::new(),
+        };
+        match text.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() && n >= 0. => Some(n),
+            _ if ["unknown", "n/a", "none", "indefinite"].contains(&text.trim()) => None,
+            _ => {
+                self.problem(field, &format!("{value} isn't a number"), report);
+                None
+            }
+        }
+    }
+
+    /// a number the model needs, 0 when it is unknown
+    fn count(&mut self, field: &str, report: &mut ImportReport) -> usize {
+        self.number(field, report).map_or(0, |n| n.round() as usize)
+    }
+
+    /// the id of one link to `kind`
+    fn link(
+        &mut self,
+        field: &str,
+        kind: &str,
+        known: &HashSet<String>,
+        report: &mut ImportReport,
+    ) -> Option<String> {
+        match self.fields.remove(field)? {
+            Value::String(url) => self.resolve(field, kind, &url, known, report),
+            Value::Null => None,
+            value => {
+                self.problem(field, &format!("{value} isn't a link"), report);
+                None
+            }
+        }
+    }
+
+    /// the ids of a list of links, `field` is also the file they point to
+    fn links(
+        &mut self,
+        field: &str,
+        known: &HashSet<String>,
+        report: &mut ImportReport,
+    ) -> Vec<String> {
+        let urls = match self.fields.remove(field) {
+            Some(Value::Array(urls)) => urls,
+            None | Some(Value::Null) => return Vec::new(),
+            Some(value) => {
+                self.problem(field, &format!("{value} isn't a list of links"), report);
+                return Vec::new();
+            }
+        };
+        urls.iter()
+            .filter_map(|url| match url.as_str() {
+                Some(url) => self.resolve(field, field, url, known, report),
+                None => {
+                    self.problem(field, &format!("{url} isn't a link"), report);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn resolve(
+        &self,
+        field: &str,
+        kind: &str,
+        url: &str,
+        known: &HashSet<String>,
+        report: &mut ImportReport,
+    ) -> Option<String> {
+        match split_url(url) {
+            Some((k, id)) if k == kind && known.contains(&id) => Some(id),
+            Some((k, _)) if k == kind => {
+                self.problem(
+                    field,
+                    &format!("links to {url}, which isn't in the dump"),
+                    report,
+                );
+                None
+            }
+            _ => {
+                self.problem(field, &format!("{url} isn't a link to {kind}"), report);
+                None
+            }
+        }
+    }
+
+    /// the id of the record, everything that wasn't taken out of it is reported as dropped
+    fn finish(self, report: &mut ImportReport) -> String {
+        for field in self.fields.keys() {
+            if DERIVED.contains(&(self.file, field.as_str())) {
+                continue;
+            }
+            *report
+                .dropped
+                .entry(format!("{}.{field}", self.file))
+                .or_default() += 1;
+        }
+        self.id
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Index, IndexMut},
+};
+
+/// Storage of the in memory api, like a slab but a key is never handed out twice.
+/// The keys are the cursors of the lists, so after a delete an old cursor
+/// must not point at the next item that gets inserted.
+pub struct Table<T> {
+    items: BTreeMap<usize, T>,
+    next_key: usize,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Table<T> {
+    pub fn new() -> Self {
+        Self {
+            items: BTreeMap::new(),
+            next_key: 0,
+        }
+    }
+
+    /// adds `item` under a key that is higher than every key before, returns that key
+    pub fn insert(&mut self, item: T) -> usize {
+        let key = self.next_key;
+        self.next_key += 1;
+        self.items.insert(key, item);
+        key
+    }
+
+    /// Panics when there is no item with `key`, like `Slab::remove`
+    pub fn remove(&mut self, key: usize) -> T {
+        self.items
+            .remove(&key)
+            .unwrap_or_else(|| panic!("no item with key {key}"))
+    }
+
+    pub fn get(&self, key: usize) -> Option<&T> {
+        self.items.get(&key)
+    }
+
+    /// every item with its key, in key order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.items.iter().map(|(&key, item)| (key, item))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+        self.items.iter_mut().map(|(&key, item)| (key, item))
+    }
+}
+
+impl<T> Index<usize> for Table<T> {
+    type Output = T;
+
+    fn index(&self, key: usize) -> &T {
+        &self.items[&key]
+    }
+}
+
+impl<T> IndexMut<usize> for Table<T> {
+    fn index_mut(&mut self, key: usize) -> &mut T {
+        self.items
+            .get_mut(&key)
+            .unwrap_or_else(|| panic!("no item with key {key}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_never_reused() {
+        let mut table = Table::new();
+        let a = table.insert("a");
+        let b = table.insert("b");
+        assert_eq!(table.remove(b), "b");
+        let c = table.insert("c");
+        assert!(c > b);
+        assert_eq!(table.get(b), None);
+        assert_eq!(table.iter().collect::<Vec<_>>(), [(a, &"a"), (c, &"c")]);
+    }
+
+    #[test]
+    fn iterates_in_key_order() {
+        let mut table = Table::new();
+        let keys: Vec<usize> = (0..5).map(|i| table.insert(i)).collect();
+        table.remove(keys[0]);
+        table.remove(keys[3]);
+        table[keys[1]] = 10;
+        assert_eq!(
+            table.iter().map(|(key, &i)| (key, i)).collect::<Vec<_>>(),
+            [(keys[1], 10), (keys[2], 2), (keys[4], 4)]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! A fresh database for every test of the postgres code.
+//! These tests need `DATABASE_URL`, a user that may create databases, and pass without it.
+//! Every database is called `swapi_test_<pid>_<n>` and stays after the test, to look into
+//! it when a test fails; remove them with `DROP DATABASE` when there are too many.
+
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    Connection, Executor, PgConnection, PgPool,
+};
+
+static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+/// a new database with every migration, `None` when `DATABASE_URL` isn't set
+pub async fn pool() -> Option<PgPool> {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("skipped, this test needs DATABASE_URL");
+        return None;
+    };
+    let name = format!(
+        "swapi_test_{}_{}",
+        std::process::id(),
+        DATABASES.fetch_add(1, Ordering::Relaxed)
+    );
+
+    let mut admin = PgConnection::connect(&url).await.unwrap();
+    admin
+        .execute(format!("DROP DATABASE IF EXISTS {name}").as_str())
+        .await
+        .unwrap();
+    admin
+        .execute(format!("CREATE DATABASE {name}").as_str())
+        .await
+        .unwrap();
+    admin.close().await.unwrap();
+
+    let options = PgConnectOptions::from_str(&url).unwrap().database(&name);
+    let pool = PgPoolOptions::new()
+        .max_connections(4)
+        .connect_with(options)
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    Some(pool)
+}
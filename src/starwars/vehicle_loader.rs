@@ -0,0 +1,18 @@
+use async_graphql::{dataloader::*, FieldError, ResultExt};
+use std::collections::HashMap;
+
+use super::{data::APIVehicle, Repository};
+
+pub struct VehicleLoader {
+    pub api: Repository,
+}
+
+/// Loader for the vehicles humans drive and films show
+impl Loader<usize> for VehicleLoader {
+    type Value = APIVehicle;
+    type Error = FieldError;
+
+    async fn load(&self, keys: &[usize]) -> Result<HashMap<usize, Self::Value>, Self::Error> {
+        self.api.get_vehicles_by_idx(keys).await.extend()
+    }
+}